@@ -0,0 +1,108 @@
+//! Compares the emulation prevention conversion against the original byte by byte loops
+//! on the NAL units of the streams in `v/input`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use rust_utils::{annexb, emulation};
+
+const STREAMS: &[&str] = &[
+    "small_bunny_1080p_30fps.h264",
+    "small_bunny_1080p_30fps_h264_keyframe_each_one_second.h264",
+    "small_bunny_1080p_30fps_h264_keyframe_each_second_CAVLC.h264",
+];
+
+fn legacy_decode(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+    let mut i = 0;
+    let i_max = data.len();
+    while i < i_max {
+        if (i + 2 < i_max) && (data[i] == 0) && (data[i + 1] == 0) && (data[i + 2] == 3) {
+            res.push(0);
+            res.push(0);
+            i += 3;
+        } else {
+            res.push(data[i]);
+            i += 1;
+        }
+    }
+    res
+}
+
+fn legacy_encode(data: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(data.len());
+    let mut i = 0;
+    let i_max = data.len();
+    while i < i_max {
+        if (i + 2 < i_max) && (data[i] == 0) && (data[i + 1] == 0) && (data[i + 2] < 4) {
+            res.push(0);
+            res.push(0);
+            res.push(3);
+            i += 2;
+        } else {
+            res.push(data[i]);
+            i += 1;
+        }
+    }
+    res
+}
+
+fn bench_emulation(c: &mut Criterion) {
+    let inputs: Vec<(&str, Vec<u8>)> = STREAMS
+        .iter()
+        .map(|name| {
+            let path = format!("{}/v/input/{}", env!("CARGO_MANIFEST_DIR"), name);
+            (*name, std::fs::read(path).unwrap())
+        })
+        .collect();
+
+    let mut decode_group = c.benchmark_group("decode");
+    for (name, data) in &inputs {
+        let nals: Vec<&[u8]> = annexb::nal_units(data)
+            .map(|nal| &data[nal.start..nal.end])
+            .collect();
+        decode_group.throughput(Throughput::Bytes(data.len() as u64));
+        decode_group.bench_function(BenchmarkId::new("legacy", name), |b| {
+            b.iter(|| {
+                nals.iter()
+                    .map(|nal| legacy_decode(nal).len())
+                    .sum::<usize>()
+            })
+        });
+        decode_group.bench_function(BenchmarkId::new("memchr", name), |b| {
+            b.iter(|| {
+                nals.iter()
+                    .map(|nal| emulation::decode(nal).len())
+                    .sum::<usize>()
+            })
+        });
+    }
+    decode_group.finish();
+
+    let mut encode_group = c.benchmark_group("encode");
+    for (name, data) in &inputs {
+        let rbsps: Vec<Vec<u8>> = annexb::nal_units(data)
+            .map(|nal| emulation::decode(&data[nal.start..nal.end]))
+            .collect();
+        encode_group.throughput(Throughput::Bytes(data.len() as u64));
+        encode_group.bench_function(BenchmarkId::new("legacy", name), |b| {
+            b.iter(|| {
+                rbsps
+                    .iter()
+                    .map(|rbsp| legacy_encode(rbsp).len())
+                    .sum::<usize>()
+            })
+        });
+        encode_group.bench_function(BenchmarkId::new("memchr", name), |b| {
+            b.iter(|| {
+                rbsps
+                    .iter()
+                    .map(|rbsp| emulation::encode(rbsp).len())
+                    .sum::<usize>()
+            })
+        });
+    }
+    encode_group.finish();
+}
+
+criterion_group!(benches, bench_emulation);
+criterion_main!(benches);
@@ -1,4 +1,3 @@
-from h26x_extractor import h26x_parser
 from crypt import utils
 
 
@@ -11,26 +10,21 @@ def encrypt(in_file, out_file, encrypt_func):
 
 
 def process_file(in_file, out_file, func):
-    ex = h26x_parser.H26xParser(in_file, verbose=True)
-
-    # nals.append((pos, is4bytes, fb, nri, type))
-    # (start, end, is4bytes, fb, nri, type)
-
     res = bytearray()
     data = utils.read_file(in_file)
 
-    pre_start, pre_end = -1, -1
+    pre_end = 0
 
-    for i, (start, end, is4bytes, fb, nri, nalu_type) in enumerate(ex.nalu_pos):
-        if start != pre_end + 1:
-            res.extend(data[pre_end + 1: start])
+    for nalu in utils.scan_nal_units(data):
+        res.extend(data[pre_end: nalu.start])
 
-        processed_nalu = func(bytes(data[start: end + 1]), fb, nri, nalu_type)
+        processed_nalu = func(
+            bytes(data[nalu.start: nalu.end]), nalu.forbidden_zero_bit, nalu.nal_ref_idc, nalu.nal_unit_type
+        )
         res.extend(processed_nalu)
 
-        pre_start, pre_end = start, end
+        pre_end = nalu.end
 
-    if pre_end != len(data) - 1:
-        res.extend(data[pre_end + 1:])
+    res.extend(data[pre_end:])
 
     utils.write_file(out_file, res)
\ No newline at end of file
@@ -3,15 +3,109 @@ import hashlib
 import rust_utils
 
 
-def nalu_decode(nalu_data: bytes) -> bytes:
+NaluValidationError = rust_utils.NaluValidationError
+
+# bit reader over RBSP data, reads like bitstring's BitStream: s.read("ue"), s.read("uint:8"), s.pos
+BitStream = rust_utils.BitStream
+ReadError = rust_utils.ReadError
+# bit writer for re-serializing syntax structures: w.ue(3), w.rbsp_trailing_bits(), w.nal_payload()
+BitWriter = rust_utils.BitWriter
+
+
+def nalu_decode(nalu_data: bytes, strict=False) -> bytes:
     """
     Get the Rbsp from the NAL unit.
+    With strict, raise NaluValidationError if the NAL unit contains forbidden byte sequences.
+    """
+    return rust_utils.nalu_decode(nalu_data, strict)
+
+
+def nalu_validate(nalu_data):
+    """
+    List the forbidden byte sequences of the NAL unit as (offset, category) tuples.
+    """
+    return rust_utils.nalu_validate(nalu_data)
+
+
+def nalu_encode(data: bytes, strict=False) -> bytes:
+    """
+    Escape the Rbsp.
+    With strict, raise NaluValidationError if it ends with an unpaired 0x00, which the NAL unit
+    would lose to the next start code.
+    """
+    return rust_utils.nalu_encode(data, strict)
+
+
+def nalu_encode_with_report(data):
     """
-    return rust_utils.nalu_decode(nalu_data)
+    Escape the Rbsp, and list the applied rules as (offset, rule) tuples, rule being one of
+    "escape", "cabac_zero_word" or "lone_trailing_zero".
+    """
+    return rust_utils.nalu_encode_with_report(data)
+
+
+def nalu_decode_with_map(nalu_data):
+    """
+    Get the Rbsp from the NAL unit, and the positions of the removed emulation prevention bytes.
+    The returned map translates offsets between the NAL unit and the Rbsp.
+    """
+    return rust_utils.nalu_decode_with_map(nalu_data)
+
+
+def nalu_decode_into(data, out) -> int:
+    """
+    Get the Rbsp from the NAL unit, written into the writable buffer out.
+    Returns the number of bytes written.
+    """
+    return rust_utils.nalu_decode_into(data, out)
 
 
-def nalu_encode(data: bytes) -> bytes:
-    return rust_utils.nalu_encode(data)
+def nalu_decode_in_place(data) -> int:
+    """
+    Replace the NAL unit in the writable buffer data by its Rbsp.
+    Returns the length of the Rbsp.
+    """
+    return rust_utils.nalu_decode_in_place(data)
+
+
+def nalu_encode_into(data, out) -> int:
+    """
+    Escape the Rbsp into the writable buffer out.
+    Returns the number of bytes written.
+    """
+    return rust_utils.nalu_encode_into(data, out)
+
+
+def scan_nal_units(data, threads=1):
+    """
+    Find the NAL units of an Annex B byte stream.
+    Each item has start/end offsets (start code excluded) and the NAL header fields.
+    With threads other than 1, the stream is scanned on that many threads (0: one per CPU).
+    """
+    return rust_utils.scan_nal_units(data, threads)
+
+
+def parse_byte_stream(data):
+    """
+    Split an Annex B byte stream into byte_stream_nal_units, with the leading_zero_8bits,
+    zero_byte and trailing_zero_8bits of each accounted separately.
+    unit.write(nal) re-frames a (processed) NAL unit with the original padding.
+    """
+    return rust_utils.parse_byte_stream(data)
+
+
+def avcc_to_annexb(data, length_size=4):
+    """
+    Convert length-prefixed (AVCC) NAL units, e.g. an MP4 sample, to an Annex B byte stream.
+    """
+    return rust_utils.avcc_to_annexb(data, length_size)
+
+
+def annexb_to_avcc(data, length_size=4):
+    """
+    Convert an Annex B byte stream to length-prefixed (AVCC) NAL units.
+    """
+    return rust_utils.annexb_to_avcc(data, length_size)
 
 
 def read_file(filename):
@@ -19,6 +113,141 @@ def read_file(filename):
         return bytearray(f.read())
 
 
+def parse_nal_header(nalu_data, hevc=False):
+    """
+    Parse the NAL unit header, including the SVC/MVC extension of NAL unit types 14, 20 and 21.
+    With hevc, parse the two-byte H.265 header instead.
+    """
+    if hevc:
+        return rust_utils.HevcNalHeader.parse(nalu_data)
+    return rust_utils.NalHeader.parse(nalu_data)
+
+
+def rbsp_stop_bit(rbsp_data):
+    """
+    Bit position of the rbsp_stop_one_bit, skipping alignment bits and cabac_zero_words, or None.
+    Compute it once per NAL unit; more_rbsp_data() is then `pos < stop_bit`.
+    """
+    return rust_utils.rbsp_stop_bit(rbsp_data)
+
+
+def trace_nal_units(data):
+    """
+    Trace every syntax element of the SPS, PPS, SEI, AUD and slice headers, like the JM decoder's
+    trace_dec.txt: name, bit offset in the NAL unit, length, descriptor, code and value.
+    """
+    return rust_utils.trace_nal_units(data)
+
+
+def dump_trace(data, format="text"):
+    """
+    The trace of trace_nal_units as "text" or "json".
+    """
+    return rust_utils.dump_trace(data, format)
+
+
+def parse_sps(nalu_data):
+    """
+    Parse an SPS NAL unit (with its header and emulation prevention bytes) into a dict of its
+    syntax elements plus derived values such as ChromaArrayType, MaxFrameNum and the cropped
+    width and height.
+    """
+    return rust_utils.parse_sps(nalu_data)
+
+
+def parse_pps(nalu_data, sps_nalu_data=None):
+    """
+    Parse a PPS NAL unit into a dict of its syntax elements. The SPS NAL unit it refers to is
+    required when the PPS carries transform_8x8_mode_flag and a scaling matrix.
+    """
+    return rust_utils.parse_pps(nalu_data, sps_nalu_data)
+
+
+def parse_sps_extension(nalu_data):
+    """
+    Parse an SPS extension NAL unit (type 13): aux_format_idc and the alpha settings.
+    """
+    return rust_utils.parse_sps_extension(nalu_data)
+
+
+def parse_subset_sps(nalu_data):
+    """
+    Parse a subset SPS NAL unit (type 15): the SPS fields plus its SVC or MVC extension,
+    including the MVC view ids and inter-view dependencies.
+    """
+    return rust_utils.parse_subset_sps(nalu_data)
+
+
+def track_parameter_sets(data):
+    """
+    Store the SPS and PPS of an Annex B stream by id and track their activation by the slices.
+    Returns the ParameterSets and the (offset, message) of the NAL units that failed to parse.
+    """
+    parameter_sets = rust_utils.ParameterSets()
+    errors = parameter_sets.process_stream(data)
+    return parameter_sets, errors
+
+
+def check_conformance(data):
+    """
+    Check an Annex B stream against the limits of its level and the constraints of its profile.
+    Returns the (offset, rule, message) of every violation.
+    """
+    return rust_utils.check_conformance(data)
+
+
+def codec_string(data, hevc=False):
+    """
+    Get the RFC 6381 codecs parameter (avc1.PPCCLL, hvc1.x.y.Lz.B0) of an Annex B stream.
+    """
+    return rust_utils.codec_string(data, hevc)
+
+
+def decoder_configuration_record(data, hevc=False):
+    """
+    Build the avcC or hvcC decoder configuration record from the parameter sets of an Annex B
+    stream.
+    """
+    if hevc:
+        return rust_utils.hevc_decoder_configuration_record(data)
+    return rust_utils.avc_decoder_configuration_record(data)
+
+
+def parse_decoder_configuration_record(record, hevc=False):
+    """
+    Parse an avcC or hvcC decoder configuration record into a dict with its parameter set NAL
+    units and codec string.
+    """
+    if hevc:
+        return rust_utils.parse_hevc_decoder_configuration_record(record)
+    return rust_utils.parse_avc_decoder_configuration_record(record)
+
+
+def rewrite_sps(data, seq_parameter_set_id, **fields):
+    """
+    Set SPS and VUI fields, e.g. level_idc=41 or colour_primaries=1, on every SPS with the
+    given id in an Annex B stream. Returns the new stream and the offsets of the replaced SPS.
+    Fields the coded slices depend on, such as frame_mbs_only_flag, cannot be set.
+    """
+    return rust_utils.rewrite_sps(data, seq_parameter_set_id, fields)
+
+
+def rewrite_pps(data, pic_parameter_set_id, **fields):
+    """
+    Set PPS fields, e.g. constrained_intra_pred_flag=1, on every PPS with the given id in an
+    Annex B stream. Returns the new stream and the offsets of the replaced PPS.
+    Fields the coded slices depend on, such as entropy_coding_mode_flag, cannot be set.
+    """
+    return rust_utils.rewrite_pps(data, pic_parameter_set_id, fields)
+
+
+def map_file(filename):
+    """
+    Memory-map an Annex B file instead of reading it, for files too large to keep in memory.
+    """
+    return rust_utils.MappedStream(str(filename))
+
+
 def write_file(filename, data):
     with open(filename, "wb") as f:
         return f.write(data)
@@ -20,6 +20,10 @@ class Encryptor:
         self.decrypt_time = 0
         self.encode_time = 0
         self.decode_time = 0
+        # reused for every slice, so that encoding and decoding do not allocate
+        self.encrypt_buffer = bytearray()
+        self.encode_buffer = bytearray()
+        self.decode_buffer = bytearray()
 
         self.total_size = 0
         self.encrypt_size = 0
@@ -38,15 +42,23 @@ class Encryptor:
             self.encrypted_size += len(data)
             return data
 
-        encrypted_data = bytearray(data[0:self.unencrypted_len])
         encrypted_payload = self.do_encrypt(data[self.unencrypted_len:])
-        encrypted_data.extend(encrypted_payload)
+        size = self.unencrypted_len + len(encrypted_payload)
+        if len(self.encrypt_buffer) < size:
+            self.encrypt_buffer = bytearray(size)
+        encrypted_data = memoryview(self.encrypt_buffer)[:size]
+        encrypted_data[0:self.unencrypted_len] = data[0:self.unencrypted_len]
+        encrypted_data[self.unencrypted_len:] = encrypted_payload
 
         self.encrypt_size += len(data) - self.unencrypted_len
 
         self.encrypt_time += (datetime.now() - start).total_seconds()
         start = datetime.now()
-        res = utils.nalu_encode(bytes(encrypted_data))
+        # at most one emulation prevention byte for every two bytes, and a final one
+        if len(self.encode_buffer) < size + size // 2 + 1:
+            self.encode_buffer = bytearray(size + size // 2 + 1)
+        buffer = memoryview(self.encode_buffer)
+        res = buffer[:utils.nalu_encode_into(encrypted_data, buffer)]
         self.encode_time += (datetime.now() - start).total_seconds()
         self.encrypted_size += len(res)
         return res
@@ -60,7 +72,10 @@ class Encryptor:
             return data
 
         start = datetime.now()
-        decoded_data = utils.nalu_decode(data)
+        if len(self.decode_buffer) < len(data):
+            self.decode_buffer = bytearray(len(data))
+        buffer = memoryview(self.decode_buffer)
+        decoded_data = buffer[:utils.nalu_decode_into(data, buffer)]
         self.decode_time += (datetime.now() - start).total_seconds()
         start = datetime.now()
 
@@ -24,10 +24,17 @@
 # SOFTWARE OR THE USE OR OTHER DEALINGS IN THE SOFTWARE.>
 
 import os
+import warnings
 
 from bitstring import BitStream
 
-from . import nalutypes
+from . import nalu_utils, nalutypes
+
+try:
+    # the extension's parameter set store, which also tracks activations and redefinitions
+    from rust_utils import ParameterSets
+except ImportError:
+    ParameterSets = None
 
 
 class H26xParser:
@@ -164,6 +171,75 @@ class H26xParser:
         retnals.append((start, end, nals[-1][1], nals[-1][2], nals[-1][3], nals[-1][4]))
         return retnals
 
+    def _callback_bitstream(self, rbsp_payload, parsed_bs):
+        """
+        bitstring.BitStream over the RBSP for the callbacks, positioned after the syntax elements
+        the parser has read from `parsed_bs`.
+        """
+        if isinstance(parsed_bs, BitStream):
+            return parsed_bs
+        rbsp_payload_bs = BitStream(bytearray(rbsp_payload))
+        rbsp_payload_bs.pos = parsed_bs.pos
+        return rbsp_payload_bs
+
+    def _slice_parameter_sets(
+        self, start, end, rbsp_payload_bs, sps_by_id, pps_by_id, parameter_sets
+    ):
+        """
+        Look up the PPS a slice refers to by its pic_parameter_set_id, and the SPS that PPS
+        refers to. Warns and returns (None, None) if either has not been received.
+
+        With the extension's `parameter_sets`, the slice activates them there, which also
+        reveals active parameter sets that changed where they may not.
+        """
+        if parameter_sets is not None:
+            return self._activate_parameter_sets(
+                parameter_sets, self.byte_stream[start : end + 1], start, sps_by_id, pps_by_id
+            )
+        pos = rbsp_payload_bs.pos
+        # first_mb_in_slice, slice_type, pic_parameter_set_id
+        rbsp_payload_bs.read("ue")
+        rbsp_payload_bs.read("ue")
+        pps_id = rbsp_payload_bs.read("ue")
+        rbsp_payload_bs.pos = pos
+        nalu_pps = pps_by_id.get(pps_id)
+        if nalu_pps is None:
+            warnings.warn(
+                "Slice refers to PPS " + str(pps_id) + ", which is not available; slice skipped"
+            )
+            return None, None
+        if nalu_pps.seq_parameter_set_id not in sps_by_id:
+            warnings.warn(
+                "PPS " + str(pps_id) + " refers to SPS " + str(nalu_pps.seq_parameter_set_id)
+                + ", which is not available; slice skipped"
+            )
+            return None, None
+        return sps_by_id[nalu_pps.seq_parameter_set_id], nalu_pps
+
+    def _activate_parameter_sets(self, parameter_sets, nalu, position, sps_by_id, pps_by_id):
+        """
+        Activate the parameter sets of the slice NAL unit `nalu` in the extension's store and
+        return their parsed SPS and PPS. Warns about redefinitions of active parameter sets the
+        slice reveals, and returns (None, None) if its parameter sets have not been received.
+        """
+        redefinitions = len(parameter_sets.redefinitions)
+        try:
+            parameter_sets.process_nal(nalu, position)
+        except ValueError as e:
+            warnings.warn("Slice at " + str(position) + ": " + str(e) + "; slice skipped")
+            return None, None
+        for kind, id, redefined_at, _ in parameter_sets.redefinitions[redefinitions:]:
+            warnings.warn(
+                "Active " + kind + " " + str(id) + " changed at " + str(redefined_at)
+                + " before slice at " + str(position)
+            )
+        nalu_sps = sps_by_id.get(parameter_sets.active_sps_id)
+        nalu_pps = pps_by_id.get(parameter_sets.active_pps_id)
+        if nalu_sps is None or nalu_pps is None:
+            # stored by the extension but not parsed here
+            return None, None
+        return nalu_sps, nalu_pps
+
     def parse(self):
         """
         Parse the bitstream and extract each NALU.
@@ -172,6 +248,12 @@ class H26xParser:
 
         self._get_nalu_pos()
 
+        # parameter sets by id; each slice refers to a PPS, which refers to an SPS. The
+        # extension's store, when available, also checks when they become active.
+        sps_by_id = {}
+        pps_by_id = {}
+        parameter_sets = ParameterSets() if ParameterSets is not None else None
+
         for idx, (start, end, is4bytes, fb, nri, type) in enumerate(self.nalu_pos):
             # print("NAL#%d: %d, %d, %d, %d, %d" % (idx, start, end, fb, nri, type))
             if is4bytes:
@@ -215,23 +297,47 @@ class H26xParser:
                 print("NALU RBSP:\t" + "0x" + substr)
                 print("")
 
-            rbsp_payload_bs = BitStream(bytearray(rbsp_payload))
+            # the NAL unit parsers read the RBSP with the extension's reader when it is built;
+            # callbacks get a bitstring.BitStream positioned where the parser stopped
+            rbsp_payload_rs = nalu_utils.rbsp_bitstream(rbsp_payload)
+            if parameter_sets is not None and type in (
+                nalutypes.NAL_UNIT_TYPE_SPS,
+                nalutypes.NAL_UNIT_TYPE_PPS,
+            ):
+                try:
+                    parameter_sets.process_nal(self.byte_stream[start : end + 1], start)
+                except ValueError as e:
+                    warnings.warn("Parameter set at " + str(start) + ": " + str(e))
             if type == nalutypes.NAL_UNIT_TYPE_SPS:
-                nalu_sps = nalutypes.SPS(rbsp_payload_bs, self.verbose)
-                self.__call("sps", rbsp_payload_bs)
+                nalu_sps = nalutypes.SPS(rbsp_payload_rs, self.verbose)
+                sps_by_id[nalu_sps.seq_parameter_set_id] = nalu_sps
+                self.__call("sps", self._callback_bitstream(rbsp_payload, rbsp_payload_rs))
             elif type == nalutypes.NAL_UNIT_TYPE_PPS:
-                nalu_pps = nalutypes.PPS(rbsp_payload_bs, self.verbose)
-                self.__call("pps", rbsp_payload_bs)
+                nalu_pps = nalutypes.PPS(rbsp_payload_rs, self.verbose)
+                pps_by_id[nalu_pps.pic_parameter_set_id] = nalu_pps
+                self.__call("pps", self._callback_bitstream(rbsp_payload, rbsp_payload_rs))
             elif type == nalutypes.NAL_UNIT_TYPE_AUD:
-                aud = nalutypes.AUD(rbsp_payload_bs, self.verbose)
-                self.__call("aud", rbsp_payload_bs)
+                aud = nalutypes.AUD(rbsp_payload_rs, self.verbose)
+                self.__call("aud", self._callback_bitstream(rbsp_payload, rbsp_payload_rs))
             elif type == nalutypes.NAL_UNIT_TYPE_CODED_SLICE_NON_IDR:
-                nalu_slice = nalutypes.CodedSliceNonIDR(
-                    rbsp_payload_bs, nalu_sps, nalu_pps, self.verbose
+                nalu_slice = None
+                nalu_sps, nalu_pps = self._slice_parameter_sets(
+                    start, end, rbsp_payload_rs, sps_by_id, pps_by_id, parameter_sets
                 )
-                self.__call("slice", rbsp_payload_bs)
+                if nalu_pps is not None:
+                    nalu_slice = nalutypes.CodedSliceNonIDR(
+                        rbsp_payload_rs, nalu_sps, nalu_pps, self.verbose
+                    )
+                # the raw slice is passed on even when its header could not be parsed
+                self.__call("slice", self._callback_bitstream(rbsp_payload, rbsp_payload_rs))
             elif type == nalutypes.NAL_UNIT_TYPE_CODED_SLICE_IDR:
-                nalu_slice = nalutypes.CodedSliceIDR(
-                    rbsp_payload_bs, nalu_sps, nalu_pps, self.verbose
+                nalu_slice = None
+                nalu_sps, nalu_pps = self._slice_parameter_sets(
+                    start, end, rbsp_payload_rs, sps_by_id, pps_by_id, parameter_sets
                 )
-                self.__call("slice", rbsp_payload_bs)
+                if nalu_pps is not None:
+                    nalu_slice = nalutypes.CodedSliceIDR(
+                        rbsp_payload_rs, nalu_sps, nalu_pps, self.verbose
+                    )
+                # the raw slice is passed on even when its header could not be parsed
+                self.__call("slice", self._callback_bitstream(rbsp_payload, rbsp_payload_rs))
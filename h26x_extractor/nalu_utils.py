@@ -1,7 +1,24 @@
 from bitstring import BitStream, Bits
 
+try:
+    # the extension's bit reader, whose more_rbsp_data() runs in constant time
+    from rust_utils import BitStream as RbspBitStream
+except ImportError:
+    RbspBitStream = BitStream
+
+
+def rbsp_bitstream(rbsp_bytes):
+    """
+    Bit stream over an RBSP for the NAL unit parsers: rust_utils.BitStream when the extension is
+    built, bitstring.BitStream otherwise. Callbacks always get a bitstring.BitStream.
+    """
+    return RbspBitStream(bytearray(rbsp_bytes))
+
 
 def more_rbsp_data(s: BitStream):
+    # rust_utils.BitStream locates the rbsp_stop_one_bit once and answers in constant time
+    if hasattr(s, "more_rbsp_data"):
+        return s.more_rbsp_data()
     if s.pos >= len(s):
         return False
     last_one_pos, = BitStream(s).rfind(Bits('0b1'))
@@ -29,6 +29,12 @@ from tabulate import tabulate
 
 from h26x_extractor import nalu_utils
 
+try:
+    # the typed NAL header model of the rust_utils extension
+    from rust_utils import NalHeader
+except ImportError:
+    NalHeader = None
+
 # NAL REF IDC codes
 NAL_REF_IDC_PRIORITY_HIGHEST = 3
 NAL_REF_IDC_PRIORITY_HIGH = 2
@@ -50,7 +56,9 @@ NAL_UNIT_TYPE_END_OF_SEQUENCE = 10  # End of sequence
 NAL_UNIT_TYPE_END_OF_STREAM = 11  # End of stream
 NAL_UNIT_TYPE_FILLER = 12  # Filler data
 NAL_UNIT_TYPE_SPS_EXT = 13  # Sequence parameter set extension
-# 14..18                                          # Reserved
+NAL_UNIT_TYPE_PREFIX = 14  # Prefix NAL unit
+NAL_UNIT_TYPE_SUBSET_SPS = 15  # Subset sequence parameter set
+# 16..18                                          # Reserved
 NAL_UNIT_TYPE_CODED_SLICE_AUX = (
     19  # Coded slice of an auxiliary coded picture without partitioning
 )
@@ -62,8 +70,11 @@ NAL_UNIT_TYPE_CODED_SLICE_AUX = (
 
 def get_description(nal_unit_type):
     """
-    Returns a clear text description of a NALU type given as an integer
+    Returns a clear text description of a NALU type given as an integer, from
+    rust_utils.NalHeader when the extension is built
     """
+    if NalHeader is not None:
+        return NalHeader.type_description(nal_unit_type)
     return {
         NAL_UNIT_TYPE_UNSPECIFIED: "Unspecified",
         NAL_UNIT_TYPE_CODED_SLICE_NON_IDR: "Coded slice of a non-IDR picture",
@@ -79,6 +90,8 @@ def get_description(nal_unit_type):
         NAL_UNIT_TYPE_END_OF_STREAM: "End of stream",
         NAL_UNIT_TYPE_FILLER: "Filler data",
         NAL_UNIT_TYPE_SPS_EXT: "Sequence parameter set extension",
+        NAL_UNIT_TYPE_PREFIX: "Prefix NAL unit",
+        NAL_UNIT_TYPE_SUBSET_SPS: "Subset sequence parameter set",
         NAL_UNIT_TYPE_CODED_SLICE_AUX: "Coded slice of an auxiliary coded picture without partitioning",
     }.get(nal_unit_type, "unknown")
 
@@ -108,7 +121,10 @@ class NALU(object):
     """
 
     def __init__(self, rbsp_bytes, verbose, order=None, include_header=False):
-        self.s = rbsp_bytes if isinstance(rbsp_bytes, BitStream) else BitStream(rbsp_bytes)
+        if isinstance(rbsp_bytes, (BitStream, nalu_utils.RbspBitStream)):
+            self.s = rbsp_bytes
+        else:
+            self.s = nalu_utils.rbsp_bitstream(rbsp_bytes)
         self.verbose = verbose
         self.order = order
 
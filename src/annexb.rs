@@ -0,0 +1,390 @@
+use std::fmt;
+
+use memchr::memmem;
+
+use crate::nal::NalHeader;
+
+const START_CODE_PREFIX: &[u8] = b"\x00\x00\x01";
+
+/// A NAL unit located in an Annex B byte stream.
+///
+/// `start..end` covers the NAL unit itself, header byte included and start code excluded.
+/// Zero bytes between the end of the NAL unit and the next start code are not part of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NalUnit {
+    pub start: usize,
+    pub end: usize,
+    pub start_code_len: usize,
+    pub header: NalHeader,
+}
+
+/// Parses the header of a non-empty NAL unit, leaving out an extension cut short by its end.
+fn header_of(nal: &[u8]) -> NalHeader {
+    NalHeader::parse(nal).unwrap_or_else(|| NalHeader::from_byte(nal[0]))
+}
+
+/// Iterator over the NAL units of an Annex B byte stream, see [`nal_units`].
+///
+/// `P` yields the offsets of the 0x000001 start code prefixes in increasing order.
+pub struct NalUnits<'a, P = memmem::FindIter<'a, 'static>> {
+    data: &'a [u8],
+    prefixes: P,
+    // (start code offset, start code length) of the next NAL unit
+    pending: Option<(usize, usize)>,
+}
+
+impl<'a, P: Iterator<Item = usize>> NalUnits<'a, P> {
+    fn from_prefixes(data: &'a [u8], prefixes: P) -> Self {
+        let mut units = NalUnits {
+            data,
+            prefixes,
+            pending: None,
+        };
+        units.pending = units.next_start_code();
+        units
+    }
+
+    fn next_start_code(&mut self) -> Option<(usize, usize)> {
+        let pos = self.prefixes.next()?;
+        if pos > 0 && self.data[pos - 1] == 0 {
+            Some((pos - 1, 4))
+        } else {
+            Some((pos, 3))
+        }
+    }
+}
+
+impl<'a, P: Iterator<Item = usize>> Iterator for NalUnits<'a, P> {
+    type Item = NalUnit;
+
+    fn next(&mut self) -> Option<NalUnit> {
+        loop {
+            let (code_pos, start_code_len) = self.pending?;
+            self.pending = self.next_start_code();
+
+            let start = code_pos + start_code_len;
+            let mut end = self.pending.map_or(self.data.len(), |(pos, _)| pos);
+            // a NAL unit never ends with a zero byte, those belong to the byte stream
+            while end > start && self.data[end - 1] == 0 {
+                end -= 1;
+            }
+            if end <= start {
+                continue;
+            }
+            return Some(NalUnit {
+                start,
+                end,
+                start_code_len,
+                header: header_of(&self.data[start..end]),
+            });
+        }
+    }
+}
+
+/// Lazily scans `data` for start codes and yields every non-empty NAL unit.
+pub fn nal_units(data: &[u8]) -> NalUnits<'_> {
+    NalUnits::from_prefixes(data, memmem::find_iter(data, START_CODE_PREFIX))
+}
+
+/// Collects all NAL units of an Annex B byte stream.
+pub fn find_nal_units(data: &[u8]) -> Vec<NalUnit> {
+    nal_units(data).collect()
+}
+
+// regions smaller than this are not worth a thread
+const MIN_REGION_LEN: usize = 1 << 20;
+
+/// Like [`find_nal_units`], but searches for start codes on `threads` threads, or on as many
+/// threads as there are CPUs if `threads` is 0. The result is identical to the sequential scan.
+pub fn find_nal_units_parallel(data: &[u8], threads: usize) -> Vec<NalUnit> {
+    let threads = match threads {
+        0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+        n => n,
+    };
+    let region_len = data.len().div_ceil(threads).max(MIN_REGION_LEN);
+    if region_len >= data.len() {
+        return find_nal_units(data);
+    }
+
+    // Each region reports the prefixes that begin inside it, looking up to two bytes into the
+    // next region for the ones that straddle the boundary. Start code prefixes cannot overlap
+    // each other, so the regions together find exactly the prefixes of the sequential scan.
+    let prefixes: Vec<Vec<usize>> = std::thread::scope(|scope| {
+        let handles: Vec<_> = (0..data.len())
+            .step_by(region_len)
+            .map(|region_start| {
+                scope.spawn(move || {
+                    let region_end = (region_start + region_len).min(data.len());
+                    let search_end = (region_end + START_CODE_PREFIX.len() - 1).min(data.len());
+                    memmem::find_iter(&data[region_start..search_end], START_CODE_PREFIX)
+                        .map(|pos| region_start + pos)
+                        .take_while(|&pos| pos < region_end)
+                        .collect()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    });
+
+    // building the NAL units only looks at the bytes around each start code
+    NalUnits::from_prefixes(data, prefixes.into_iter().flatten()).collect()
+}
+
+/// A byte_stream_nal_unit() with the zero bytes around the NAL unit accounted as in B.1.1.
+///
+/// The units returned by [`parse_byte_stream`] tile the whole stream, so writing them back with
+/// [`ByteStreamNalUnit::write`] reproduces it byte for byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteStreamNalUnit {
+    /// Offset of the first byte of the unit, leading zero bytes included.
+    pub offset: usize,
+    /// Zero bytes before the zero_byte, only found before the first NAL unit of a stream.
+    pub leading_zero_8bits: usize,
+    /// Whether the start code prefix is preceded by a zero_byte, i.e. is a 4 byte start code.
+    pub zero_byte: bool,
+    pub nal: NalUnit,
+    /// Zero bytes after the NAL unit that do not belong to the next start code.
+    pub trailing_zero_8bits: usize,
+}
+
+impl ByteStreamNalUnit {
+    /// Offset one past the last trailing zero byte.
+    pub fn end(&self) -> usize {
+        self.nal.end + self.trailing_zero_8bits
+    }
+
+    /// Writes `nal` framed with the start code and zero bytes of this unit.
+    pub fn write(&self, nal: &[u8], out: &mut Vec<u8>) {
+        out.resize(
+            out.len() + self.leading_zero_8bits + self.zero_byte as usize,
+            0,
+        );
+        out.extend_from_slice(START_CODE_PREFIX);
+        out.extend_from_slice(nal);
+        out.resize(out.len() + self.trailing_zero_8bits, 0);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ByteStreamError {
+    /// The stream does not begin with zero bytes followed by a start code.
+    DataBeforeStartCode { offset: usize },
+    /// A start code is directly followed by another one.
+    EmptyNalUnit { offset: usize },
+}
+
+impl fmt::Display for ByteStreamError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ByteStreamError::DataBeforeStartCode { offset } => {
+                write!(
+                    f,
+                    "non-zero byte before the first start code at offset {}",
+                    offset
+                )
+            }
+            ByteStreamError::EmptyNalUnit { offset } => {
+                write!(
+                    f,
+                    "empty NAL unit after the start code at offset {}",
+                    offset
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for ByteStreamError {}
+
+/// Parses an Annex B byte stream following B.1.1 and B.2.
+///
+/// Zero bytes between two NAL units are trailing_zero_8bits of the first one, except for the
+/// last one which is the zero_byte of the next start code.
+pub fn parse_byte_stream(data: &[u8]) -> Result<Vec<ByteStreamNalUnit>, ByteStreamError> {
+    let mut units: Vec<ByteStreamNalUnit> = Vec::new();
+    let mut prefixes = memmem::find_iter(data, START_CODE_PREFIX).peekable();
+    // where the zero bytes before the next start code begin
+    let mut zeros_start = 0;
+
+    while let Some(prefix) = prefixes.next() {
+        if let Some(offset) = data[zeros_start..prefix].iter().position(|&b| b != 0) {
+            return Err(ByteStreamError::DataBeforeStartCode {
+                offset: zeros_start + offset,
+            });
+        }
+        let zeros = prefix - zeros_start;
+        let zero_byte = zeros > 0;
+        let unit_offset = match units.last_mut() {
+            Some(prev) => {
+                prev.trailing_zero_8bits = zeros.saturating_sub(1);
+                prev.end()
+            }
+            None => 0,
+        };
+
+        let start = prefix + START_CODE_PREFIX.len();
+        let next = prefixes.peek().copied().unwrap_or(data.len());
+        let end = data[start..next]
+            .iter()
+            .rposition(|&b| b != 0)
+            .map(|pos| start + pos + 1)
+            .ok_or(ByteStreamError::EmptyNalUnit { offset: prefix })?;
+        units.push(ByteStreamNalUnit {
+            offset: unit_offset,
+            leading_zero_8bits: if units.is_empty() {
+                zeros.saturating_sub(1)
+            } else {
+                0
+            },
+            zero_byte,
+            nal: NalUnit {
+                start,
+                end,
+                start_code_len: START_CODE_PREFIX.len() + zero_byte as usize,
+                header: header_of(&data[start..end]),
+            },
+            trailing_zero_8bits: 0,
+        });
+        zeros_start = end;
+    }
+
+    match units.last_mut() {
+        Some(last) => last.trailing_zero_8bits = data.len() - last.nal.end,
+        None => {
+            if let Some(offset) = data.iter().position(|&b| b != 0) {
+                return Err(ByteStreamError::DataBeforeStartCode { offset });
+            }
+        }
+    }
+    Ok(units)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nal::NalUnitType;
+
+    #[test]
+    fn test_find_nal_units() {
+        let data = b"\x00\x00\x00\x01\x67\x64\x00\x00\x01\x68\xeb\x00\x00\x00\x00\x01\x65\x88";
+        let units = find_nal_units(data);
+        assert_eq!(units.len(), 3);
+
+        assert_eq!(
+            (units[0].start, units[0].end, units[0].start_code_len),
+            (4, 6, 4)
+        );
+        assert_eq!(units[0].header.nal_unit_type, NalUnitType::Sps);
+        assert_eq!(units[0].header.nal_ref_idc, 3);
+
+        assert_eq!(
+            (units[1].start, units[1].end, units[1].start_code_len),
+            (9, 11, 3)
+        );
+        assert_eq!(units[1].header.nal_unit_type, NalUnitType::Pps);
+
+        // the extra zero byte is trailing_zero_8bits, not part of the PPS
+        assert_eq!(
+            (units[2].start, units[2].end, units[2].start_code_len),
+            (16, 18, 4)
+        );
+        assert_eq!(units[2].header.nal_unit_type, NalUnitType::SliceIdr);
+    }
+
+    #[test]
+    fn test_find_nal_units_skips_empty() {
+        assert!(find_nal_units(b"").is_empty());
+        assert!(find_nal_units(b"\x00\x00\x01").is_empty());
+        let units = find_nal_units(b"\x00\x00\x01\x00\x00\x01\x09\xf0");
+        assert_eq!(units.len(), 1);
+        assert_eq!(units[0].header.nal_unit_type, NalUnitType::Aud);
+    }
+
+    #[test]
+    fn test_find_nal_units_parallel() {
+        let data = std::fs::read(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/v/input/small_bunny_1080p_30fps.h264"
+        ))
+        .unwrap();
+        let expected = find_nal_units(&data);
+        for threads in [0, 1, 2, 3, 8] {
+            assert_eq!(find_nal_units_parallel(&data, threads), expected);
+        }
+
+        // start codes at and around the boundary between two regions
+        for shift in 0..5 {
+            let mut data = vec![0x65; 2 * MIN_REGION_LEN];
+            data[..4].copy_from_slice(b"\x00\x00\x00\x01");
+            let pos = MIN_REGION_LEN - shift;
+            data[pos..pos + 4].copy_from_slice(b"\x00\x00\x00\x01");
+            let expected = find_nal_units(&data);
+            assert_eq!(expected.len(), 2);
+            assert_eq!(
+                find_nal_units_parallel(&data, 2),
+                expected,
+                "shift {}",
+                shift
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_byte_stream() {
+        let data =
+            b"\x00\x00\x00\x00\x01\x67\x64\x00\x00\x01\x68\xeb\x00\x00\x00\x00\x01\x65\x88\x00\x00";
+        let units = parse_byte_stream(data).unwrap();
+        assert_eq!(units.len(), 3);
+
+        assert_eq!(units[0].offset, 0);
+        assert_eq!(units[0].leading_zero_8bits, 1);
+        assert!(units[0].zero_byte);
+        assert_eq!((units[0].nal.start, units[0].nal.end), (5, 7));
+        assert_eq!(units[0].trailing_zero_8bits, 0);
+
+        assert_eq!(units[1].offset, 7);
+        assert!(!units[1].zero_byte);
+        assert_eq!(units[1].nal.start_code_len, 3);
+        assert_eq!(units[1].trailing_zero_8bits, 1);
+
+        assert_eq!(units[2].offset, 13);
+        assert_eq!(units[2].leading_zero_8bits, 0);
+        assert!(units[2].zero_byte);
+        assert_eq!(units[2].trailing_zero_8bits, 2);
+        assert_eq!(units[2].end(), data.len());
+
+        let mut out = Vec::new();
+        for unit in &units {
+            unit.write(&data[unit.nal.start..unit.nal.end], &mut out);
+        }
+        assert_eq!(out, data);
+
+        let nals: Vec<NalUnit> = units.iter().map(|unit| unit.nal).collect();
+        assert_eq!(nals, find_nal_units(data));
+    }
+
+    #[test]
+    fn test_parse_byte_stream_errors() {
+        assert_eq!(parse_byte_stream(b"\x00\x00"), Ok(vec![]));
+        assert_eq!(
+            parse_byte_stream(b"\x00\x09\x00\x00\x01\x09"),
+            Err(ByteStreamError::DataBeforeStartCode { offset: 1 })
+        );
+        assert_eq!(
+            parse_byte_stream(b"\x00\x00\x01\x00\x00\x00\x01\x09"),
+            Err(ByteStreamError::EmptyNalUnit { offset: 0 })
+        );
+    }
+
+    #[test]
+    fn test_find_nal_units_file() {
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.h264")).unwrap();
+        let types: Vec<u8> = nal_units(&data)
+            .map(|nal| nal.header.nal_unit_type.value())
+            .collect();
+        assert_eq!(&types[..4], &[7, 8, 6, 5]);
+    }
+}
@@ -0,0 +1,158 @@
+//! Length-prefixed NAL unit framing as used in MP4 samples (ISO/IEC 14496-15), a.k.a. AVCC.
+
+use std::fmt;
+
+use crate::annexb;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AvccError {
+    /// The NAL length size is not 1, 2 or 4 bytes.
+    InvalidLengthSize(usize),
+    /// A length field or the NAL unit it announces runs past the end of the data.
+    Truncated { offset: usize },
+    /// A NAL unit is too long to be described by the length field.
+    NalTooLong { len: usize, length_size: usize },
+}
+
+impl fmt::Display for AvccError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AvccError::InvalidLengthSize(size) => {
+                write!(f, "invalid NAL length size {}, expected 1, 2 or 4", size)
+            }
+            AvccError::Truncated { offset } => write!(f, "truncated NAL unit at offset {}", offset),
+            AvccError::NalTooLong { len, length_size } => write!(
+                f,
+                "NAL unit of {} bytes does not fit a {} byte length field",
+                len, length_size
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AvccError {}
+
+fn check_length_size(length_size: usize) -> Result<(), AvccError> {
+    match length_size {
+        1 | 2 | 4 => Ok(()),
+        _ => Err(AvccError::InvalidLengthSize(length_size)),
+    }
+}
+
+/// Splits length-prefixed data into its NAL units.
+pub fn read_nal_units(data: &[u8], length_size: usize) -> Result<Vec<&[u8]>, AvccError> {
+    check_length_size(length_size)?;
+    let mut nals = Vec::new();
+    let mut pos = 0;
+    while pos < data.len() {
+        let len_end = pos + length_size;
+        if len_end > data.len() {
+            return Err(AvccError::Truncated { offset: pos });
+        }
+        let len = data[pos..len_end]
+            .iter()
+            .fold(0usize, |acc, &b| (acc << 8) | b as usize);
+        let end = len_end + len;
+        if end > data.len() {
+            return Err(AvccError::Truncated { offset: pos });
+        }
+        nals.push(&data[len_end..end]);
+        pos = end;
+    }
+    Ok(nals)
+}
+
+/// Writes NAL units with a big-endian length prefix of `length_size` bytes each.
+pub fn write_nal_units<'a, I>(nals: I, length_size: usize) -> Result<Vec<u8>, AvccError>
+where
+    I: IntoIterator<Item = &'a [u8]>,
+{
+    check_length_size(length_size)?;
+    let max_len = (u32::MAX >> (8 * (4 - length_size))) as usize;
+    let mut res = Vec::new();
+    for nal in nals {
+        if nal.len() > max_len {
+            return Err(AvccError::NalTooLong {
+                len: nal.len(),
+                length_size,
+            });
+        }
+        res.extend_from_slice(&(nal.len() as u32).to_be_bytes()[4 - length_size..]);
+        res.extend_from_slice(nal);
+    }
+    Ok(res)
+}
+
+/// Converts an Annex B byte stream into length-prefixed NAL units.
+///
+/// NAL unit bytes are kept as they are; start codes and zero stuffing between NAL units are dropped.
+pub fn annexb_to_avcc(data: &[u8], length_size: usize) -> Result<Vec<u8>, AvccError> {
+    write_nal_units(
+        annexb::nal_units(data).map(|nal| &data[nal.start..nal.end]),
+        length_size,
+    )
+}
+
+/// Converts length-prefixed NAL units into an Annex B byte stream with 4 byte start codes.
+pub fn avcc_to_annexb(data: &[u8], length_size: usize) -> Result<Vec<u8>, AvccError> {
+    let nals = read_nal_units(data, length_size)?;
+    let mut res = Vec::with_capacity(data.len() + nals.len() * 4);
+    for nal in nals.into_iter().filter(|nal| !nal.is_empty()) {
+        res.extend_from_slice(b"\x00\x00\x00\x01");
+        res.extend_from_slice(nal);
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_write_nal_units() {
+        let nals: Vec<&[u8]> = vec![b"\x67\x64\x00\x0d", b"\x68", b"\x65\x88\x84"];
+        for length_size in [1, 2, 4] {
+            let avcc = write_nal_units(nals.iter().copied(), length_size).unwrap();
+            assert_eq!(avcc.len(), 8 + 3 * length_size);
+            assert_eq!(read_nal_units(&avcc, length_size).unwrap(), nals);
+        }
+        assert_eq!(
+            write_nal_units(nals.iter().copied(), 2).unwrap()[..6],
+            [0x00, 0x04, 0x67, 0x64, 0x00, 0x0d]
+        );
+    }
+
+    #[test]
+    fn test_avcc_errors() {
+        assert_eq!(read_nal_units(b"", 3), Err(AvccError::InvalidLengthSize(3)));
+        assert_eq!(
+            read_nal_units(b"\x02\x65\x88\x03\x65", 1),
+            Err(AvccError::Truncated { offset: 3 })
+        );
+        assert_eq!(
+            read_nal_units(b"\x00\x00\x00", 4),
+            Err(AvccError::Truncated { offset: 0 })
+        );
+        let long = vec![0x65; 256];
+        assert_eq!(
+            write_nal_units([&long[..]], 1),
+            Err(AvccError::NalTooLong {
+                len: 256,
+                length_size: 1
+            })
+        );
+    }
+
+    #[test]
+    fn test_annexb_avcc_round_trip() {
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.h264")).unwrap();
+        let avcc = annexb_to_avcc(&data, 4).unwrap();
+        let annexb = avcc_to_annexb(&avcc, 4).unwrap();
+        assert_eq!(annexb_to_avcc(&annexb, 4).unwrap(), avcc);
+
+        let original: Vec<&[u8]> = annexb::nal_units(&data)
+            .map(|nal| &data[nal.start..nal.end])
+            .collect();
+        assert_eq!(read_nal_units(&avcc, 4).unwrap(), original);
+    }
+}
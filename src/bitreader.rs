@@ -0,0 +1,289 @@
+//! Bit reader for RBSP data with the descriptors of H.264 7.2: u(n), f(n), ue(v), se(v) and te(v).
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitReaderError {
+    /// Reading `needed` bits at bit position `pos` runs past the end of the data.
+    Eof { pos: usize, needed: usize },
+    /// A read of more bits than fit the returned integer.
+    InvalidWidth(u32),
+    /// An Exp-Golomb code starting at bit position `pos` does not fit 32 bits.
+    InvalidExpGolomb { pos: usize },
+}
+
+impl fmt::Display for BitReaderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitReaderError::Eof { pos, needed } => write!(
+                f,
+                "cannot read {} bits at bit position {}: end of data",
+                needed, pos
+            ),
+            BitReaderError::InvalidWidth(n) => write!(f, "cannot read {} bits at once", n),
+            BitReaderError::InvalidExpGolomb { pos } => {
+                write!(f, "Exp-Golomb code at bit position {} exceeds 32 bits", pos)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitReaderError {}
+
+/// Bit position of the rbsp_stop_one_bit: the last 1 bit of the RBSP, which is followed only by
+/// alignment zero bits and, in slices, cabac_zero_words. None if the RBSP has no 1 bit.
+pub fn rbsp_stop_bit(rbsp: &[u8]) -> Option<usize> {
+    let last = rbsp.iter().rposition(|&b| b != 0)?;
+    Some(last * 8 + 7 - rbsp[last].trailing_zeros() as usize)
+}
+
+/// Reads syntax elements MSB first from RBSP data, i.e. with emulation prevention bytes removed.
+#[derive(Debug, Clone)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    stop_bit: Option<usize>,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            pos: 0,
+            stop_bit: rbsp_stop_bit(data),
+        }
+    }
+
+    /// Resumes reading at `pos` with the stop bit already computed by `rbsp_stop_bit`, to avoid
+    /// locating it again for every reader over the same data.
+    pub fn from_parts(data: &'a [u8], pos: usize, stop_bit: Option<usize>) -> Self {
+        BitReader {
+            data,
+            pos,
+            stop_bit,
+        }
+    }
+
+    /// The data being read.
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Current position in bits from the start of the data.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves to a bit position; positions past the end make every following read fail.
+    pub fn set_position(&mut self, pos: usize) {
+        self.pos = pos;
+    }
+
+    /// Length of the data in bits.
+    pub fn len_bits(&self) -> usize {
+        self.data.len() * 8
+    }
+
+    /// Number of bits left to read.
+    pub fn bits_left(&self) -> usize {
+        self.len_bits().saturating_sub(self.pos)
+    }
+
+    fn check_left(&self, needed: usize) -> Result<(), BitReaderError> {
+        if needed > self.bits_left() {
+            return Err(BitReaderError::Eof {
+                pos: self.pos,
+                needed,
+            });
+        }
+        Ok(())
+    }
+
+    /// Reads `n` bits, up to 64, as an unsigned integer.
+    pub fn read_bits(&mut self, n: u32) -> Result<u64, BitReaderError> {
+        if n > 64 {
+            return Err(BitReaderError::InvalidWidth(n));
+        }
+        self.check_left(n as usize)?;
+        let mut value = 0u64;
+        let mut left = n;
+        while left > 0 {
+            let avail = 8 - (self.pos % 8) as u32;
+            let take = avail.min(left);
+            let byte = self.data[self.pos / 8] as u64;
+            let bits = (byte >> (avail - take)) & ((1 << take) - 1);
+            // take < 64, so the shift cannot overflow even when value is still 0
+            value = (value << take) | bits;
+            self.pos += take as usize;
+            left -= take;
+        }
+        Ok(value)
+    }
+
+    /// Reads a single bit as a flag.
+    pub fn read_flag(&mut self) -> Result<bool, BitReaderError> {
+        Ok(self.read_bits(1)? == 1)
+    }
+
+    /// Skips `n` bits.
+    pub fn skip_bits(&mut self, n: usize) -> Result<(), BitReaderError> {
+        self.check_left(n)?;
+        self.pos += n;
+        Ok(())
+    }
+
+    /// u(n): unsigned integer using `n` bits, up to 32.
+    pub fn u(&mut self, n: u32) -> Result<u32, BitReaderError> {
+        if n > 32 {
+            return Err(BitReaderError::InvalidWidth(n));
+        }
+        Ok(self.read_bits(n)? as u32)
+    }
+
+    /// f(n): fixed-pattern bit string using `n` bits, up to 32.
+    pub fn f(&mut self, n: u32) -> Result<u32, BitReaderError> {
+        self.u(n)
+    }
+
+    /// ue(v): unsigned integer Exp-Golomb-coded syntax element (9.1).
+    pub fn ue(&mut self) -> Result<u32, BitReaderError> {
+        let start = self.pos;
+        let mut leading_zero_bits = 0;
+        while !self.read_flag()? {
+            leading_zero_bits += 1;
+            if leading_zero_bits > 32 {
+                return Err(BitReaderError::InvalidExpGolomb { pos: start });
+            }
+        }
+        let suffix = self.read_bits(leading_zero_bits)?;
+        let code_num = (1u64 << leading_zero_bits) - 1 + suffix;
+        u32::try_from(code_num).map_err(|_| BitReaderError::InvalidExpGolomb { pos: start })
+    }
+
+    /// se(v): signed integer Exp-Golomb-coded syntax element (9.1.1).
+    pub fn se(&mut self) -> Result<i32, BitReaderError> {
+        let start = self.pos;
+        let k = self.ue()? as i64;
+        let value = if k % 2 == 1 { (k + 1) / 2 } else { -(k / 2) };
+        i32::try_from(value).map_err(|_| BitReaderError::InvalidExpGolomb { pos: start })
+    }
+
+    /// te(v): truncated Exp-Golomb-coded syntax element (9.1) whose range is 0..=`range`.
+    pub fn te(&mut self, range: u32) -> Result<u32, BitReaderError> {
+        if range > 1 {
+            self.ue()
+        } else {
+            Ok(!self.read_flag()? as u32)
+        }
+    }
+
+    /// byte_aligned(): whether the position is on a byte boundary.
+    // usize::is_multiple_of needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn byte_aligned(&self) -> bool {
+        self.pos % 8 == 0
+    }
+
+    /// Moves to the next byte boundary, returning the number of bits skipped.
+    pub fn byte_align(&mut self) -> usize {
+        let skipped = (8 - self.pos % 8) % 8;
+        self.pos += skipped;
+        skipped
+    }
+
+    /// Bit position of the rbsp_stop_one_bit, see `rbsp_stop_bit`.
+    pub fn stop_bit(&self) -> Option<usize> {
+        self.stop_bit
+    }
+
+    /// more_rbsp_data(): whether there is more data before the rbsp_stop_one_bit (7.2).
+    pub fn more_rbsp_data(&self) -> bool {
+        self.stop_bit.is_some_and(|stop_bit| self.pos < stop_bit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulation;
+
+    #[test]
+    fn test_read_bits() {
+        let mut r = BitReader::new(&[0b1010_1100, 0x0f, 0xff, 0x01]);
+        assert_eq!(r.u(1), Ok(1));
+        assert_eq!(r.u(3), Ok(0b010));
+        assert_eq!(r.u(8), Ok(0b1100_0000));
+        assert!(!r.byte_aligned());
+        assert_eq!(r.byte_align(), 4);
+        assert_eq!(r.read_bits(16), Ok(0xff01));
+        assert_eq!(r.bits_left(), 0);
+        assert_eq!(r.u(1), Err(BitReaderError::Eof { pos: 32, needed: 1 }));
+        assert_eq!(r.u(33), Err(BitReaderError::InvalidWidth(33)));
+
+        let mut r = BitReader::new(&[0xde, 0xad, 0xbe, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89]);
+        r.skip_bits(4).unwrap();
+        assert_eq!(r.read_bits(64), Ok(0xeadbeef012345678));
+    }
+
+    #[test]
+    fn test_exp_golomb() {
+        // ue: 1 | 010 | 011 | 00100 | 00111 | 0001000, then 1 as te with range 1 and 0 as te with range 1
+        let mut r = BitReader::new(&[0b1010_0110, 0b0100_0011, 0b1000_1000, 0b1000_0000]);
+        assert_eq!(r.ue(), Ok(0));
+        assert_eq!(r.ue(), Ok(1));
+        assert_eq!(r.ue(), Ok(2));
+        assert_eq!(r.ue(), Ok(3));
+        assert_eq!(r.ue(), Ok(6));
+        assert_eq!(r.position(), 17);
+        r.set_position(1);
+        assert_eq!(r.se(), Ok(1));
+        assert_eq!(r.se(), Ok(-1));
+        assert_eq!(r.se(), Ok(2));
+        assert_eq!(r.se(), Ok(-3));
+        assert_eq!(r.te(7), Ok(7));
+        assert_eq!(r.te(1), Ok(0));
+        assert_eq!(r.te(1), Ok(1));
+
+        // 32 leading zeros and a zero suffix is the largest value a ue(v) can hold
+        let mut max = vec![0u8; 4];
+        max.extend([0x80, 0, 0, 0, 0]);
+        assert_eq!(BitReader::new(&max).ue(), Ok(u32::MAX));
+        assert_eq!(
+            BitReader::new(&max).se(),
+            Err(BitReaderError::InvalidExpGolomb { pos: 0 })
+        );
+        assert_eq!(
+            BitReader::new(&[0, 0, 0, 0, 0, 0x80]).ue(),
+            Err(BitReaderError::InvalidExpGolomb { pos: 0 })
+        );
+        assert_eq!(
+            BitReader::new(&[0, 0x01]).ue(),
+            Err(BitReaderError::Eof {
+                pos: 16,
+                needed: 15
+            })
+        );
+    }
+
+    #[test]
+    fn test_more_rbsp_data() {
+        // payload bits 1, 0, 1 followed by the rbsp_stop_one_bit and alignment zeros
+        let mut r = BitReader::new(&[0b1011_0000, 0x00]);
+        assert!(r.more_rbsp_data());
+        r.skip_bits(3).unwrap();
+        assert!(!r.more_rbsp_data());
+        assert!(!BitReader::new(&[]).more_rbsp_data());
+        assert!(!BitReader::new(&[0x80]).more_rbsp_data());
+        assert!(!BitReader::new(&[0, 0]).more_rbsp_data());
+
+        // a CABAC slice ending in two cabac_zero_words, i.e. NAL bytes 8c 00 00 03 00 00 03
+        let rbsp = emulation::decode(&[0x8c, 0, 0, 3, 0, 0, 3]);
+        assert_eq!(rbsp, [0x8c, 0, 0, 0, 0]);
+        assert_eq!(rbsp_stop_bit(&rbsp), Some(5));
+        let mut r = BitReader::new(&rbsp);
+        r.skip_bits(4).unwrap();
+        assert!(r.more_rbsp_data());
+        r.skip_bits(1).unwrap();
+        assert!(!r.more_rbsp_data());
+    }
+}
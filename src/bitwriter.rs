@@ -0,0 +1,236 @@
+//! Bit writer producing RBSP data, the counterpart of `bitreader`, and NAL unit payloads from it.
+
+use std::fmt;
+
+use crate::emulation;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BitWriterError {
+    /// A write of more bits than the descriptor allows.
+    InvalidWidth(u32),
+    /// The value does not fit the number of bits, or the 32 bits of an Exp-Golomb code.
+    ValueOutOfRange { value: i64, bits: u32 },
+}
+
+impl fmt::Display for BitWriterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BitWriterError::InvalidWidth(n) => write!(f, "cannot write {} bits at once", n),
+            BitWriterError::ValueOutOfRange { value, bits } => {
+                write!(f, "value {} does not fit {} bits", value, bits)
+            }
+        }
+    }
+}
+
+impl std::error::Error for BitWriterError {}
+
+/// Writes syntax elements MSB first; the last byte is zero-padded until it is complete.
+#[derive(Debug, Clone, Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bits written.
+    pub fn position(&self) -> usize {
+        self.len
+    }
+
+    /// Writes the `n` low bits of `value`, up to 64.
+    pub fn write_bits(&mut self, n: u32, value: u64) -> Result<(), BitWriterError> {
+        if n > 64 {
+            return Err(BitWriterError::InvalidWidth(n));
+        }
+        if n < 64 && value >> n != 0 {
+            return Err(BitWriterError::ValueOutOfRange {
+                value: value as i64,
+                bits: n,
+            });
+        }
+        let mut left = n;
+        while left > 0 {
+            let free = 8 - (self.len % 8) as u32;
+            if free == 8 {
+                self.data.push(0);
+            }
+            let take = free.min(left);
+            let bits = ((value >> (left - take)) & ((1 << take) - 1)) as u8;
+            *self.data.last_mut().unwrap() |= bits << (free - take);
+            self.len += take as usize;
+            left -= take;
+        }
+        Ok(())
+    }
+
+    /// Writes a single bit.
+    pub fn write_flag(&mut self, flag: bool) {
+        // a single bit always fits
+        let _ = self.write_bits(1, flag as u64);
+    }
+
+    /// u(n): unsigned integer using `n` bits, up to 32.
+    pub fn u(&mut self, n: u32, value: u32) -> Result<(), BitWriterError> {
+        if n > 32 {
+            return Err(BitWriterError::InvalidWidth(n));
+        }
+        self.write_bits(n, value as u64)
+    }
+
+    /// f(n): fixed-pattern bit string using `n` bits, up to 32.
+    pub fn f(&mut self, n: u32, value: u32) -> Result<(), BitWriterError> {
+        self.u(n, value)
+    }
+
+    /// ue(v): unsigned integer Exp-Golomb-coded syntax element (9.1).
+    pub fn ue(&mut self, value: u32) -> Result<(), BitWriterError> {
+        let code = value as u64 + 1;
+        let len = 64 - code.leading_zeros();
+        self.write_bits(len - 1, 0)?;
+        self.write_bits(len, code)
+    }
+
+    /// se(v): signed integer Exp-Golomb-coded syntax element (9.1.1).
+    pub fn se(&mut self, value: i32) -> Result<(), BitWriterError> {
+        let value = value as i64;
+        let code_num = if value > 0 { 2 * value - 1 } else { -2 * value };
+        let code_num = u32::try_from(code_num)
+            .map_err(|_| BitWriterError::ValueOutOfRange { value, bits: 32 })?;
+        self.ue(code_num)
+    }
+
+    /// te(v): truncated Exp-Golomb-coded syntax element (9.1) whose range is 0..=`range`.
+    pub fn te(&mut self, range: u32, value: u32) -> Result<(), BitWriterError> {
+        if range > 1 {
+            return self.ue(value);
+        }
+        if value > 1 {
+            return Err(BitWriterError::ValueOutOfRange {
+                value: value as i64,
+                bits: 1,
+            });
+        }
+        self.write_flag(value == 0);
+        Ok(())
+    }
+
+    /// byte_aligned(): whether the next bit starts a byte.
+    // usize::is_multiple_of needs Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn byte_aligned(&self) -> bool {
+        self.len % 8 == 0
+    }
+
+    /// rbsp_trailing_bits() (7.3.2.11): the rbsp_stop_one_bit and alignment zero bits.
+    pub fn rbsp_trailing_bits(&mut self) {
+        self.write_flag(true);
+        // the padding of the last byte is already zero
+        self.len = self.data.len() * 8;
+    }
+
+    /// rbsp_slice_trailing_bits() (7.3.2.10): rbsp_trailing_bits followed by `cabac_zero_words`
+    /// cabac_zero_word (0x0000) elements.
+    pub fn rbsp_slice_trailing_bits(&mut self, cabac_zero_words: usize) {
+        self.rbsp_trailing_bits();
+        self.data.resize(self.data.len() + 2 * cabac_zero_words, 0);
+        self.len = self.data.len() * 8;
+    }
+
+    /// The RBSP written so far, with the last byte zero-padded.
+    pub fn rbsp(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn into_rbsp(self) -> Vec<u8> {
+        self.data
+    }
+
+    /// The RBSP with emulation prevention bytes inserted as by `emulation::encode`, ready to
+    /// follow a NAL unit header.
+    pub fn to_nal_payload(&self) -> Vec<u8> {
+        emulation::encode(&self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitreader::BitReader;
+
+    #[test]
+    fn test_write_bits() {
+        let mut w = BitWriter::new();
+        w.u(1, 1).unwrap();
+        w.u(3, 0b010).unwrap();
+        w.u(8, 0b1100_0000).unwrap();
+        assert!(!w.byte_aligned());
+        w.write_bits(4, 0xf).unwrap();
+        w.write_bits(16, 0xff01).unwrap();
+        assert_eq!(w.rbsp(), &[0b1010_1100, 0x0f, 0xff, 0x01]);
+        assert_eq!(
+            w.u(2, 4),
+            Err(BitWriterError::ValueOutOfRange { value: 4, bits: 2 })
+        );
+        assert_eq!(w.u(33, 0), Err(BitWriterError::InvalidWidth(33)));
+        assert_eq!(w.position(), 32);
+    }
+
+    #[test]
+    fn test_exp_golomb_round_trip() {
+        let mut w = BitWriter::new();
+        for value in [0, 1, 2, 3, 6, 7, 255, 65535, u32::MAX] {
+            w.ue(value).unwrap();
+        }
+        for value in [0, 1, -1, 2, -3, 1000, i32::MAX, -i32::MAX] {
+            w.se(value).unwrap();
+        }
+        w.te(1, 0).unwrap();
+        w.te(1, 1).unwrap();
+        w.te(5, 4).unwrap();
+        assert_eq!(
+            w.se(i32::MIN),
+            Err(BitWriterError::ValueOutOfRange {
+                value: i32::MIN as i64,
+                bits: 32
+            })
+        );
+        w.rbsp_trailing_bits();
+
+        let mut r = BitReader::new(w.rbsp());
+        for value in [0, 1, 2, 3, 6, 7, 255, 65535, u32::MAX] {
+            assert_eq!(r.ue(), Ok(value));
+        }
+        for value in [0, 1, -1, 2, -3, 1000, i32::MAX, -i32::MAX] {
+            assert_eq!(r.se(), Ok(value));
+        }
+        assert_eq!(r.te(1), Ok(0));
+        assert_eq!(r.te(1), Ok(1));
+        assert_eq!(r.te(5), Ok(4));
+        assert!(!r.more_rbsp_data());
+        assert!(w.byte_aligned());
+    }
+
+    #[test]
+    fn test_trailing_bits() {
+        let mut w = BitWriter::new();
+        w.u(3, 0b101).unwrap();
+        w.rbsp_trailing_bits();
+        assert_eq!(w.rbsp(), &[0b1011_0000]);
+        w.u(8, 0x80).unwrap();
+        w.rbsp_trailing_bits();
+        assert_eq!(w.rbsp(), &[0b1011_0000, 0x80, 0x80]);
+
+        let mut w = BitWriter::new();
+        w.u(8, 0).unwrap();
+        w.u(8, 0).unwrap();
+        w.rbsp_slice_trailing_bits(2);
+        assert_eq!(w.rbsp(), &[0, 0, 0x80, 0, 0, 0, 0]);
+        // 00 00 80 needs no escape; the cabac_zero_words do, and end with a final 0x03
+        assert_eq!(w.to_nal_payload(), &[0, 0, 0x80, 0, 0, 3, 0, 0, 3]);
+    }
+}
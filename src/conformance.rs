@@ -0,0 +1,730 @@
+//! Profile and level conformance, Annex A: the Table A-1 limits of the signalled level and
+//! the A.2 constraints of the signalled profile, checked against the parameter sets and slices
+//! of a stream.
+
+use std::fmt;
+
+use crate::annexb;
+use crate::nal::{NalHeader, NalUnitType};
+use crate::parameter_sets::{ParameterSetKind, ParameterSets};
+use crate::pps::Pps;
+use crate::slice::{self, SliceType};
+use crate::sps::Sps;
+use crate::syntax::ParseError;
+use crate::vui::HrdParameters;
+
+/// A limit or constraint the stream does not stay within.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    /// Offset of the NAL unit showing the violation, None when checking a lone parameter set.
+    pub position: Option<usize>,
+    /// The Table A-1 limit, e.g. "MaxFS", or the profile whose constraint is not met.
+    pub rule: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(position) = self.position {
+            write!(f, "@{}: ", position)?;
+        }
+        write!(f, "{}: {}", self.rule, self.message)
+    }
+}
+
+/// The limits of one level, Table A-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelLimits {
+    pub level: &'static str,
+    /// MaxMBPS, macroblocks per second.
+    pub max_mbps: u64,
+    /// MaxFS, macroblocks.
+    pub max_fs: u64,
+    /// MaxBR, in units of cpbBrVclFactor or cpbBrNalFactor bits/s.
+    pub max_br: u64,
+    /// MaxCPB, in units of cpbBrVclFactor or cpbBrNalFactor bits.
+    pub max_cpb: u64,
+}
+
+const fn limits(
+    level: &'static str,
+    max_mbps: u64,
+    max_fs: u64,
+    max_br: u64,
+    max_cpb: u64,
+) -> LevelLimits {
+    LevelLimits {
+        level,
+        max_mbps,
+        max_fs,
+        max_br,
+        max_cpb,
+    }
+}
+
+/// Table A-1 by level_idc, level 1b under 9. MaxDpbMbs is applied by `Sps::max_dpb_frames`.
+const LEVELS: [(u8, LevelLimits); 20] = [
+    (10, limits("1", 1485, 99, 64, 175)),
+    (9, limits("1b", 1485, 99, 128, 350)),
+    (11, limits("1.1", 3000, 396, 192, 500)),
+    (12, limits("1.2", 6000, 396, 384, 1000)),
+    (13, limits("1.3", 11880, 396, 768, 2000)),
+    (20, limits("2", 11880, 396, 2000, 2000)),
+    (21, limits("2.1", 19800, 792, 4000, 4000)),
+    (22, limits("2.2", 20250, 1620, 4000, 4000)),
+    (30, limits("3", 40500, 1620, 10000, 10000)),
+    (31, limits("3.1", 108000, 3600, 14000, 14000)),
+    (32, limits("3.2", 216000, 5120, 20000, 20000)),
+    (40, limits("4", 245760, 8192, 20000, 25000)),
+    (41, limits("4.1", 245760, 8192, 50000, 62500)),
+    (42, limits("4.2", 522240, 8704, 50000, 62500)),
+    (50, limits("5", 589824, 22080, 135000, 135000)),
+    (51, limits("5.1", 983040, 36864, 240000, 240000)),
+    (52, limits("5.2", 2073600, 36864, 240000, 240000)),
+    (60, limits("6", 4177920, 139264, 240000, 240000)),
+    (61, limits("6.1", 8355840, 139264, 480000, 480000)),
+    (62, limits("6.2", 16711680, 139264, 800000, 800000)),
+];
+
+/// The limits of the level the SPS signals, None for an unknown level_idc. Level 1b is found
+/// under either of its level_idc, see `Sps::effective_level_idc`.
+pub fn level_limits(sps: &Sps) -> Option<&'static LevelLimits> {
+    let level_idc = sps.effective_level_idc();
+    LEVELS
+        .iter()
+        .find(|(idc, _)| *idc == level_idc)
+        .map(|(_, limits)| limits)
+}
+
+/// cpbBrVclFactor and cpbBrNalFactor of a profile, Table A-2.
+pub fn cpb_br_factors(profile_idc: u8) -> (u64, u64) {
+    match profile_idc {
+        100 => (1250, 1500),
+        110 => (3000, 3600),
+        122 | 244 | 44 => (4000, 4800),
+        _ => (1000, 1200),
+    }
+}
+
+/// The A.2 constraints of one profile.
+#[derive(Debug, Clone, Copy)]
+struct ProfileConstraints {
+    name: &'static str,
+    max_chroma_format_idc: u32,
+    max_bit_depth: u32,
+    field_coding: bool,
+    direct_8x8_inference_required: bool,
+    b_slices: bool,
+    switching_slices: bool,
+    intra_only: bool,
+    cabac: bool,
+    weighted_prediction: bool,
+    slice_groups: bool,
+    redundant_pictures: bool,
+    data_partitioning: bool,
+    transform_8x8: bool,
+}
+
+const BASELINE: ProfileConstraints = ProfileConstraints {
+    name: "Baseline",
+    max_chroma_format_idc: 1,
+    max_bit_depth: 8,
+    field_coding: false,
+    direct_8x8_inference_required: false,
+    b_slices: false,
+    switching_slices: false,
+    intra_only: false,
+    cabac: false,
+    weighted_prediction: false,
+    slice_groups: true,
+    redundant_pictures: true,
+    data_partitioning: false,
+    transform_8x8: false,
+};
+
+const MAIN: ProfileConstraints = ProfileConstraints {
+    name: "Main",
+    field_coding: true,
+    b_slices: true,
+    cabac: true,
+    weighted_prediction: true,
+    slice_groups: false,
+    redundant_pictures: false,
+    ..BASELINE
+};
+
+const EXTENDED: ProfileConstraints = ProfileConstraints {
+    name: "Extended",
+    field_coding: true,
+    direct_8x8_inference_required: true,
+    b_slices: true,
+    switching_slices: true,
+    weighted_prediction: true,
+    data_partitioning: true,
+    ..BASELINE
+};
+
+const HIGH: ProfileConstraints = ProfileConstraints {
+    name: "High",
+    transform_8x8: true,
+    ..MAIN
+};
+
+/// The constraints of profile_idc, with constraint_set3_flag selecting the intra profiles.
+fn profile_constraints(profile_idc: u8, constraint_set3_flag: bool) -> Option<ProfileConstraints> {
+    let intra = constraint_set3_flag;
+    let constraints = match profile_idc {
+        66 => BASELINE,
+        77 => MAIN,
+        88 => EXTENDED,
+        100 => HIGH,
+        110 => ProfileConstraints {
+            name: if intra { "High 10 Intra" } else { "High 10" },
+            max_bit_depth: 10,
+            intra_only: intra,
+            ..HIGH
+        },
+        122 => ProfileConstraints {
+            name: if intra {
+                "High 4:2:2 Intra"
+            } else {
+                "High 4:2:2"
+            },
+            max_chroma_format_idc: 2,
+            max_bit_depth: 10,
+            intra_only: intra,
+            ..HIGH
+        },
+        244 => ProfileConstraints {
+            name: if intra {
+                "High 4:4:4 Intra"
+            } else {
+                "High 4:4:4 Predictive"
+            },
+            max_chroma_format_idc: 3,
+            max_bit_depth: 14,
+            intra_only: intra,
+            ..HIGH
+        },
+        44 => ProfileConstraints {
+            name: "CAVLC 4:4:4 Intra",
+            max_chroma_format_idc: 3,
+            max_bit_depth: 14,
+            intra_only: true,
+            cabac: false,
+            ..HIGH
+        },
+        _ => return None,
+    };
+    Some(constraints)
+}
+
+/// Every profile the SPS claims conformance to: the one of profile_idc, then Baseline, Main
+/// and Extended for constraint_set0_flag to constraint_set2_flag.
+///
+/// constraint_set4_flag (progressive) and constraint_set5_flag (no B slices) tighten the
+/// profile of profile_idc where A.2 defines them.
+fn claimed_profiles(sps: &Sps) -> Vec<ProfileConstraints> {
+    let mut profiles = Vec::new();
+    if let Some(mut profile) = profile_constraints(sps.profile_idc, sps.constraint_set3_flag) {
+        if sps.constraint_set4_flag && matches!(sps.profile_idc, 77 | 88 | 100 | 110 | 122) {
+            profile.field_coding = false;
+        }
+        if sps.constraint_set5_flag && matches!(sps.profile_idc, 77 | 88 | 100) {
+            profile.b_slices = false;
+        }
+        profiles.push(profile);
+    }
+    for (flag, constraints) in [
+        (sps.constraint_set0_flag, BASELINE),
+        (sps.constraint_set1_flag, MAIN),
+        (sps.constraint_set2_flag, EXTENDED),
+    ] {
+        if flag && profiles.iter().all(|p| p.name != constraints.name) {
+            profiles.push(constraints);
+        }
+    }
+    profiles
+}
+
+fn violation(rule: &'static str, message: String) -> Violation {
+    Violation {
+        position: None,
+        rule,
+        message,
+    }
+}
+
+fn check_hrd(
+    hrd: &HrdParameters,
+    kind: &str,
+    factor: u64,
+    limits: &LevelLimits,
+    violations: &mut Vec<Violation>,
+) {
+    for i in 0..hrd.cpbs.len() {
+        let bit_rate = hrd.bit_rate(i).unwrap_or(0);
+        if bit_rate > limits.max_br * factor {
+            violations.push(violation(
+                "MaxBR",
+                format!(
+                    "{} HRD BitRate[{}] {} exceeds {} bit/s of level {}",
+                    kind,
+                    i,
+                    bit_rate,
+                    limits.max_br * factor,
+                    limits.level
+                ),
+            ));
+        }
+        let cpb_size = hrd.cpb_size(i).unwrap_or(0);
+        if cpb_size > limits.max_cpb * factor {
+            violations.push(violation(
+                "MaxCPB",
+                format!(
+                    "{} HRD CpbSize[{}] {} exceeds {} bits of level {}",
+                    kind,
+                    i,
+                    cpb_size,
+                    limits.max_cpb * factor,
+                    limits.level
+                ),
+            ));
+        }
+    }
+}
+
+/// Checks the SPS against the Table A-1 limits of its level: frame size, DPB size, macroblock
+/// rate from the VUI timing, and bit rate and CPB size from the HRD parameters.
+pub fn check_level(sps: &Sps) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let Some(limits) = level_limits(sps) else {
+        violations.push(violation(
+            "level_idc",
+            format!("unknown level_idc {}", sps.level_idc),
+        ));
+        return violations;
+    };
+
+    let width = sps.pic_width_in_mbs() as u64;
+    let height = sps.frame_height_in_mbs() as u64;
+    let frame_size = width * height;
+    if frame_size > limits.max_fs {
+        violations.push(violation(
+            "MaxFS",
+            format!(
+                "frame size {} MBs exceeds MaxFS {} of level {}",
+                frame_size, limits.max_fs, limits.level
+            ),
+        ));
+    }
+    for (name, mbs) in [("PicWidthInMbs", width), ("FrameHeightInMbs", height)] {
+        if mbs * mbs > limits.max_fs * 8 {
+            violations.push(violation(
+                "MaxFS",
+                format!(
+                    "{} {} exceeds Sqrt(MaxFS * 8) of level {}",
+                    name, mbs, limits.level
+                ),
+            ));
+        }
+    }
+
+    // known whenever the level is
+    let max_dpb_frames = sps.max_dpb_frames().unwrap_or(16) as u64;
+    if sps.max_num_ref_frames as u64 > max_dpb_frames {
+        violations.push(violation(
+            "MaxDpbMbs",
+            format!(
+                "max_num_ref_frames {} exceeds MaxDpbFrames {} of level {}",
+                sps.max_num_ref_frames, max_dpb_frames, limits.level
+            ),
+        ));
+    }
+
+    let Some(vui) = &sps.vui_parameters else {
+        return violations;
+    };
+    if vui.bitstream_restriction_flag && vui.max_dec_frame_buffering as u64 > max_dpb_frames {
+        violations.push(violation(
+            "max_dec_frame_buffering",
+            format!(
+                "max_dec_frame_buffering {} exceeds MaxDpbFrames {} of level {}",
+                vui.max_dec_frame_buffering, max_dpb_frames, limits.level
+            ),
+        ));
+    }
+    if let Some(frame_rate) = vui.frame_rate() {
+        let mbps = frame_rate * frame_size as f64;
+        if mbps > limits.max_mbps as f64 {
+            violations.push(violation(
+                "MaxMBPS",
+                format!(
+                    "{} MBs at {:.3} frames/s exceed MaxMBPS {} of level {}",
+                    frame_size, frame_rate, limits.max_mbps, limits.level
+                ),
+            ));
+        }
+    }
+    let (vcl_factor, nal_factor) = cpb_br_factors(sps.profile_idc);
+    if let Some(hrd) = &vui.nal_hrd_parameters {
+        check_hrd(hrd, "NAL", nal_factor, limits, &mut violations);
+    }
+    if let Some(hrd) = &vui.vcl_hrd_parameters {
+        check_hrd(hrd, "VCL", vcl_factor, limits, &mut violations);
+    }
+    violations
+}
+
+/// Checks the SPS against the limits of its level and the constraints of every profile it
+/// claims conformance to.
+pub fn check_sps(sps: &Sps) -> Vec<Violation> {
+    let mut violations = check_level(sps);
+    for profile in claimed_profiles(sps) {
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                violations.push(violation(profile.name, message));
+            }
+        };
+        check(
+            sps.chroma_format_idc <= profile.max_chroma_format_idc,
+            format!("chroma_format_idc {} not allowed", sps.chroma_format_idc),
+        );
+        check(
+            sps.bit_depth_luma().max(sps.bit_depth_chroma()) <= profile.max_bit_depth,
+            format!(
+                "bit depth {}/{} exceeds {}",
+                sps.bit_depth_luma(),
+                sps.bit_depth_chroma(),
+                profile.max_bit_depth
+            ),
+        );
+        check(
+            profile.field_coding || sps.frame_mbs_only_flag,
+            "field coding not allowed (frame_mbs_only_flag 0)".to_string(),
+        );
+        check(
+            !profile.direct_8x8_inference_required || sps.direct_8x8_inference_flag,
+            "direct_8x8_inference_flag 0 not allowed".to_string(),
+        );
+    }
+    violations
+}
+
+/// Checks the PPS against the constraints of every profile its SPS claims conformance to.
+pub fn check_pps(sps: &Sps, pps: &Pps) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    for profile in claimed_profiles(sps) {
+        let mut check = |ok: bool, message: String| {
+            if !ok {
+                violations.push(violation(profile.name, message));
+            }
+        };
+        check(
+            profile.cabac || !pps.entropy_coding_mode_flag,
+            "CABAC not allowed (entropy_coding_mode_flag 1)".to_string(),
+        );
+        check(
+            profile.weighted_prediction
+                || (!pps.weighted_pred_flag && pps.weighted_bipred_idc == 0),
+            "weighted prediction not allowed".to_string(),
+        );
+        let max_slice_groups_minus1 = if profile.slice_groups { 7 } else { 0 };
+        check(
+            pps.num_slice_groups_minus1 <= max_slice_groups_minus1,
+            format!("{} slice groups not allowed", pps.num_slice_groups()),
+        );
+        check(
+            profile.redundant_pictures || !pps.redundant_pic_cnt_present_flag,
+            "redundant pictures not allowed (redundant_pic_cnt_present_flag 1)".to_string(),
+        );
+        check(
+            profile.transform_8x8
+                || (!pps.transform_8x8_mode_flag && !pps.pic_scaling_matrix_present_flag),
+            "8x8 transform and scaling matrices not allowed".to_string(),
+        );
+    }
+    violations
+}
+
+/// Checks the NAL units of a stream in order, each parameter set when a slice activates it and
+/// every slice against the profiles of the active SPS.
+///
+/// A violation is reported once, at the first NAL unit showing it.
+#[derive(Debug, Default)]
+pub struct ConformanceChecker {
+    parameter_sets: ParameterSets,
+    violations: Vec<Violation>,
+}
+
+impl ConformanceChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn report(&mut self, found: Vec<Violation>, position: usize) {
+        for v in found {
+            if !self
+                .violations
+                .iter()
+                .any(|r| r.rule == v.rule && r.message == v.message)
+            {
+                self.violations.push(Violation {
+                    position: Some(position),
+                    ..v
+                });
+            }
+        }
+    }
+
+    fn check_slice(
+        &mut self,
+        nal: &[u8],
+        header: &NalHeader,
+        position: usize,
+    ) -> Result<(), ParseError> {
+        let (first_mb_in_slice, slice_type, pic_parameter_set_id) = slice::slice_ids(nal)?;
+        let idr_pic = header.nal_unit_type == NalUnitType::SliceIdr;
+
+        let activated = self.parameter_sets.activations().len();
+        self.parameter_sets.activate(
+            pic_parameter_set_id,
+            idr_pic,
+            first_mb_in_slice == 0,
+            position,
+        )?;
+        let sets = &self.parameter_sets;
+        let (sps, pps) = sets.resolve(pic_parameter_set_id)?;
+        let mut found = Vec::new();
+        for activation in &sets.activations()[activated..] {
+            match activation.kind {
+                ParameterSetKind::Sps => found.extend(check_sps(sps)),
+                ParameterSetKind::Pps => found.extend(check_pps(sps, pps)),
+            }
+        }
+        for profile in claimed_profiles(sps) {
+            let allowed = match slice_type {
+                _ if profile.intra_only => slice_type == SliceType::I && idr_pic,
+                SliceType::I | SliceType::P => true,
+                SliceType::B => profile.b_slices,
+                SliceType::Sp | SliceType::Si => profile.switching_slices,
+            };
+            if !allowed {
+                let message = if profile.intra_only {
+                    "only IDR pictures with I slices allowed".to_string()
+                } else {
+                    format!("{} slices not allowed", slice_type.name())
+                };
+                found.push(violation(profile.name, message));
+            }
+            if header.nal_unit_type == NalUnitType::SliceDataPartitionA
+                && !profile.data_partitioning
+            {
+                found.push(violation(
+                    profile.name,
+                    "data partitioning not allowed".to_string(),
+                ));
+            }
+        }
+        self.report(found, position);
+        Ok(())
+    }
+
+    /// Checks the NAL unit at `position`, given without its start code.
+    pub fn process_nal(&mut self, nal: &[u8], position: usize) -> Result<(), ParseError> {
+        let Some(header) = NalHeader::parse(nal) else {
+            return Ok(());
+        };
+        match header.nal_unit_type {
+            NalUnitType::SliceNonIdr | NalUnitType::SliceIdr | NalUnitType::SliceDataPartitionA => {
+                self.check_slice(nal, &header, position)
+            }
+            _ => self.parameter_sets.process_nal(nal, position),
+        }
+    }
+
+    /// The violations found so far, in stream order.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    pub fn into_violations(self) -> Vec<Violation> {
+        self.violations
+    }
+}
+
+/// Checks every NAL unit of an Annex B stream. NAL units that cannot be parsed are reported as
+/// "syntax" violations.
+pub fn check_stream(data: &[u8]) -> Vec<Violation> {
+    let mut checker = ConformanceChecker::new();
+    let mut errors = Vec::new();
+    for nal in annexb::nal_units(data) {
+        if let Err(err) = checker.process_nal(&data[nal.start..nal.end], nal.start) {
+            errors.push(Violation {
+                position: Some(nal.start),
+                rule: "syntax",
+                message: err.to_string(),
+            });
+        }
+    }
+    let mut violations = checker.into_violations();
+    violations.extend(errors);
+    violations.sort_by_key(|v| v.position);
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulation;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+    use crate::vui::CpbSpec;
+
+    fn high_sps() -> Sps {
+        Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap()
+    }
+
+    #[test]
+    fn test_level_limits() {
+        let sps = high_sps();
+        assert_eq!(level_limits(&sps).unwrap().level, "4");
+        assert_eq!(check_sps(&sps), []);
+
+        let level_1b = Sps {
+            profile_idc: 66,
+            level_idc: 11,
+            constraint_set3_flag: true,
+            ..sps.clone()
+        };
+        assert_eq!(level_limits(&level_1b).unwrap().level, "1b");
+        let level_11 = Sps {
+            profile_idc: 100,
+            ..level_1b
+        };
+        assert_eq!(level_limits(&level_11).unwrap().level, "1.1");
+
+        // 1920x1080 at 30 frames/s is too much for level 3
+        let level_3 = Sps {
+            level_idc: 30,
+            ..sps.clone()
+        };
+        let rules: Vec<_> = check_level(&level_3).iter().map(|v| v.rule).collect();
+        assert_eq!(
+            rules,
+            [
+                "MaxFS",
+                "MaxFS",
+                "MaxDpbMbs",
+                "max_dec_frame_buffering",
+                "MaxMBPS"
+            ]
+        );
+        assert_eq!(
+            check_level(&level_3)[0].to_string(),
+            "MaxFS: frame size 8160 MBs exceeds MaxFS 1620 of level 3"
+        );
+        let unknown = Sps {
+            level_idc: 33,
+            ..sps
+        };
+        assert_eq!(check_level(&unknown)[0].rule, "level_idc");
+    }
+
+    #[test]
+    fn test_hrd_limits() {
+        let mut sps = high_sps();
+        let vui = sps.vui_parameters.as_mut().unwrap();
+        // 25 Mbit/s is within 1250 * 20000 bit/s for VCL, 30 Mbit/s is not
+        let hrd = HrdParameters {
+            bit_rate_scale: 0,
+            cpb_size_scale: 0,
+            cpbs: vec![
+                CpbSpec {
+                    bit_rate_value_minus1: 25_000_000 / 64 - 1,
+                    cpb_size_value_minus1: 1000,
+                    cbr_flag: false,
+                },
+                CpbSpec {
+                    bit_rate_value_minus1: 30_000_000 / 64 - 1,
+                    cpb_size_value_minus1: 40_000_000 / 16 - 1,
+                    cbr_flag: true,
+                },
+            ],
+            initial_cpb_removal_delay_length_minus1: 23,
+            cpb_removal_delay_length_minus1: 23,
+            dpb_output_delay_length_minus1: 23,
+            time_offset_length: 24,
+        };
+        vui.vcl_hrd_parameters = Some(hrd.clone());
+        vui.nal_hrd_parameters = Some(hrd);
+        let messages: Vec<_> = check_sps(&sps).iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "MaxCPB: NAL HRD CpbSize[1] 40000000 exceeds 37500000 bits of level 4",
+                "MaxBR: VCL HRD BitRate[1] 30000000 exceeds 25000000 bit/s of level 4",
+                "MaxCPB: VCL HRD CpbSize[1] 40000000 exceeds 31250000 bits of level 4",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_profile_constraints() {
+        let baseline = Sps {
+            profile_idc: 66,
+            constraint_set1_flag: true,
+            ..high_sps()
+        };
+        let mut checker = ConformanceChecker::new();
+        checker.parameter_sets.insert_sps(baseline, 0).unwrap();
+        checker.process_nal(&CABAC_PPS, 30).unwrap();
+        // IDR I slice, then two B slices
+        checker.process_nal(&[0x65, 0x88, 0x84], 40).unwrap();
+        checker.process_nal(&[0x41, 0x9e], 50).unwrap();
+        checker.process_nal(&[0x41, 0x9e], 60).unwrap();
+        let messages: Vec<_> = checker.violations().iter().map(|v| v.to_string()).collect();
+        assert_eq!(
+            messages,
+            [
+                "@40: Baseline: CABAC not allowed (entropy_coding_mode_flag 1)",
+                "@40: Baseline: weighted prediction not allowed",
+                "@40: Baseline: 8x8 transform and scaling matrices not allowed",
+                "@40: Main: 8x8 transform and scaling matrices not allowed",
+                "@50: Baseline: B slices not allowed",
+            ]
+        );
+
+        let intra = Sps {
+            profile_idc: 110,
+            constraint_set3_flag: true,
+            bit_depth_luma_minus8: 4,
+            ..high_sps()
+        };
+        let messages: Vec<_> = check_sps(&intra).iter().map(|v| v.to_string()).collect();
+        assert_eq!(messages, ["High 10 Intra: bit depth 12/8 exceeds 10"]);
+    }
+
+    #[test]
+    fn test_check_stream() {
+        let mut data = Vec::new();
+        for nal in [
+            &[0x65, 0x88, 0x84][..],
+            &HIGH_SPS,
+            &CABAC_PPS,
+            &[0x65, 0x88, 0x84],
+        ] {
+            data.extend([0, 0, 0, 1]);
+            data.extend(nal);
+        }
+        let violations = check_stream(&data);
+        assert_eq!(
+            violations,
+            [Violation {
+                position: Some(4),
+                rule: "syntax",
+                message: ParseError::MissingPps(0).to_string()
+            }]
+        );
+    }
+}
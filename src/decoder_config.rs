@@ -0,0 +1,737 @@
+//! Decoder configuration records of ISO/IEC 14496-15, the avcC (5.3.3.1) and hvcC (8.3.3.1)
+//! boxes of MP4 sample entries, and the RFC 6381 codecs parameter derived from them.
+
+use std::fmt;
+
+use crate::annexb;
+use crate::emulation;
+use crate::nal::{HevcNalHeader, HevcNalUnitType, NalUnitType};
+use crate::sps::Sps;
+use crate::syntax::{ParseError, SyntaxReader};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigRecordError {
+    /// A field or NAL unit runs past the end of the record.
+    Truncated { offset: usize },
+    /// configurationVersion is not 1.
+    UnsupportedVersion(u8),
+    /// The record would have no SPS to take the profile and level from.
+    MissingSps,
+    /// More NAL units of one type than the count field can describe.
+    TooManyNalUnits { nal_unit_type: u8, count: usize },
+    /// A NAL unit longer than its 16 bit length field allows.
+    NalTooLong(usize),
+    /// The SPS the record is built from does not parse.
+    Syntax(ParseError),
+}
+
+impl fmt::Display for ConfigRecordError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigRecordError::Truncated { offset } => {
+                write!(
+                    f,
+                    "decoder configuration record truncated at offset {}",
+                    offset
+                )
+            }
+            ConfigRecordError::UnsupportedVersion(version) => {
+                write!(f, "unsupported configurationVersion {}", version)
+            }
+            ConfigRecordError::MissingSps => write!(f, "no SPS available"),
+            ConfigRecordError::TooManyNalUnits {
+                nal_unit_type,
+                count,
+            } => write!(
+                f,
+                "{} NAL units of type {} do not fit the record",
+                count, nal_unit_type
+            ),
+            ConfigRecordError::NalTooLong(len) => write!(
+                f,
+                "NAL unit of {} bytes does not fit a 2 byte length field",
+                len
+            ),
+            ConfigRecordError::Syntax(err) => write!(f, "invalid SPS: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ConfigRecordError {}
+
+impl From<ParseError> for ConfigRecordError {
+    fn from(err: ParseError) -> Self {
+        ConfigRecordError::Syntax(err)
+    }
+}
+
+/// Reads the big-endian fields of a record.
+struct RecordReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> RecordReader<'a> {
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], ConfigRecordError> {
+        let end = self.pos + n;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(ConfigRecordError::Truncated { offset: self.pos })?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self, n: usize) -> Result<u64, ConfigRecordError> {
+        Ok(self
+            .bytes(n)?
+            .iter()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64))
+    }
+
+    fn u8(&mut self) -> Result<u8, ConfigRecordError> {
+        Ok(self.uint(1)? as u8)
+    }
+
+    fn u16(&mut self) -> Result<u16, ConfigRecordError> {
+        Ok(self.uint(2)? as u16)
+    }
+
+    fn nal_units(&mut self, count: usize) -> Result<Vec<Vec<u8>>, ConfigRecordError> {
+        (0..count)
+            .map(|_| {
+                let len = self.u16()? as usize;
+                Ok(self.bytes(len)?.to_vec())
+            })
+            .collect()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+fn write_nal_units(out: &mut Vec<u8>, nals: &[Vec<u8>]) -> Result<(), ConfigRecordError> {
+    for nal in nals {
+        let len = u16::try_from(nal.len()).map_err(|_| ConfigRecordError::NalTooLong(nal.len()))?;
+        out.extend(len.to_be_bytes());
+        out.extend(nal);
+    }
+    Ok(())
+}
+
+fn count(nals: &[Vec<u8>], nal_unit_type: u8, max: usize) -> Result<usize, ConfigRecordError> {
+    if nals.len() > max {
+        return Err(ConfigRecordError::TooManyNalUnits {
+            nal_unit_type,
+            count: nals.len(),
+        });
+    }
+    Ok(nals.len())
+}
+
+/// Appends `nal` unless an identical NAL unit is already there.
+fn push_distinct(nals: &mut Vec<Vec<u8>>, nal: &[u8]) {
+    if !nals.iter().any(|n| n == nal) {
+        nals.push(nal.to_vec());
+    }
+}
+
+/// The RFC 6381 codecs parameter of an H.264 SPS: avc1.PPCCLL, profile_idc, the constraint
+/// flags byte and level_idc in hex.
+pub fn avc_codec_string(sps: &Sps) -> String {
+    format!(
+        "avc1.{:02X}{:02X}{:02X}",
+        sps.profile_idc,
+        profile_compatibility(sps),
+        sps.level_idc
+    )
+}
+
+/// The byte of the SPS holding constraint_set0_flag to reserved_zero_2bits.
+fn profile_compatibility(sps: &Sps) -> u8 {
+    [
+        sps.constraint_set0_flag,
+        sps.constraint_set1_flag,
+        sps.constraint_set2_flag,
+        sps.constraint_set3_flag,
+        sps.constraint_set4_flag,
+        sps.constraint_set5_flag,
+    ]
+    .iter()
+    .fold(0, |acc, &flag| (acc << 1) | flag as u8)
+        << 2
+        | sps.reserved_zero_2bits
+}
+
+/// Extra fields of the avcC record for the High profiles with chroma formats and bit depths
+/// other than 4:2:0 8 bit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcHighProfileFields {
+    pub chroma_format: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub sequence_parameter_set_ext: Vec<Vec<u8>>,
+}
+
+/// AVCDecoderConfigurationRecord. NAL units are kept with their header and emulation
+/// prevention bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvcDecoderConfigurationRecord {
+    pub avc_profile_indication: u8,
+    pub profile_compatibility: u8,
+    pub avc_level_indication: u8,
+    pub length_size_minus_one: u8,
+    pub sequence_parameter_sets: Vec<Vec<u8>>,
+    pub picture_parameter_sets: Vec<Vec<u8>>,
+    /// Present for profile_idc 100, 110, 122 and 144.
+    pub high_profile_fields: Option<AvcHighProfileFields>,
+}
+
+fn has_high_profile_fields(profile_idc: u8) -> bool {
+    matches!(profile_idc, 100 | 110 | 122 | 144)
+}
+
+impl AvcDecoderConfigurationRecord {
+    /// Builds the record from SPS, PPS and SPS extension NAL units, taking the profile, level
+    /// and chroma fields from the first SPS. lengthSizeMinusOne is 3.
+    pub fn from_parameter_sets(
+        sps: Vec<Vec<u8>>,
+        pps: Vec<Vec<u8>>,
+        sps_ext: Vec<Vec<u8>>,
+    ) -> Result<Self, ConfigRecordError> {
+        let first = sps.first().ok_or(ConfigRecordError::MissingSps)?;
+        let parsed = Sps::from_rbsp(&emulation::decode(first))?;
+        let high_profile_fields =
+            has_high_profile_fields(parsed.profile_idc).then_some(AvcHighProfileFields {
+                chroma_format: parsed.chroma_format_idc as u8,
+                bit_depth_luma_minus8: parsed.bit_depth_luma_minus8 as u8,
+                bit_depth_chroma_minus8: parsed.bit_depth_chroma_minus8 as u8,
+                sequence_parameter_set_ext: sps_ext,
+            });
+        Ok(AvcDecoderConfigurationRecord {
+            avc_profile_indication: parsed.profile_idc,
+            profile_compatibility: profile_compatibility(&parsed),
+            avc_level_indication: parsed.level_idc,
+            length_size_minus_one: 3,
+            sequence_parameter_sets: sps,
+            picture_parameter_sets: pps,
+            high_profile_fields,
+        })
+    }
+
+    /// Builds the record from the SPS, PPS and SPS extension NAL units of an Annex B stream,
+    /// each distinct NAL unit once, in stream order.
+    pub fn from_stream(data: &[u8]) -> Result<Self, ConfigRecordError> {
+        let (mut sps, mut pps, mut sps_ext) = (Vec::new(), Vec::new(), Vec::new());
+        for nal in annexb::nal_units(data) {
+            let bytes = &data[nal.start..nal.end];
+            match nal.header.nal_unit_type {
+                NalUnitType::Sps => push_distinct(&mut sps, bytes),
+                NalUnitType::Pps => push_distinct(&mut pps, bytes),
+                NalUnitType::SpsExtension => push_distinct(&mut sps_ext, bytes),
+                _ => {}
+            }
+        }
+        Self::from_parameter_sets(sps, pps, sps_ext)
+    }
+
+    /// Parses an avcC box payload. The High profile fields are optional, as some writers leave
+    /// them out.
+    pub fn parse(data: &[u8]) -> Result<Self, ConfigRecordError> {
+        let mut r = RecordReader { data, pos: 0 };
+        let version = r.u8()?;
+        if version != 1 {
+            return Err(ConfigRecordError::UnsupportedVersion(version));
+        }
+        let avc_profile_indication = r.u8()?;
+        let profile_compatibility = r.u8()?;
+        let avc_level_indication = r.u8()?;
+        let length_size_minus_one = r.u8()? & 0x03;
+        let num_sps = (r.u8()? & 0x1f) as usize;
+        let sequence_parameter_sets = r.nal_units(num_sps)?;
+        let num_pps = r.u8()? as usize;
+        let picture_parameter_sets = r.nal_units(num_pps)?;
+        let mut high_profile_fields = None;
+        if has_high_profile_fields(avc_profile_indication) && !r.at_end() {
+            let chroma_format = r.u8()? & 0x03;
+            let bit_depth_luma_minus8 = r.u8()? & 0x07;
+            let bit_depth_chroma_minus8 = r.u8()? & 0x07;
+            let num_sps_ext = r.u8()? as usize;
+            high_profile_fields = Some(AvcHighProfileFields {
+                chroma_format,
+                bit_depth_luma_minus8,
+                bit_depth_chroma_minus8,
+                sequence_parameter_set_ext: r.nal_units(num_sps_ext)?,
+            });
+        }
+        Ok(AvcDecoderConfigurationRecord {
+            avc_profile_indication,
+            profile_compatibility,
+            avc_level_indication,
+            length_size_minus_one,
+            sequence_parameter_sets,
+            picture_parameter_sets,
+            high_profile_fields,
+        })
+    }
+
+    /// The avcC box payload.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConfigRecordError> {
+        let mut out = vec![
+            1,
+            self.avc_profile_indication,
+            self.profile_compatibility,
+            self.avc_level_indication,
+            0xfc | self.length_size_minus_one,
+        ];
+        out.push(0xe0 | count(&self.sequence_parameter_sets, 7, 31)? as u8);
+        write_nal_units(&mut out, &self.sequence_parameter_sets)?;
+        out.push(count(&self.picture_parameter_sets, 8, 255)? as u8);
+        write_nal_units(&mut out, &self.picture_parameter_sets)?;
+        if let Some(high) = &self.high_profile_fields {
+            out.push(0xfc | high.chroma_format);
+            out.push(0xf8 | high.bit_depth_luma_minus8);
+            out.push(0xf8 | high.bit_depth_chroma_minus8);
+            out.push(count(&high.sequence_parameter_set_ext, 13, 255)? as u8);
+            write_nal_units(&mut out, &high.sequence_parameter_set_ext)?;
+        }
+        Ok(out)
+    }
+
+    /// The RFC 6381 codecs parameter, avc1.PPCCLL. Sample entries carrying parameter sets in
+    /// band use avc3 instead.
+    pub fn codec_string(&self) -> String {
+        format!(
+            "avc1.{:02X}{:02X}{:02X}",
+            self.avc_profile_indication, self.profile_compatibility, self.avc_level_indication
+        )
+    }
+}
+
+/// The general part of profile_tier_level() of H.265 (7.3.3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HevcProfileTierLevel {
+    pub general_profile_space: u8,
+    pub general_tier_flag: bool,
+    pub general_profile_idc: u8,
+    pub general_profile_compatibility_flags: u32,
+    /// general_progressive_source_flag to general_inbld_flag/reserved bit, 48 bits.
+    pub general_constraint_indicator_flags: u64,
+    pub general_level_idc: u8,
+}
+
+impl HevcProfileTierLevel {
+    /// The RFC 6381 codecs parameter as defined in ISO/IEC 14496-15 E.3, e.g. hvc1.1.6.L93.B0.
+    /// Sample entries carrying parameter sets in band use hev1 instead.
+    pub fn codec_string(&self) -> String {
+        let profile_space = ["", "A", "B", "C"][self.general_profile_space as usize & 3];
+        let mut codec = format!(
+            "hvc1.{}{}.{:X}.{}{}",
+            profile_space,
+            self.general_profile_idc,
+            self.general_profile_compatibility_flags.reverse_bits(),
+            if self.general_tier_flag { 'H' } else { 'L' },
+            self.general_level_idc
+        );
+        let constraints = &self.general_constraint_indicator_flags.to_be_bytes()[2..];
+        let used = constraints
+            .iter()
+            .rposition(|&b| b != 0)
+            .map_or(0, |i| i + 1);
+        for byte in &constraints[..used] {
+            codec.push_str(&format!(".{:X}", byte));
+        }
+        codec
+    }
+}
+
+/// The fields of an H.265 SPS up to the bit depths (7.3.2.2), what the hvcC record needs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HevcSpsHeader {
+    pub sps_max_sub_layers_minus1: u8,
+    pub sps_temporal_id_nesting_flag: bool,
+    pub profile_tier_level: HevcProfileTierLevel,
+    pub sps_seq_parameter_set_id: u32,
+    pub chroma_format_idc: u32,
+    pub bit_depth_luma_minus8: u32,
+    pub bit_depth_chroma_minus8: u32,
+}
+
+impl HevcSpsHeader {
+    /// Parses the SPS NAL unit, given with its two byte header.
+    pub fn from_nal(nal: &[u8]) -> Result<Self, ParseError> {
+        let rbsp = emulation::decode(nal);
+        let mut s = SyntaxReader::new(&rbsp);
+        s.skip_to(16);
+        s.u("sps_video_parameter_set_id", 4)?;
+        let sps_max_sub_layers_minus1 = s.u("sps_max_sub_layers_minus1", 3)? as u8;
+        let sps_temporal_id_nesting_flag = s.flag("sps_temporal_id_nesting_flag")?;
+
+        let general_profile_space = s.u("general_profile_space", 2)? as u8;
+        let general_tier_flag = s.flag("general_tier_flag")?;
+        let general_profile_idc = s.u("general_profile_idc", 5)? as u8;
+        let general_profile_compatibility_flags = s.u("general_profile_compatibility_flags", 32)?;
+        let high = s.u("general_constraint_indicator_flags", 16)? as u64;
+        let low = s.u("general_constraint_indicator_flags", 32)? as u64;
+        let general_level_idc = s.u("general_level_idc", 8)? as u8;
+        let mut sub_layers = Vec::new();
+        for i in 0..sps_max_sub_layers_minus1 {
+            sub_layers.push((
+                s.flag(format_args!("sub_layer_profile_present_flag[{}]", i))?,
+                s.flag(format_args!("sub_layer_level_present_flag[{}]", i))?,
+            ));
+        }
+        if sps_max_sub_layers_minus1 > 0 {
+            for i in sps_max_sub_layers_minus1..8 {
+                s.u(format_args!("reserved_zero_2bits[{}]", i), 2)?;
+            }
+        }
+        for (i, (profile_present, level_present)) in sub_layers.into_iter().enumerate() {
+            if profile_present {
+                // sub_layer_profile_space[i] to the sub-layer constraint flags, 88 bits
+                s.u(format_args!("sub_layer_profile[{}]", i), 24)?;
+                s.u(format_args!("sub_layer_profile[{}]", i), 32)?;
+                s.u(format_args!("sub_layer_profile[{}]", i), 32)?;
+            }
+            if level_present {
+                s.u(format_args!("sub_layer_level_idc[{}]", i), 8)?;
+            }
+        }
+
+        let sps_seq_parameter_set_id = s.ue_max("sps_seq_parameter_set_id", 15)?;
+        let chroma_format_idc = s.ue_max("chroma_format_idc", 3)?;
+        if chroma_format_idc == 3 {
+            s.flag("separate_colour_plane_flag")?;
+        }
+        s.ue("pic_width_in_luma_samples")?;
+        s.ue("pic_height_in_luma_samples")?;
+        if s.flag("conformance_window_flag")? {
+            s.ue("conf_win_left_offset")?;
+            s.ue("conf_win_right_offset")?;
+            s.ue("conf_win_top_offset")?;
+            s.ue("conf_win_bottom_offset")?;
+        }
+        let bit_depth_luma_minus8 = s.ue_max("bit_depth_luma_minus8", 8)?;
+        let bit_depth_chroma_minus8 = s.ue_max("bit_depth_chroma_minus8", 8)?;
+        Ok(HevcSpsHeader {
+            sps_max_sub_layers_minus1,
+            sps_temporal_id_nesting_flag,
+            profile_tier_level: HevcProfileTierLevel {
+                general_profile_space,
+                general_tier_flag,
+                general_profile_idc,
+                general_profile_compatibility_flags,
+                general_constraint_indicator_flags: high << 32 | low,
+                general_level_idc,
+            },
+            sps_seq_parameter_set_id,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+        })
+    }
+}
+
+/// One array of NAL units of the same type in the hvcC record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcNalArray {
+    /// Set when every NAL unit of the type is in the record and none is in band.
+    pub array_completeness: bool,
+    pub nal_unit_type: u8,
+    pub nal_units: Vec<Vec<u8>>,
+}
+
+/// HEVCDecoderConfigurationRecord. NAL units are kept with their header and emulation
+/// prevention bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HevcDecoderConfigurationRecord {
+    pub profile_tier_level: HevcProfileTierLevel,
+    pub min_spatial_segmentation_idc: u16,
+    pub parallelism_type: u8,
+    pub chroma_format_idc: u8,
+    pub bit_depth_luma_minus8: u8,
+    pub bit_depth_chroma_minus8: u8,
+    pub avg_frame_rate: u16,
+    pub constant_frame_rate: u8,
+    pub num_temporal_layers: u8,
+    pub temporal_id_nested: bool,
+    pub length_size_minus_one: u8,
+    pub arrays: Vec<HevcNalArray>,
+}
+
+impl HevcDecoderConfigurationRecord {
+    /// Builds the record from VPS, SPS and PPS NAL units, taking the profile, tier, level,
+    /// chroma and temporal layer fields from the first SPS. The fields that need the VUI or
+    /// the coded pictures, min_spatial_segmentation_idc, parallelismType, avgFrameRate and
+    /// constantFrameRate, are 0 for unknown. lengthSizeMinusOne is 3.
+    pub fn from_parameter_sets(
+        vps: Vec<Vec<u8>>,
+        sps: Vec<Vec<u8>>,
+        pps: Vec<Vec<u8>>,
+    ) -> Result<Self, ConfigRecordError> {
+        let header = HevcSpsHeader::from_nal(sps.first().ok_or(ConfigRecordError::MissingSps)?)?;
+        let arrays = [(32, vps), (33, sps), (34, pps)]
+            .into_iter()
+            .filter(|(_, nals)| !nals.is_empty())
+            .map(|(nal_unit_type, nal_units)| HevcNalArray {
+                array_completeness: true,
+                nal_unit_type,
+                nal_units,
+            })
+            .collect();
+        Ok(HevcDecoderConfigurationRecord {
+            profile_tier_level: header.profile_tier_level,
+            min_spatial_segmentation_idc: 0,
+            parallelism_type: 0,
+            chroma_format_idc: header.chroma_format_idc as u8,
+            bit_depth_luma_minus8: header.bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: header.bit_depth_chroma_minus8 as u8,
+            avg_frame_rate: 0,
+            constant_frame_rate: 0,
+            num_temporal_layers: header.sps_max_sub_layers_minus1 + 1,
+            temporal_id_nested: header.sps_temporal_id_nesting_flag,
+            length_size_minus_one: 3,
+            arrays,
+        })
+    }
+
+    /// Builds the record from the VPS, SPS and PPS NAL units of an H.265 Annex B stream, each
+    /// distinct NAL unit once, in stream order.
+    pub fn from_stream(data: &[u8]) -> Result<Self, ConfigRecordError> {
+        let (mut vps, mut sps, mut pps) = (Vec::new(), Vec::new(), Vec::new());
+        for nal in annexb::nal_units(data) {
+            let bytes = &data[nal.start..nal.end];
+            let Some(header) = HevcNalHeader::parse(bytes) else {
+                continue;
+            };
+            match header.nal_unit_type {
+                HevcNalUnitType::Vps => push_distinct(&mut vps, bytes),
+                HevcNalUnitType::Sps => push_distinct(&mut sps, bytes),
+                HevcNalUnitType::Pps => push_distinct(&mut pps, bytes),
+                _ => {}
+            }
+        }
+        Self::from_parameter_sets(vps, sps, pps)
+    }
+
+    /// Parses an hvcC box payload.
+    pub fn parse(data: &[u8]) -> Result<Self, ConfigRecordError> {
+        let mut r = RecordReader { data, pos: 0 };
+        let version = r.u8()?;
+        if version != 1 {
+            return Err(ConfigRecordError::UnsupportedVersion(version));
+        }
+        let byte = r.u8()?;
+        let profile_tier_level = HevcProfileTierLevel {
+            general_profile_space: byte >> 6,
+            general_tier_flag: byte & 0x20 != 0,
+            general_profile_idc: byte & 0x1f,
+            general_profile_compatibility_flags: r.uint(4)? as u32,
+            general_constraint_indicator_flags: r.uint(6)?,
+            general_level_idc: r.u8()?,
+        };
+        let min_spatial_segmentation_idc = r.u16()? & 0x0fff;
+        let parallelism_type = r.u8()? & 0x03;
+        let chroma_format_idc = r.u8()? & 0x03;
+        let bit_depth_luma_minus8 = r.u8()? & 0x07;
+        let bit_depth_chroma_minus8 = r.u8()? & 0x07;
+        let avg_frame_rate = r.u16()?;
+        let byte = r.u8()?;
+        let num_arrays = r.u8()?;
+        let mut arrays = Vec::new();
+        for _ in 0..num_arrays {
+            let array_byte = r.u8()?;
+            let num_nalus = r.u16()? as usize;
+            arrays.push(HevcNalArray {
+                array_completeness: array_byte & 0x80 != 0,
+                nal_unit_type: array_byte & 0x3f,
+                nal_units: r.nal_units(num_nalus)?,
+            });
+        }
+        Ok(HevcDecoderConfigurationRecord {
+            profile_tier_level,
+            min_spatial_segmentation_idc,
+            parallelism_type,
+            chroma_format_idc,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            avg_frame_rate,
+            constant_frame_rate: byte >> 6,
+            num_temporal_layers: (byte >> 3) & 0x07,
+            temporal_id_nested: byte & 0x04 != 0,
+            length_size_minus_one: byte & 0x03,
+            arrays,
+        })
+    }
+
+    /// The hvcC box payload.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ConfigRecordError> {
+        let ptl = &self.profile_tier_level;
+        let mut out = vec![
+            1,
+            ptl.general_profile_space << 6
+                | (ptl.general_tier_flag as u8) << 5
+                | ptl.general_profile_idc,
+        ];
+        out.extend(ptl.general_profile_compatibility_flags.to_be_bytes());
+        out.extend(&ptl.general_constraint_indicator_flags.to_be_bytes()[2..]);
+        out.push(ptl.general_level_idc);
+        out.extend((0xf000 | self.min_spatial_segmentation_idc).to_be_bytes());
+        out.push(0xfc | self.parallelism_type);
+        out.push(0xfc | self.chroma_format_idc);
+        out.push(0xf8 | self.bit_depth_luma_minus8);
+        out.push(0xf8 | self.bit_depth_chroma_minus8);
+        out.extend(self.avg_frame_rate.to_be_bytes());
+        out.push(
+            self.constant_frame_rate << 6
+                | self.num_temporal_layers << 3
+                | (self.temporal_id_nested as u8) << 2
+                | self.length_size_minus_one,
+        );
+        // at most 64 arrays, one per NAL unit type
+        out.push(self.arrays.len().min(255) as u8);
+        for array in self.arrays.iter().take(255) {
+            out.push((array.array_completeness as u8) << 7 | array.nal_unit_type);
+            let n = count(&array.nal_units, array.nal_unit_type, 0xffff)?;
+            out.extend((n as u16).to_be_bytes());
+            write_nal_units(&mut out, &array.nal_units)?;
+        }
+        Ok(out)
+    }
+
+    /// The NAL units of one type.
+    pub fn nal_units(&self, nal_unit_type: u8) -> impl Iterator<Item = &Vec<u8>> {
+        self.arrays
+            .iter()
+            .filter(move |a| a.nal_unit_type == nal_unit_type)
+            .flat_map(|a| &a.nal_units)
+    }
+
+    pub fn codec_string(&self) -> String {
+        self.profile_tier_level.codec_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+
+    #[test]
+    fn test_avc_record() {
+        let mut data = Vec::new();
+        for nal in [&HIGH_SPS[..], &CABAC_PPS, &HIGH_SPS, &[0x65, 0x88, 0x84]] {
+            data.extend([0, 0, 0, 1]);
+            data.extend(nal);
+        }
+        let record = AvcDecoderConfigurationRecord::from_stream(&data).unwrap();
+        assert_eq!(record.codec_string(), "avc1.640028");
+        assert_eq!(
+            avc_codec_string(&Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap()),
+            "avc1.640028"
+        );
+        assert_eq!(record.sequence_parameter_sets, [HIGH_SPS.to_vec()]);
+        assert_eq!(record.picture_parameter_sets, [CABAC_PPS.to_vec()]);
+
+        let bytes = record.to_bytes().unwrap();
+        assert_eq!(bytes[..8], [1, 0x64, 0x00, 0x28, 0xff, 0xe1, 0x00, 27]);
+        assert_eq!(bytes[35..38], [0x01, 0x00, 6]);
+        assert_eq!(bytes[44..], [0xfd, 0xf8, 0xf8, 0x00]);
+        assert_eq!(
+            AvcDecoderConfigurationRecord::parse(&bytes).unwrap(),
+            record
+        );
+
+        // without the High profile fields
+        let short = AvcDecoderConfigurationRecord::parse(&bytes[..44]).unwrap();
+        assert_eq!(short.high_profile_fields, None);
+        assert_eq!(
+            AvcDecoderConfigurationRecord::parse(&bytes[..40]),
+            Err(ConfigRecordError::Truncated { offset: 38 })
+        );
+        assert_eq!(
+            AvcDecoderConfigurationRecord::from_stream(&[0, 0, 1, 0x68, 0xce]),
+            Err(ConfigRecordError::MissingSps)
+        );
+    }
+
+    fn hevc_sps(max_sub_layers_minus1: u32) -> Vec<u8> {
+        let mut w = BitWriter::new();
+        w.u(16, 0x4201).unwrap();
+        w.u(4, 0).unwrap(); // sps_video_parameter_set_id
+        w.u(3, max_sub_layers_minus1).unwrap();
+        w.write_flag(true); // sps_temporal_id_nesting_flag
+        w.u(8, 0x01).unwrap(); // Main profile, Main tier
+        w.u(32, 0x6000_0000).unwrap();
+        w.u(16, 0xb000).unwrap(); // progressive, non-packed and frame only
+        w.u(32, 0).unwrap();
+        w.u(8, 93).unwrap(); // level 3.1
+        if max_sub_layers_minus1 > 0 {
+            w.write_flag(false); // sub_layer_profile_present_flag[0]
+            w.write_flag(true); // sub_layer_level_present_flag[0]
+            w.u(14, 0).unwrap();
+            w.u(8, 90).unwrap();
+        }
+        w.ue(0).unwrap(); // sps_seq_parameter_set_id
+        w.ue(1).unwrap(); // chroma_format_idc
+        w.ue(1280).unwrap();
+        w.ue(720).unwrap();
+        w.write_flag(false); // conformance_window_flag
+        w.ue(2).unwrap(); // bit_depth_luma_minus8
+        w.ue(2).unwrap();
+        w.rbsp_trailing_bits();
+        w.to_nal_payload()
+    }
+
+    #[test]
+    fn test_hevc_record() {
+        let header = HevcSpsHeader::from_nal(&hevc_sps(1)).unwrap();
+        assert_eq!(header.sps_max_sub_layers_minus1, 1);
+        assert_eq!(header.profile_tier_level.general_level_idc, 93);
+        assert_eq!(header.bit_depth_chroma_minus8, 2);
+
+        let vps = vec![0x40, 0x01, 0x0c];
+        let pps = vec![0x44, 0x01, 0xc1];
+        let mut data = Vec::new();
+        for nal in [&vps, &hevc_sps(0), &pps] {
+            data.extend([0, 0, 0, 1]);
+            data.extend(nal);
+        }
+        let record = HevcDecoderConfigurationRecord::from_stream(&data).unwrap();
+        assert_eq!(record.codec_string(), "hvc1.1.6.L93.B0");
+        assert_eq!(record.num_temporal_layers, 1);
+        assert!(record.temporal_id_nested);
+        assert_eq!(record.nal_units(34).collect::<Vec<_>>(), [&pps]);
+
+        let bytes = record.to_bytes().unwrap();
+        assert_eq!(
+            bytes[..23],
+            [
+                1, 0x01, 0x60, 0, 0, 0, 0xb0, 0, 0, 0, 0, 0, 93, 0xf0, 0, 0xfc, 0xfd, 0xfa, 0xfa,
+                0, 0, 0x0f, 3
+            ]
+        );
+        assert_eq!(bytes[23..26], [0xa0, 0, 1]);
+        assert_eq!(
+            HevcDecoderConfigurationRecord::parse(&bytes).unwrap(),
+            record
+        );
+        assert_eq!(
+            HevcDecoderConfigurationRecord::parse(&[0]),
+            Err(ConfigRecordError::UnsupportedVersion(0))
+        );
+
+        let high_tier = HevcProfileTierLevel {
+            general_profile_space: 1,
+            general_tier_flag: true,
+            general_profile_idc: 2,
+            general_profile_compatibility_flags: 0x2000_0000,
+            general_constraint_indicator_flags: 0,
+            general_level_idc: 120,
+        };
+        assert_eq!(high_tier.codec_string(), "hvc1.A2.4.H120");
+    }
+}
@@ -0,0 +1,701 @@
+//! Parameter set editing: changes SPS, VUI and PPS fields by syntax element name, writes the
+//! parameter set again and substitutes it for every copy with the same id in an Annex B stream.
+
+use std::fmt;
+
+use crate::annexb;
+use crate::bitwriter::BitWriterError;
+use crate::emulation;
+use crate::nal::{NalHeader, NalUnitType};
+use crate::pps::Pps;
+use crate::sps::Sps;
+use crate::syntax::{self, ParseError};
+use crate::vui::{VuiParameters, EXTENDED_SAR};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditError {
+    /// No editable syntax element has this name.
+    UnknownField(String),
+    /// The value does not fit the syntax element.
+    OutOfRange { name: String, value: i64 },
+    /// The field cannot be changed on this parameter set, e.g. because the number of scaling
+    /// lists would change with it.
+    Unsupported(String),
+    /// The parameter set to edit does not parse.
+    Parse(ParseError),
+    /// The edited parameter set cannot be written.
+    Write(BitWriterError),
+    /// The stream has no SPS or PPS with the id.
+    NotFound { kind: &'static str, id: u32 },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EditError::UnknownField(name) => write!(f, "unknown field {}", name),
+            EditError::OutOfRange { name, value } => {
+                write!(f, "{} = {} is out of range", name, value)
+            }
+            EditError::Unsupported(message) => write!(f, "{}", message),
+            EditError::Parse(err) => err.fmt(f),
+            EditError::Write(err) => err.fmt(f),
+            EditError::NotFound { kind, id } => {
+                write!(f, "no {} with id {} in the stream", kind, id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl From<ParseError> for EditError {
+    fn from(err: ParseError) -> Self {
+        EditError::Parse(err)
+    }
+}
+
+impl From<BitWriterError> for EditError {
+    fn from(err: BitWriterError) -> Self {
+        EditError::Write(err)
+    }
+}
+
+/// Largest value of a ue(v) element.
+const UE_MAX: i64 = u32::MAX as i64 - 1;
+
+fn range(name: &str, value: i64, min: i64, max: i64) -> Result<i64, EditError> {
+    if (min..=max).contains(&value) {
+        Ok(value)
+    } else {
+        Err(EditError::OutOfRange {
+            name: name.to_string(),
+            value,
+        })
+    }
+}
+
+fn unsigned(name: &str, value: i64, max: i64) -> Result<u32, EditError> {
+    Ok(range(name, value, 0, max)? as u32)
+}
+
+fn signed(name: &str, value: i64) -> Result<i32, EditError> {
+    Ok(range(name, value, -(i32::MAX as i64), i32::MAX as i64)? as i32)
+}
+
+fn flag(name: &str, value: i64) -> Result<bool, EditError> {
+    Ok(range(name, value, 0, 1)? == 1)
+}
+
+/// The VUI of the SPS, added with inferred values when there is none.
+fn vui(sps: &mut Sps) -> &mut VuiParameters {
+    let dpb_frames = sps.inferred_dpb_frames();
+    sps.vui_parameters_present_flag = true;
+    sps.vui_parameters.get_or_insert_with(|| VuiParameters {
+        max_num_reorder_frames: dpb_frames,
+        max_dec_frame_buffering: dpb_frames,
+        ..Default::default()
+    })
+}
+
+/// Sets a VUI field, returning false when there is none with that name.
+fn set_vui_field(sps: &mut Sps, name: &str, value: i64) -> Result<bool, EditError> {
+    if !VUI_FIELDS.contains(&name) {
+        return Ok(false);
+    }
+    let v = vui(sps);
+    match name {
+        "aspect_ratio_info_present_flag" => v.aspect_ratio_info_present_flag = flag(name, value)?,
+        "aspect_ratio_idc" => {
+            v.aspect_ratio_idc = unsigned(name, value, 255)? as u8;
+            v.aspect_ratio_info_present_flag = true;
+        }
+        "sar_width" | "sar_height" => {
+            let sar = unsigned(name, value, 0xffff)? as u16;
+            if name == "sar_width" {
+                v.sar_width = sar;
+            } else {
+                v.sar_height = sar;
+            }
+            v.aspect_ratio_idc = EXTENDED_SAR;
+            v.aspect_ratio_info_present_flag = true;
+        }
+        "overscan_info_present_flag" => v.overscan_info_present_flag = flag(name, value)?,
+        "overscan_appropriate_flag" => {
+            v.overscan_appropriate_flag = flag(name, value)?;
+            v.overscan_info_present_flag = true;
+        }
+        "video_signal_type_present_flag" => v.video_signal_type_present_flag = flag(name, value)?,
+        "video_format" | "video_full_range_flag" | "colour_description_present_flag" => {
+            match name {
+                "video_format" => v.video_format = unsigned(name, value, 7)? as u8,
+                "video_full_range_flag" => v.video_full_range_flag = flag(name, value)?,
+                _ => v.colour_description_present_flag = flag(name, value)?,
+            }
+            v.video_signal_type_present_flag = true;
+        }
+        "colour_primaries" | "transfer_characteristics" | "matrix_coefficients" => {
+            let colour = unsigned(name, value, 255)? as u8;
+            match name {
+                "colour_primaries" => v.colour_primaries = colour,
+                "transfer_characteristics" => v.transfer_characteristics = colour,
+                _ => v.matrix_coefficients = colour,
+            }
+            v.colour_description_present_flag = true;
+            v.video_signal_type_present_flag = true;
+        }
+        "chroma_loc_info_present_flag" => v.chroma_loc_info_present_flag = flag(name, value)?,
+        "chroma_sample_loc_type_top_field" | "chroma_sample_loc_type_bottom_field" => {
+            let loc = unsigned(name, value, 5)?;
+            if name == "chroma_sample_loc_type_top_field" {
+                v.chroma_sample_loc_type_top_field = loc;
+            } else {
+                v.chroma_sample_loc_type_bottom_field = loc;
+            }
+            v.chroma_loc_info_present_flag = true;
+        }
+        "timing_info_present_flag" => v.timing_info_present_flag = flag(name, value)?,
+        "num_units_in_tick" | "time_scale" | "fixed_frame_rate_flag" => {
+            match name {
+                "num_units_in_tick" => {
+                    v.num_units_in_tick = unsigned(name, value, u32::MAX as i64)?
+                }
+                "time_scale" => v.time_scale = unsigned(name, value, u32::MAX as i64)?,
+                _ => v.fixed_frame_rate_flag = flag(name, value)?,
+            }
+            v.timing_info_present_flag = true;
+        }
+        "low_delay_hrd_flag" => {
+            if v.nal_hrd_parameters.is_none() && v.vcl_hrd_parameters.is_none() {
+                return Err(EditError::Unsupported(
+                    "low_delay_hrd_flag is only present with HRD parameters".to_string(),
+                ));
+            }
+            v.low_delay_hrd_flag = flag(name, value)?;
+        }
+        "pic_struct_present_flag" => v.pic_struct_present_flag = flag(name, value)?,
+        "bitstream_restriction_flag" => v.bitstream_restriction_flag = flag(name, value)?,
+        _ => {
+            match name {
+                "motion_vectors_over_pic_boundaries_flag" => {
+                    v.motion_vectors_over_pic_boundaries_flag = flag(name, value)?
+                }
+                "max_bytes_per_pic_denom" => v.max_bytes_per_pic_denom = unsigned(name, value, 16)?,
+                "max_bits_per_mb_denom" => v.max_bits_per_mb_denom = unsigned(name, value, 16)?,
+                "log2_max_mv_length_horizontal" => {
+                    v.log2_max_mv_length_horizontal = unsigned(name, value, 16)?
+                }
+                "log2_max_mv_length_vertical" => {
+                    v.log2_max_mv_length_vertical = unsigned(name, value, 16)?
+                }
+                "max_num_reorder_frames" => {
+                    v.max_num_reorder_frames = unsigned(name, value, UE_MAX)?
+                }
+                _ => v.max_dec_frame_buffering = unsigned(name, value, UE_MAX)?,
+            }
+            v.bitstream_restriction_flag = true;
+        }
+    }
+    Ok(true)
+}
+
+/// The VUI syntax elements `set_sps_field` accepts, the HRD parameters excepted.
+const VUI_FIELDS: [&str; 30] = [
+    "aspect_ratio_info_present_flag",
+    "aspect_ratio_idc",
+    "sar_width",
+    "sar_height",
+    "overscan_info_present_flag",
+    "overscan_appropriate_flag",
+    "video_signal_type_present_flag",
+    "video_format",
+    "video_full_range_flag",
+    "colour_description_present_flag",
+    "colour_primaries",
+    "transfer_characteristics",
+    "matrix_coefficients",
+    "chroma_loc_info_present_flag",
+    "chroma_sample_loc_type_top_field",
+    "chroma_sample_loc_type_bottom_field",
+    "timing_info_present_flag",
+    "num_units_in_tick",
+    "time_scale",
+    "fixed_frame_rate_flag",
+    "low_delay_hrd_flag",
+    "pic_struct_present_flag",
+    "bitstream_restriction_flag",
+    "motion_vectors_over_pic_boundaries_flag",
+    "max_bytes_per_pic_denom",
+    "max_bits_per_mb_denom",
+    "log2_max_mv_length_horizontal",
+    "log2_max_mv_length_vertical",
+    "max_num_reorder_frames",
+    "max_dec_frame_buffering",
+];
+
+/// SPS syntax elements the coded slice syntax depends on: slices that refer to the SPS would
+/// no longer parse after a change, so they cannot be set. seq_parameter_set_id is included
+/// since the PPSs refer to the SPS through it, and profile_idc since it decides which of the
+/// others are present.
+const CODED_SLICE_SPS_FIELDS: [&str; 14] = [
+    "profile_idc",
+    "seq_parameter_set_id",
+    "chroma_format_idc",
+    "separate_colour_plane_flag",
+    "bit_depth_luma_minus8",
+    "bit_depth_chroma_minus8",
+    "log2_max_frame_num_minus4",
+    "pic_order_cnt_type",
+    "log2_max_pic_order_cnt_lsb_minus4",
+    "delta_pic_order_always_zero_flag",
+    "pic_width_in_mbs_minus1",
+    "pic_height_in_map_units_minus1",
+    "frame_mbs_only_flag",
+    "mb_adaptive_frame_field_flag",
+];
+
+/// PPS syntax elements the coded slice syntax depends on, pic_parameter_set_id and
+/// seq_parameter_set_id included since the slices refer to the PPS and its SPS through them.
+const CODED_SLICE_PPS_FIELDS: [&str; 11] = [
+    "pic_parameter_set_id",
+    "seq_parameter_set_id",
+    "entropy_coding_mode_flag",
+    "bottom_field_pic_order_in_frame_present_flag",
+    "num_ref_idx_l0_default_active_minus1",
+    "num_ref_idx_l1_default_active_minus1",
+    "weighted_pred_flag",
+    "weighted_bipred_idc",
+    "deblocking_filter_control_present_flag",
+    "redundant_pic_cnt_present_flag",
+    "transform_8x8_mode_flag",
+];
+
+fn check_coded_slice_field(fields: &[&str], name: &str) -> Result<(), EditError> {
+    if fields.contains(&name) {
+        return Err(EditError::Unsupported(format!(
+            "{} changes the coded slice syntax",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Sets an SPS or VUI syntax element by name.
+///
+/// Setting an element that is only present under a flag sets that flag, e.g. colour_primaries
+/// sets video_signal_type_present_flag and colour_description_present_flag, and sar_width
+/// sets aspect_ratio_idc to Extended_SAR. Setting a VUI element on an SPS without VUI adds one
+/// with inferred values; as when parsing, max_num_reorder_frames and max_dec_frame_buffering
+/// are inferred from the profile and level, so the bitstream restriction keeps them when it is
+/// turned on. Scaling lists, offset_for_ref_frame, the HRD parameters and the elements the
+/// coded slice syntax depends on cannot be set.
+pub fn set_sps_field(sps: &mut Sps, name: &str, value: i64) -> Result<(), EditError> {
+    check_coded_slice_field(&CODED_SLICE_SPS_FIELDS, name)?;
+    if set_vui_field(sps, name, value)? {
+        return Ok(());
+    }
+    match name {
+        "constraint_set0_flag" => sps.constraint_set0_flag = flag(name, value)?,
+        "constraint_set1_flag" => sps.constraint_set1_flag = flag(name, value)?,
+        "constraint_set2_flag" => sps.constraint_set2_flag = flag(name, value)?,
+        "constraint_set3_flag" => sps.constraint_set3_flag = flag(name, value)?,
+        "constraint_set4_flag" => sps.constraint_set4_flag = flag(name, value)?,
+        "constraint_set5_flag" => sps.constraint_set5_flag = flag(name, value)?,
+        "level_idc" => sps.level_idc = unsigned(name, value, 255)? as u8,
+        "qpprime_y_zero_transform_bypass_flag" => {
+            sps.qpprime_y_zero_transform_bypass_flag = flag(name, value)?
+        }
+        "offset_for_non_ref_pic" => sps.offset_for_non_ref_pic = signed(name, value)?,
+        "offset_for_top_to_bottom_field" => {
+            sps.offset_for_top_to_bottom_field = signed(name, value)?
+        }
+        "max_num_ref_frames" => sps.max_num_ref_frames = unsigned(name, value, UE_MAX)?,
+        "gaps_in_frame_num_value_allowed_flag" => {
+            sps.gaps_in_frame_num_value_allowed_flag = flag(name, value)?
+        }
+        "direct_8x8_inference_flag" => sps.direct_8x8_inference_flag = flag(name, value)?,
+        "frame_cropping_flag" => sps.frame_cropping_flag = flag(name, value)?,
+        "frame_crop_left_offset"
+        | "frame_crop_right_offset"
+        | "frame_crop_top_offset"
+        | "frame_crop_bottom_offset" => {
+            let offset = unsigned(name, value, UE_MAX)?;
+            match name {
+                "frame_crop_left_offset" => sps.frame_crop_left_offset = offset,
+                "frame_crop_right_offset" => sps.frame_crop_right_offset = offset,
+                "frame_crop_top_offset" => sps.frame_crop_top_offset = offset,
+                _ => sps.frame_crop_bottom_offset = offset,
+            }
+            sps.frame_cropping_flag = true;
+        }
+        "vui_parameters_present_flag" => {
+            if flag(name, value)? {
+                vui(sps);
+            } else {
+                sps.vui_parameters_present_flag = false;
+                sps.vui_parameters = None;
+            }
+        }
+        _ => return Err(EditError::UnknownField(name.to_string())),
+    }
+    Ok(())
+}
+
+/// Sets a PPS syntax element by name. The slice group map, the scaling lists,
+/// pic_scaling_matrix_present_flag and the elements the coded slice syntax depends on cannot be
+/// set.
+///
+/// Without the transform_8x8_mode_flag extension, second_chroma_qp_index_offset follows
+/// chroma_qp_index_offset as its inferred value, so that the extension is not added.
+pub fn set_pps_field(pps: &mut Pps, name: &str, value: i64) -> Result<(), EditError> {
+    check_coded_slice_field(&CODED_SLICE_PPS_FIELDS, name)?;
+    match name {
+        "pic_init_qp_minus26" => pps.pic_init_qp_minus26 = range(name, value, -26, 25)? as i32,
+        "pic_init_qs_minus26" => pps.pic_init_qs_minus26 = range(name, value, -26, 25)? as i32,
+        "chroma_qp_index_offset" => {
+            let offset = range(name, value, -12, 12)? as i32;
+            if !pps.has_transform_8x8_extension() {
+                pps.second_chroma_qp_index_offset = offset;
+            }
+            pps.chroma_qp_index_offset = offset;
+        }
+        "constrained_intra_pred_flag" => pps.constrained_intra_pred_flag = flag(name, value)?,
+        "second_chroma_qp_index_offset" => {
+            pps.second_chroma_qp_index_offset = range(name, value, -12, 12)? as i32
+        }
+        _ => return Err(EditError::UnknownField(name.to_string())),
+    }
+    Ok(())
+}
+
+/// An Annex B stream with parameter sets replaced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rewrite {
+    pub data: Vec<u8>,
+    /// Offsets in the original stream of the NAL units that were replaced.
+    pub positions: Vec<usize>,
+}
+
+/// Replaces every NAL unit for which `edit` returns new bytes. Everything else, start codes
+/// and trailing zero bytes included, is copied as it is.
+fn rewrite_nal_units(
+    data: &[u8],
+    mut edit: impl FnMut(&[u8]) -> Result<Option<Vec<u8>>, EditError>,
+) -> Result<Rewrite, EditError> {
+    let mut out = Vec::with_capacity(data.len());
+    let mut positions = Vec::new();
+    let mut copied = 0;
+    for nal in annexb::nal_units(data) {
+        if let Some(replacement) = edit(&data[nal.start..nal.end])? {
+            out.extend_from_slice(&data[copied..nal.start]);
+            out.extend(replacement);
+            copied = nal.end;
+            positions.push(nal.start);
+        }
+    }
+    out.extend_from_slice(&data[copied..]);
+    Ok(Rewrite {
+        data: out,
+        positions,
+    })
+}
+
+/// Applies `fields` to the SPS NAL unit and writes it again, keeping its nal_ref_idc.
+pub fn edit_sps_nal(nal: &[u8], fields: &[(&str, i64)]) -> Result<Vec<u8>, EditError> {
+    let mut sps = Sps::from_rbsp(&emulation::decode(nal))?;
+    for &(name, value) in fields {
+        set_sps_field(&mut sps, name, value)?;
+    }
+    Ok(sps.to_nal(nal[0] >> 5)?)
+}
+
+/// Applies `fields` to the PPS NAL unit, which refers to `sps`, and writes it again, keeping
+/// its nal_ref_idc.
+pub fn edit_pps_nal(nal: &[u8], sps: &Sps, fields: &[(&str, i64)]) -> Result<Vec<u8>, EditError> {
+    let mut pps = Pps::from_rbsp(&emulation::decode(nal), |_| Ok(sps))?;
+    for &(name, value) in fields {
+        set_pps_field(&mut pps, name, value)?;
+    }
+    Ok(pps.to_nal(nal[0] >> 5)?)
+}
+
+fn nal_unit_type(nal: &[u8]) -> Option<NalUnitType> {
+    NalHeader::parse(nal).map(|header| header.nal_unit_type)
+}
+
+/// seq_parameter_set_id of an SPS NAL unit, read without parsing the rest.
+fn seq_parameter_set_id(nal: &[u8]) -> Result<u32, ParseError> {
+    syntax::read_leading(nal, |s| {
+        // profile_idc, the constraint flags and level_idc come first
+        s.skip_to(32);
+        s.ue("seq_parameter_set_id")
+    })
+}
+
+/// Applies `fields` to every SPS with the given seq_parameter_set_id in an Annex B stream.
+/// Each copy is edited on its own, so copies that differ stay different in the other fields.
+pub fn rewrite_sps(
+    data: &[u8],
+    seq_parameter_set_id: u32,
+    fields: &[(&str, i64)],
+) -> Result<Rewrite, EditError> {
+    let rewrite = rewrite_nal_units(data, |nal| {
+        if nal_unit_type(nal) != Some(NalUnitType::Sps) {
+            return Ok(None);
+        }
+        if self::seq_parameter_set_id(nal)? != seq_parameter_set_id {
+            return Ok(None);
+        }
+        edit_sps_nal(nal, fields).map(Some)
+    })?;
+    if rewrite.positions.is_empty() {
+        return Err(EditError::NotFound {
+            kind: "SPS",
+            id: seq_parameter_set_id,
+        });
+    }
+    Ok(rewrite)
+}
+
+/// Applies `fields` to every PPS with the given pic_parameter_set_id in an Annex B stream,
+/// each parsed with the SPS received last before it.
+pub fn rewrite_pps(
+    data: &[u8],
+    pic_parameter_set_id: u32,
+    fields: &[(&str, i64)],
+) -> Result<Rewrite, EditError> {
+    let mut sps_by_id: Vec<Option<Sps>> = vec![None; 32];
+    let rewrite = rewrite_nal_units(data, |nal| match nal_unit_type(nal) {
+        Some(NalUnitType::Sps) => {
+            // an SPS that does not parse only matters if a PPS to edit refers to it
+            if let Ok(sps) = Sps::from_rbsp(&emulation::decode(nal)) {
+                let id = sps.seq_parameter_set_id as usize;
+                sps_by_id[id] = Some(sps);
+            }
+            Ok(None)
+        }
+        Some(NalUnitType::Pps) => {
+            let (pps_id, sps_id) = syntax::read_leading(nal, |s| {
+                let pps_id = s.ue("pic_parameter_set_id")?;
+                Ok((pps_id, s.ue("seq_parameter_set_id")?))
+            })?;
+            if pps_id != pic_parameter_set_id {
+                return Ok(None);
+            }
+            let sps = sps_by_id
+                .get(sps_id as usize)
+                .and_then(Option::as_ref)
+                .ok_or(ParseError::MissingSps(sps_id))?;
+            edit_pps_nal(nal, sps, fields).map(Some)
+        }
+        _ => Ok(None),
+    })?;
+    if rewrite.positions.is_empty() {
+        return Err(EditError::NotFound {
+            kind: "PPS",
+            id: pic_parameter_set_id,
+        });
+    }
+    Ok(rewrite)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::conformance;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+
+    fn parse_sps(nal: &[u8]) -> Sps {
+        Sps::from_rbsp(&emulation::decode(nal)).unwrap()
+    }
+
+    #[test]
+    fn test_edit_sps() {
+        let nal = edit_sps_nal(
+            &HIGH_SPS,
+            &[
+                ("level_idc", 41),
+                ("colour_primaries", 1),
+                ("transfer_characteristics", 1),
+                ("matrix_coefficients", 1),
+                ("frame_crop_bottom_offset", 4),
+            ],
+        )
+        .unwrap();
+        assert_eq!(nal[0], 0x67);
+        let sps = parse_sps(&nal);
+        assert_eq!(sps.level_idc, 41);
+        assert_eq!(sps.height(), 1080);
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        assert!(vui.video_signal_type_present_flag && vui.colour_description_present_flag);
+        assert_eq!(vui.colour_primaries_name(), "BT.709");
+        assert_eq!(vui.matrix_coefficients, 1);
+        // the other elements are kept
+        assert_eq!(sps.frame_rate(), Some(30.0));
+        assert_eq!(vui.max_dec_frame_buffering, 4);
+
+        // timing_info added to an SPS without VUI
+        let mut sps = Sps {
+            vui_parameters_present_flag: false,
+            vui_parameters: None,
+            ..sps
+        };
+        set_sps_field(&mut sps, "num_units_in_tick", 1001).unwrap();
+        set_sps_field(&mut sps, "time_scale", 60000).unwrap();
+        set_sps_field(&mut sps, "fixed_frame_rate_flag", 1).unwrap();
+        let sps = parse_sps(&sps.to_nal(3).unwrap());
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        assert!(vui.timing_info_present_flag && !vui.aspect_ratio_info_present_flag);
+        assert_eq!(sps.frame_rate(), Some(60000.0 / 2002.0));
+
+        let mut sps = sps;
+        assert_eq!(
+            set_sps_field(&mut sps, "level", 40),
+            Err(EditError::UnknownField("level".to_string()))
+        );
+        assert_eq!(
+            set_sps_field(&mut sps, "level_idc", 256),
+            Err(EditError::OutOfRange {
+                name: "level_idc".to_string(),
+                value: 256
+            })
+        );
+        assert!(matches!(
+            set_sps_field(&mut sps, "low_delay_hrd_flag", 1),
+            Err(EditError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_bitstream_restriction() {
+        // level 4.0 holds 4 frames of 1920x1088
+        let mut sps = Sps {
+            vui_parameters_present_flag: false,
+            vui_parameters: None,
+            ..parse_sps(&HIGH_SPS)
+        };
+        set_sps_field(&mut sps, "max_num_reorder_frames", 2).unwrap();
+        let sps = parse_sps(&sps.to_nal(3).unwrap());
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        assert!(vui.bitstream_restriction_flag);
+        assert_eq!(vui.max_num_reorder_frames, 2);
+        assert_eq!(vui.max_dec_frame_buffering, 4);
+        assert!(conformance::check_level(&sps).is_empty());
+
+        // High 10 Intra
+        let mut intra = Sps {
+            profile_idc: 110,
+            constraint_set3_flag: true,
+            vui_parameters: None,
+            ..sps.clone()
+        };
+        set_sps_field(&mut intra, "bitstream_restriction_flag", 1).unwrap();
+        let vui = intra.vui_parameters.as_ref().unwrap();
+        assert_eq!(
+            (vui.max_num_reorder_frames, vui.max_dec_frame_buffering),
+            (0, 0)
+        );
+    }
+
+    #[test]
+    fn test_coded_slice_fields() {
+        let mut sps = parse_sps(&HIGH_SPS);
+        for name in CODED_SLICE_SPS_FIELDS {
+            assert_eq!(
+                set_sps_field(&mut sps, name, 1),
+                Err(EditError::Unsupported(format!(
+                    "{} changes the coded slice syntax",
+                    name
+                )))
+            );
+        }
+        assert_eq!(sps, parse_sps(&HIGH_SPS));
+
+        let mut pps = Pps::from_rbsp(&emulation::decode(&CABAC_PPS), |_| Ok(&sps)).unwrap();
+        let original = pps.clone();
+        for name in CODED_SLICE_PPS_FIELDS {
+            assert!(matches!(
+                set_pps_field(&mut pps, name, 1),
+                Err(EditError::Unsupported(_))
+            ));
+        }
+        assert_eq!(pps, original);
+        assert!(matches!(
+            edit_pps_nal(&CABAC_PPS, &sps, &[("weighted_pred_flag", 1)]),
+            Err(EditError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn test_chroma_qp_index_offset() {
+        let sps = parse_sps(&HIGH_SPS);
+        let pps = Pps::from_rbsp(&emulation::decode(&CABAC_PPS), |_| Ok(&sps)).unwrap();
+
+        // without the extension, second_chroma_qp_index_offset is inferred and follows
+        let stripped = Pps {
+            transform_8x8_mode_flag: false,
+            ..pps.clone()
+        };
+        assert!(!stripped.has_transform_8x8_extension());
+        let nal = stripped.to_nal(3).unwrap();
+        let edited = edit_pps_nal(&nal, &sps, &[("chroma_qp_index_offset", 3)]).unwrap();
+        assert_eq!(edited.len(), nal.len());
+        let parsed = Pps::from_rbsp(&emulation::decode(&edited), |_| Ok(&sps)).unwrap();
+        assert_eq!(
+            parsed,
+            Pps {
+                chroma_qp_index_offset: 3,
+                second_chroma_qp_index_offset: 3,
+                ..stripped
+            }
+        );
+        assert_eq!(parsed.to_nal(3).unwrap(), edited);
+
+        // with it, second_chroma_qp_index_offset keeps its value
+        let edited = edit_pps_nal(&CABAC_PPS, &sps, &[("chroma_qp_index_offset", 3)]).unwrap();
+        let parsed = Pps::from_rbsp(&emulation::decode(&edited), |_| Ok(&sps)).unwrap();
+        assert_eq!(parsed.chroma_qp_index_offset, 3);
+        assert_eq!(parsed.second_chroma_qp_index_offset, -2);
+    }
+
+    #[test]
+    fn test_rewrite_stream() {
+        let slice = [0x65, 0x88, 0x84, 0x00, 0x00, 0x03, 0x01];
+        let mut data = Vec::new();
+        for nal in [&HIGH_SPS[..], &CABAC_PPS, &slice, &HIGH_SPS, &CABAC_PPS] {
+            data.extend([0, 0, 0, 1]);
+            data.extend(nal);
+        }
+        data.push(0);
+
+        let rewrite = rewrite_sps(&data, 0, &[("level_idc", 42)]).unwrap();
+        assert_eq!(rewrite.positions, [4, 56]);
+        let nals: Vec<_> = annexb::nal_units(&rewrite.data)
+            .map(|nal| &rewrite.data[nal.start..nal.end])
+            .collect();
+        assert_eq!(nals.len(), 5);
+        assert_eq!(parse_sps(nals[0]).level_idc, 42);
+        assert_eq!(nals[0], nals[3]);
+        assert_eq!(nals[2], slice);
+        assert_eq!(rewrite.data.len(), data.len());
+        assert_eq!(rewrite.data.last(), Some(&0));
+
+        let rewrite = rewrite_pps(&data, 0, &[("constrained_intra_pred_flag", 1)]).unwrap();
+        assert_eq!(rewrite.positions, [35, 87]);
+        let sps = parse_sps(&HIGH_SPS);
+        let nal = &rewrite.data[35..41];
+        let pps = Pps::from_rbsp(&emulation::decode(nal), |_| Ok(&sps)).unwrap();
+        assert!(pps.constrained_intra_pred_flag);
+        assert!(pps.transform_8x8_mode_flag);
+
+        assert_eq!(
+            rewrite_sps(&data, 1, &[("level_idc", 42)]),
+            Err(EditError::NotFound { kind: "SPS", id: 1 })
+        );
+        // a PPS before its SPS
+        assert_eq!(
+            rewrite_pps(&data[31..], 0, &[]),
+            Err(EditError::Parse(ParseError::MissingSps(0)))
+        );
+    }
+}
@@ -0,0 +1,622 @@
+//! Emulation prevention: conversion between NAL unit bytes and RBSP (7.3.1, 7.4.1).
+
+use std::fmt;
+use std::ops::Range;
+
+/// The output buffer cannot hold the converted data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BufferTooSmall {
+    pub needed: usize,
+    pub available: usize,
+}
+
+impl fmt::Display for BufferTooSmall {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "output buffer too small: {} bytes needed, {} available",
+            self.needed, self.available
+        )
+    }
+}
+
+impl std::error::Error for BufferTooSmall {}
+
+/// A byte sequence that must not occur inside a NAL unit (7.4.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViolationKind {
+    /// 0x000000
+    ZeroSequence,
+    /// 0x000001, a start code prefix
+    StartCode,
+    /// 0x000002
+    ReservedSequence,
+    /// 0x000003 followed by a byte greater than 0x03
+    InvalidEscape,
+    /// The NAL unit ends with 0x00.
+    TrailingZero,
+}
+
+impl ViolationKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViolationKind::ZeroSequence => "zero_sequence",
+            ViolationKind::StartCode => "start_code",
+            ViolationKind::ReservedSequence => "reserved_sequence",
+            ViolationKind::InvalidEscape => "invalid_escape",
+            ViolationKind::TrailingZero => "trailing_zero",
+        }
+    }
+}
+
+/// An illegal byte sequence, `offset` being the position of its first byte in the NAL unit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub offset: usize,
+    pub kind: ViolationKind,
+}
+
+/// A NAL unit that failed strict validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub violations: Vec<Violation>,
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} illegal byte sequence(s) in NAL unit",
+            self.violations.len()
+        )?;
+        if let Some(first) = self.violations.first() {
+            write!(
+                f,
+                ", first: {} at offset {}",
+                first.kind.name(),
+                first.offset
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Finds the first `i >= from` with `src[i] == src[i + 1] == 0` and `third(src[i + 2])`,
+/// returns `i + 2`.
+///
+/// Zero bytes are located with `memchr`, which checks a whole vector register at a time, so the
+/// long stretches of non-zero slice data in between are skipped in bulk.
+fn find_zero_pair(src: &[u8], mut from: usize, third: impl Fn(u8) -> bool) -> Option<usize> {
+    while from + 2 < src.len() {
+        let i = from + memchr::memchr(0, &src[from..src.len() - 2])?;
+        if src[i + 1] != 0 {
+            // no pair can start at i + 1 either
+            from = i + 2;
+        } else if third(src[i + 2]) {
+            return Some(i + 2);
+        } else {
+            from = i + 1;
+        }
+    }
+    None
+}
+
+/// Finds the next 0x000003 sequence starting at or after `from`, returns the offset of the 0x03.
+fn find_escape(src: &[u8], from: usize) -> Option<usize> {
+    find_zero_pair(src, from, |b| b == 3)
+}
+
+/// Finds the next 0x0000 pair starting at or after `from` that must be escaped,
+/// returns the offset at which the 0x03 has to be inserted.
+fn find_emulation(src: &[u8], from: usize) -> Option<usize> {
+    find_zero_pair(src, from, |b| b < 4)
+}
+
+/// Calls `keep` with the ranges of `src` that remain once emulation prevention bytes are removed.
+fn decode_runs(src: &[u8], mut keep: impl FnMut(Range<usize>)) {
+    let mut run_start = 0;
+    while let Some(escape) = find_escape(src, run_start) {
+        keep(run_start..escape);
+        run_start = escape + 1;
+    }
+    keep(run_start..src.len());
+}
+
+/// Calls `emit` with the pieces of the encoded output. Escape bytes are passed as `[0x03]` along
+/// with the offset in `src` they are inserted before.
+fn encode_runs(src: &[u8], mut emit: impl FnMut(&[u8], Option<usize>)) {
+    let mut run_start = 0;
+    while let Some(pos) = find_emulation(src, run_start) {
+        emit(&src[run_start..pos], None);
+        emit(&[3], Some(pos));
+        run_start = pos;
+    }
+    emit(&src[run_start..], None);
+    // an RBSP ending with cabac_zero_words gets a final 0x03 so the NAL unit does not end with 0x00
+    if src.ends_with(&[0, 0]) && run_start + 2 <= src.len() {
+        emit(&[3], Some(src.len()));
+    }
+}
+
+/// Length of `src` once its emulation prevention bytes are removed.
+pub fn decoded_len(src: &[u8]) -> usize {
+    let mut len = 0;
+    decode_runs(src, |run| len += run.len());
+    len
+}
+
+/// Length of `src` once emulation prevention bytes are inserted.
+pub fn encoded_len(src: &[u8]) -> usize {
+    let mut len = 0;
+    encode_runs(src, |piece, _| len += piece.len());
+    len
+}
+
+/// Removes the emulation prevention bytes of a NAL unit, giving its RBSP.
+pub fn decode(src: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(src.len());
+    decode_runs(src, |run| res.extend_from_slice(&src[run]));
+    res
+}
+
+/// Like [`decode`], but writes into `dst` and returns the number of bytes written.
+pub fn decode_into(src: &[u8], dst: &mut [u8]) -> Result<usize, BufferTooSmall> {
+    // the output is never longer than the input, so only count when that bound does not hold
+    if dst.len() < src.len() {
+        let needed = decoded_len(src);
+        if dst.len() < needed {
+            return Err(BufferTooSmall {
+                needed,
+                available: dst.len(),
+            });
+        }
+    }
+    let mut written = 0;
+    decode_runs(src, |run| {
+        let len = run.len();
+        dst[written..written + len].copy_from_slice(&src[run]);
+        written += len;
+    });
+    Ok(written)
+}
+
+/// Removes the emulation prevention bytes in place and returns the length of the RBSP,
+/// which is left at the beginning of `buf`.
+pub fn decode_in_place(buf: &mut [u8]) -> usize {
+    let mut written = 0;
+    let mut run_start = 0;
+    while let Some(escape) = find_escape(buf, run_start) {
+        buf.copy_within(run_start..escape, written);
+        written += escape - run_start;
+        run_start = escape + 1;
+    }
+    let len = buf.len();
+    buf.copy_within(run_start..len, written);
+    written + len - run_start
+}
+
+/// Offsets of the emulation prevention bytes removed from a NAL unit, used to translate
+/// positions between the NAL unit bytes and its RBSP.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EpbMap {
+    positions: Vec<usize>,
+}
+
+impl EpbMap {
+    /// Offsets of the removed 0x03 bytes in the NAL unit, in increasing order.
+    pub fn positions(&self) -> &[usize] {
+        &self.positions
+    }
+
+    /// Translates an offset in the NAL unit into the RBSP offset of the same byte.
+    ///
+    /// An offset that points at an emulation prevention byte maps to the RBSP byte following it.
+    pub fn nal_to_rbsp(&self, nal_offset: usize) -> usize {
+        nal_offset - self.positions.partition_point(|&pos| pos < nal_offset)
+    }
+
+    /// Translates an offset in the RBSP into the offset of the same byte in the NAL unit.
+    pub fn rbsp_to_nal(&self, rbsp_offset: usize) -> usize {
+        // the k-th removed byte sits right before RBSP offset `positions[k] - k`
+        let (mut lo, mut hi) = (0, self.positions.len());
+        while lo < hi {
+            let mid = (lo + hi) / 2;
+            if self.positions[mid] - mid <= rbsp_offset {
+                lo = mid + 1;
+            } else {
+                hi = mid;
+            }
+        }
+        rbsp_offset + lo
+    }
+
+    /// Translates a bit position in the RBSP into the bit position in the NAL unit.
+    pub fn rbsp_bit_to_nal_bit(&self, rbsp_bit: usize) -> usize {
+        self.rbsp_to_nal(rbsp_bit / 8) * 8 + rbsp_bit % 8
+    }
+}
+
+/// Like [`decode`], but also returns where the emulation prevention bytes were.
+pub fn decode_with_map(src: &[u8]) -> (Vec<u8>, EpbMap) {
+    let mut res = Vec::with_capacity(src.len());
+    let mut positions = Vec::new();
+    let mut run_start = 0;
+    while let Some(escape) = find_escape(src, run_start) {
+        res.extend_from_slice(&src[run_start..escape]);
+        positions.push(escape);
+        run_start = escape + 1;
+    }
+    res.extend_from_slice(&src[run_start..]);
+    (res, EpbMap { positions })
+}
+
+/// Reports every byte sequence of a NAL unit that is forbidden by 7.4.1.
+pub fn validate(src: &[u8]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut from = 0;
+    while let Some(pos) = find_zero_pair(src, from, |b| b <= 3) {
+        let kind = match src[pos] {
+            0 => Some(ViolationKind::ZeroSequence),
+            1 => Some(ViolationKind::StartCode),
+            2 => Some(ViolationKind::ReservedSequence),
+            // 0x000003 may end the NAL unit, after a cabac_zero_word
+            _ => match src.get(pos + 1) {
+                Some(&next) if next > 3 => Some(ViolationKind::InvalidEscape),
+                _ => None,
+            },
+        };
+        if let Some(kind) = kind {
+            violations.push(Violation {
+                offset: pos - 2,
+                kind,
+            });
+        }
+        from = pos - 1;
+    }
+    if src.last() == Some(&0) {
+        violations.push(Violation {
+            offset: src.len() - 1,
+            kind: ViolationKind::TrailingZero,
+        });
+    }
+    violations
+}
+
+/// Like [`decode`], but fails if the NAL unit contains any forbidden byte sequence.
+pub fn decode_strict(src: &[u8]) -> Result<Vec<u8>, ValidationError> {
+    let violations = validate(src);
+    if !violations.is_empty() {
+        return Err(ValidationError { violations });
+    }
+    Ok(decode(src))
+}
+
+/// Which part of 7.4.1 caused [`encode_with_report`] to act.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeRule {
+    /// 0x03 inserted after 0x0000 because the next byte is 0x00 to 0x03.
+    Escape,
+    /// 0x03 inserted into or appended to the cabac_zero_words (0x0000) that end the RBSP.
+    CabacZeroWord,
+    /// Nothing inserted: the RBSP ends with an unpaired 0x00 which no escape can protect,
+    /// so it is not a valid RBSP and the NAL unit ends with 0x00.
+    LoneTrailingZero,
+}
+
+impl EncodeRule {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EncodeRule::Escape => "escape",
+            EncodeRule::CabacZeroWord => "cabac_zero_word",
+            EncodeRule::LoneTrailingZero => "lone_trailing_zero",
+        }
+    }
+}
+
+/// A rule applied by [`encode_with_report`], `offset` being the position in the output of the
+/// inserted 0x03, or of the unprotected 0x00 for [`EncodeRule::LoneTrailingZero`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AppliedRule {
+    pub offset: usize,
+    pub rule: EncodeRule,
+}
+
+/// Inserts emulation prevention bytes into an RBSP, giving the NAL unit bytes.
+///
+/// Besides escaping every 0x000000 to 0x000003 sequence, a final 0x03 is appended when the RBSP
+/// ends with cabac_zero_words.
+pub fn encode(src: &[u8]) -> Vec<u8> {
+    let mut res = Vec::with_capacity(src.len() + src.len() / 64);
+    encode_runs(src, |piece, _| res.extend_from_slice(piece));
+    res
+}
+
+/// Like [`encode`], but fails if the RBSP ends with an unpaired 0x00. No emulation prevention
+/// byte can protect it, so the NAL unit would end with 0x00, which a byte stream parser takes
+/// for trailing_zero_8bits.
+pub fn encode_strict(src: &[u8]) -> Result<Vec<u8>, ValidationError> {
+    let res = encode(src);
+    if res.last() == Some(&0) {
+        return Err(ValidationError {
+            violations: vec![Violation {
+                offset: res.len() - 1,
+                kind: ViolationKind::TrailingZero,
+            }],
+        });
+    }
+    Ok(res)
+}
+
+/// Like [`encode`], but also returns the rules that were applied.
+pub fn encode_with_report(src: &[u8]) -> (Vec<u8>, Vec<AppliedRule>) {
+    // escapes at or after this offset of `src` fall into the trailing zero bytes
+    let zero_tail = src.iter().rposition(|&b| b != 0).map_or(0, |pos| pos + 1);
+    let mut res = Vec::with_capacity(src.len() + src.len() / 64);
+    let mut rules = Vec::new();
+    encode_runs(src, |piece, escape_at| {
+        if let Some(pos) = escape_at {
+            let rule = if pos >= zero_tail + 2 {
+                EncodeRule::CabacZeroWord
+            } else {
+                EncodeRule::Escape
+            };
+            rules.push(AppliedRule {
+                offset: res.len(),
+                rule,
+            });
+        }
+        res.extend_from_slice(piece);
+    });
+    if res.last() == Some(&0) {
+        rules.push(AppliedRule {
+            offset: res.len() - 1,
+            rule: EncodeRule::LoneTrailingZero,
+        });
+    }
+    (res, rules)
+}
+
+/// Like [`encode`], but writes into `dst` and returns the number of bytes written.
+pub fn encode_into(src: &[u8], dst: &mut [u8]) -> Result<usize, BufferTooSmall> {
+    let mut written = 0;
+    let mut overflow = false;
+    encode_runs(src, |piece, _| {
+        if overflow || written + piece.len() > dst.len() {
+            overflow = true;
+            return;
+        }
+        dst[written..written + piece.len()].copy_from_slice(piece);
+        written += piece.len();
+    });
+    if overflow {
+        return Err(BufferTooSmall {
+            needed: encoded_len(src),
+            available: dst.len(),
+        });
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the original byte by byte implementations, kept as reference
+    fn legacy_decode(data: &[u8]) -> Vec<u8> {
+        let mut res = Vec::with_capacity(data.len());
+        let mut i = 0;
+        while i < data.len() {
+            if i + 2 < data.len() && data[i] == 0 && data[i + 1] == 0 && data[i + 2] == 3 {
+                res.extend_from_slice(&[0, 0]);
+                i += 3;
+            } else {
+                res.push(data[i]);
+                i += 1;
+            }
+        }
+        res
+    }
+
+    fn legacy_encode(data: &[u8]) -> Vec<u8> {
+        let mut res = Vec::with_capacity(data.len());
+        let mut i = 0;
+        while i < data.len() {
+            if i + 2 < data.len() && data[i] == 0 && data[i + 1] == 0 && data[i + 2] < 4 {
+                res.extend_from_slice(&[0, 0, 3]);
+                i += 2;
+            } else {
+                res.push(data[i]);
+                i += 1;
+            }
+        }
+        res
+    }
+
+    // the legacy encoder never appended the final 0x03 after cabac_zero_words
+    fn legacy_encode_final(data: &[u8]) -> Vec<u8> {
+        let mut res = legacy_encode(data);
+        if res.ends_with(&[0, 0]) {
+            res.push(3);
+        }
+        res
+    }
+
+    #[test]
+    fn test_matches_legacy() {
+        // xorshift, biased towards the bytes that matter for emulation prevention
+        let mut state = 0x2545_f491_4f6c_dd1d_u64;
+        let alphabet = [0, 0, 0, 0, 1, 2, 3, 4, 0xff];
+        for len in 0..200 {
+            let data: Vec<u8> = (0..len)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 7;
+                    state ^= state << 17;
+                    alphabet[(state % alphabet.len() as u64) as usize]
+                })
+                .collect();
+            assert_eq!(decode(&data), legacy_decode(&data), "{:?}", data);
+            assert_eq!(encode(&data), legacy_encode_final(&data), "{:?}", data);
+        }
+
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.h264")).unwrap();
+        assert_eq!(decode(&data), legacy_decode(&data));
+        assert_eq!(encode(&data), legacy_encode_final(&data));
+    }
+
+    #[test]
+    fn test_decode() {
+        let nal = b"\x65\x00\x00\x03\x01\x00\x00\x03\x00\x00\x03";
+        let rbsp = b"\x65\x00\x00\x01\x00\x00\x00\x00";
+        assert_eq!(decode(nal), rbsp);
+        assert_eq!(decoded_len(nal), rbsp.len());
+
+        let mut dst = [0; 8];
+        assert_eq!(decode_into(nal, &mut dst), Ok(8));
+        assert_eq!(&dst, rbsp);
+        assert_eq!(
+            decode_into(nal, &mut [0; 7]),
+            Err(BufferTooSmall {
+                needed: 8,
+                available: 7
+            })
+        );
+
+        let mut buf = nal.to_vec();
+        let len = decode_in_place(&mut buf);
+        assert_eq!(&buf[..len], rbsp);
+    }
+
+    #[test]
+    fn test_decode_with_map() {
+        let nal = b"\x65\x00\x00\x03\x01\x00\x00\x03\x00\x00\x03";
+        let (rbsp, map) = decode_with_map(nal);
+        assert_eq!(rbsp, decode(nal));
+        assert_eq!(map.positions(), &[3, 7, 10]);
+
+        // every RBSP byte translates to the NAL byte it came from and back
+        for (rbsp_offset, &byte) in rbsp.iter().enumerate() {
+            let nal_offset = map.rbsp_to_nal(rbsp_offset);
+            assert_eq!(nal[nal_offset], byte);
+            assert_eq!(map.nal_to_rbsp(nal_offset), rbsp_offset);
+        }
+        assert_eq!(map.nal_to_rbsp(3), 3);
+        assert_eq!(map.rbsp_to_nal(rbsp.len()), nal.len());
+        assert_eq!(map.rbsp_bit_to_nal_bit(3 * 8 + 5), 4 * 8 + 5);
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(validate(b"\x65\x00\x00\x03\x01\x00\x00\x03").is_empty());
+        assert_eq!(
+            decode_strict(b"\x65\x00\x00\x03\x01"),
+            Ok(b"\x65\x00\x00\x01".to_vec())
+        );
+
+        let nal = b"\x65\x00\x00\x00\x01\x00\x00\x02\x00\x00\x03\x04\x00";
+        let violations = validate(nal);
+        let found: Vec<(usize, ViolationKind)> =
+            violations.iter().map(|v| (v.offset, v.kind)).collect();
+        assert_eq!(
+            found,
+            vec![
+                (1, ViolationKind::ZeroSequence),
+                (2, ViolationKind::StartCode),
+                (5, ViolationKind::ReservedSequence),
+                (8, ViolationKind::InvalidEscape),
+                (12, ViolationKind::TrailingZero),
+            ]
+        );
+        assert_eq!(decode_strict(nal), Err(ValidationError { violations }));
+
+        // the encoder output is valid as long as the RBSP does not end with a lone 0x00
+        let rbsp = b"\x65\x00\x00\x00\x00\x01\x00\x00\x02\x00\x00\x03\x04";
+        assert!(validate(&encode(rbsp)).is_empty());
+    }
+
+    #[test]
+    fn test_encode_with_report() {
+        // slice data ending in rbsp_slice_trailing_bits and two cabac_zero_words
+        let rbsp = b"\x65\x00\x00\x01\x80\x00\x00\x00\x00";
+        let (nal, rules) = encode_with_report(rbsp);
+        assert_eq!(nal, b"\x65\x00\x00\x03\x01\x80\x00\x00\x03\x00\x00\x03");
+        assert_eq!(nal, encode(rbsp));
+        assert_eq!(
+            rules,
+            vec![
+                AppliedRule {
+                    offset: 3,
+                    rule: EncodeRule::Escape
+                },
+                AppliedRule {
+                    offset: 8,
+                    rule: EncodeRule::CabacZeroWord
+                },
+                AppliedRule {
+                    offset: 11,
+                    rule: EncodeRule::CabacZeroWord
+                },
+            ]
+        );
+        assert_eq!(decode(&nal), rbsp);
+        assert!(validate(&nal).is_empty());
+
+        let (nal, rules) = encode_with_report(b"\x65\x00\x00\x00");
+        assert_eq!(nal, b"\x65\x00\x00\x03\x00");
+        assert_eq!(rules[1].rule, EncodeRule::LoneTrailingZero);
+        assert_eq!(rules[1].offset, 4);
+    }
+
+    #[test]
+    fn test_lone_trailing_zero_in_byte_stream() {
+        // ciphertext ending in 0x12, 0x00 is passed through, and the byte stream parser takes the
+        // final zero for trailing_zero_8bits
+        let payload = b"\x65\xb8\x00\x00\x01\x12\x00";
+        let nal = encode(payload);
+        assert_eq!(nal, b"\x65\xb8\x00\x00\x03\x01\x12\x00");
+        let mut stream = b"\x00\x00\x00\x01".to_vec();
+        stream.extend(&nal);
+        stream.extend(b"\x00\x00\x00\x01\x09\xf0");
+        let nals: Vec<_> = crate::annexb::nal_units(&stream).collect();
+        assert_eq!(nals.len(), 2);
+        assert_eq!(
+            decode(&stream[nals[0].start..nals[0].end]),
+            payload[..payload.len() - 1]
+        );
+
+        // strict encoding refuses it
+        assert_eq!(
+            encode_strict(payload),
+            Err(ValidationError {
+                violations: vec![Violation {
+                    offset: 7,
+                    kind: ViolationKind::TrailingZero
+                }]
+            })
+        );
+        let payload = b"\x65\xb8\x00\x00\x01\x12\x00\x80";
+        assert_eq!(encode_strict(payload), Ok(encode(payload)));
+    }
+
+    #[test]
+    fn test_encode() {
+        let rbsp = b"\x65\x00\x00\x01\x00\x00\x00\x00\x00\x04";
+        let nal = b"\x65\x00\x00\x03\x01\x00\x00\x03\x00\x00\x03\x00\x04";
+        assert_eq!(encode(rbsp), nal);
+        assert_eq!(encoded_len(rbsp), nal.len());
+
+        let mut dst = [0; 13];
+        assert_eq!(encode_into(rbsp, &mut dst), Ok(13));
+        assert_eq!(&dst, nal);
+        assert_eq!(
+            encode_into(rbsp, &mut [0; 12]),
+            Err(BufferTooSmall {
+                needed: 13,
+                available: 12
+            })
+        );
+        assert_eq!(decode(&encode(rbsp)), rbsp);
+    }
+}
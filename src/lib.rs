@@ -1,56 +1,2001 @@
-use pyo3::prelude::*;
-use pyo3::types::PyBytes;
+//! H.264/H.265 Annex B scanning, NAL unit header parsing and emulation prevention.
+//!
+//! The modules are plain Rust; the `python` feature adds the `rust_utils` extension module on top.
 
-/// Formats the sum of two numbers as string.
-#[pyfunction]
-fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
-    Ok((a + b).to_string())
-}
+pub mod annexb;
+pub mod avcc;
+pub mod bitreader;
+pub mod bitwriter;
+pub mod conformance;
+pub mod decoder_config;
+pub mod edit;
+pub mod emulation;
+pub mod mapped;
+pub mod nal;
+pub mod parameter_sets;
+pub mod pps;
+pub mod sei;
+pub mod slice;
+pub mod splitter;
+pub mod sps;
+pub mod sps_ext;
+pub mod syntax;
+pub mod trace;
+pub mod vui;
+
+/// The pyo3 bindings, built into the `rust_utils` extension module by maturin.
+#[cfg(feature = "python")]
+mod python {
+    use pyo3::buffer::PyBuffer;
+    use pyo3::create_exception;
+    use pyo3::exceptions::{PyIndexError, PyValueError};
+    use pyo3::prelude::*;
+    use pyo3::types::{PyBytes, PyDict};
+
+    use crate::bitreader::{BitReader, BitReaderError};
+    use crate::bitwriter::{BitWriter, BitWriterError};
+    use crate::{
+        annexb, avcc, bitreader, conformance, decoder_config, edit, emulation, mapped, nal,
+        parameter_sets, pps, splitter, sps, sps_ext, syntax, trace, vui,
+    };
+
+    /// Borrows the contents of a byte buffer (bytes, bytearray, memoryview, ...).
+    fn buffer_as_bytes(buf: &PyBuffer<u8>) -> PyResult<&[u8]> {
+        if !buf.is_c_contiguous() {
+            return Err(PyValueError::new_err("buffer must be C-contiguous"));
+        }
+        if buf.len_bytes() == 0 {
+            return Ok(&[]);
+        }
+        // SAFETY: the buffer is contiguous and stays exported (so it cannot be resized) while `buf` lives.
+        Ok(unsafe { std::slice::from_raw_parts(buf.buf_ptr() as *const u8, buf.len_bytes()) })
+    }
+
+    /// Mutably borrows the contents of a writable byte buffer (bytearray, memoryview, numpy array, ...).
+    #[allow(clippy::mut_from_ref)]
+    fn buffer_as_bytes_mut(buf: &PyBuffer<u8>) -> PyResult<&mut [u8]> {
+        if buf.readonly() {
+            return Err(PyValueError::new_err("buffer must be writable"));
+        }
+        if !buf.is_c_contiguous() {
+            return Err(PyValueError::new_err("buffer must be C-contiguous"));
+        }
+        if buf.len_bytes() == 0 {
+            return Ok(&mut []);
+        }
+        // SAFETY: as above, and the GIL is held while the slice is in use, so nothing else writes to it.
+        Ok(unsafe { std::slice::from_raw_parts_mut(buf.buf_ptr() as *mut u8, buf.len_bytes()) })
+    }
+
+    /// Formats the sum of two numbers as string.
+    #[pyfunction]
+    fn sum_as_string(a: usize, b: usize) -> PyResult<String> {
+        Ok((a + b).to_string())
+    }
+
+    create_exception!(
+        rust_utils,
+        NaluValidationError,
+        PyValueError,
+        "A NAL unit contains byte sequences forbidden by 7.4.1, listed in its `violations` attribute."
+    );
+
+    /// (offset, category) pairs as handed to Python.
+    type OffsetReport = Vec<(usize, &'static str)>;
+
+    fn violation_list(violations: &[emulation::Violation]) -> OffsetReport {
+        violations
+            .iter()
+            .map(|v| (v.offset, v.kind.name()))
+            .collect()
+    }
+
+    fn validation_err(py: Python, err: emulation::ValidationError) -> PyErr {
+        let violations = violation_list(&err.violations);
+        let py_err = NaluValidationError::new_err(err.to_string());
+        if let Err(e) = py_err.value(py).setattr("violations", violations) {
+            return e;
+        }
+        py_err
+    }
+
+    /// Get the Rbsp from the NAL unit.
+    ///
+    /// With `strict`, raises `NaluValidationError` if the NAL unit contains forbidden byte sequences.
+    #[pyfunction]
+    #[pyo3(signature = (data, strict = false))]
+    fn nalu_decode(py: Python, data: &PyBytes, strict: bool) -> PyResult<Py<PyAny>> {
+        let data = data.as_bytes();
+        let rbsp = if strict {
+            emulation::decode_strict(data).map_err(|err| validation_err(py, err))?
+        } else {
+            emulation::decode(data)
+        };
+        Ok(PyBytes::new(py, &rbsp).into())
+    }
+
+    /// Lists the forbidden byte sequences of a NAL unit as (offset, category) tuples.
+    #[pyfunction]
+    fn nalu_validate(data: PyBuffer<u8>) -> PyResult<OffsetReport> {
+        Ok(violation_list(&emulation::validate(buffer_as_bytes(
+            &data,
+        )?)))
+    }
 
-#[pyfunction]
-fn nalu_decode(py: Python, data: &PyBytes) -> Py<PyAny> {
-    let data = data.as_bytes();
-    let mut res = Vec::with_capacity(data.len());
-    let mut i = 0;
-    let i_max = data.len();
-    while i < i_max {
-        if (i + 2 < i_max) && (data[i] == 0) && (data[i + 1] == 0) && (data[i + 2] == 3) {
-            res.push(0);
-            res.push(0);
-            i += 3;
+    /// Escape the Rbsp.
+    ///
+    /// With `strict`, raises `NaluValidationError` if it ends with an unpaired 0x00, which the NAL
+    /// unit would lose to the next start code.
+    #[pyfunction]
+    #[pyo3(signature = (data, strict = false))]
+    fn nalu_encode(py: Python, data: &PyBytes, strict: bool) -> PyResult<Py<PyAny>> {
+        let data = data.as_bytes();
+        let nal = if strict {
+            emulation::encode_strict(data).map_err(|err| validation_err(py, err))?
         } else {
-            res.push(data[i]);
-            i += 1;
+            emulation::encode(data)
+        };
+        Ok(PyBytes::new(py, &nal).into())
+    }
+
+    /// Offsets of the emulation prevention bytes removed by `nalu_decode_with_map`.
+    #[pyclass(name = "EpbMap")]
+    struct PyEpbMap(emulation::EpbMap);
+
+    #[pymethods]
+    impl PyEpbMap {
+        /// Offsets of the removed 0x03 bytes in the NAL unit.
+        #[getter]
+        fn positions(&self) -> Vec<usize> {
+            self.0.positions().to_vec()
+        }
+
+        /// Translates an offset in the NAL unit into the RBSP offset of the same byte.
+        fn nal_to_rbsp(&self, nal_offset: usize) -> usize {
+            self.0.nal_to_rbsp(nal_offset)
+        }
+
+        /// Translates an offset in the RBSP into the offset of the same byte in the NAL unit.
+        fn rbsp_to_nal(&self, rbsp_offset: usize) -> usize {
+            self.0.rbsp_to_nal(rbsp_offset)
+        }
+
+        /// Translates a bit position in the RBSP into the bit position in the NAL unit.
+        fn rbsp_bit_to_nal_bit(&self, rbsp_bit: usize) -> usize {
+            self.0.rbsp_bit_to_nal_bit(rbsp_bit)
+        }
+
+        fn __len__(&self) -> usize {
+            self.0.positions().len()
         }
     }
-    PyBytes::new(py, &res).into()
-}
 
-#[pyfunction]
-fn nalu_encode(py: Python, data: &PyBytes) -> Py<PyAny> {
-    let data = data.as_bytes();
-    let mut res = Vec::with_capacity(data.len());
-    let mut i = 0;
-    let i_max = data.len();
-    while i < i_max {
-        if (i + 2 < i_max) && (data[i] == 0) && (data[i + 1] == 0) && (data[i + 2] < 4) {
-            res.push(0);
-            res.push(0);
-            res.push(3);
-            i += 2;
+    /// Get the Rbsp from the NAL unit, along with the map of removed emulation prevention bytes.
+    #[pyfunction]
+    fn nalu_decode_with_map(py: Python, data: PyBuffer<u8>) -> PyResult<(Py<PyBytes>, PyEpbMap)> {
+        let (rbsp, map) = emulation::decode_with_map(buffer_as_bytes(&data)?);
+        Ok((PyBytes::new(py, &rbsp).into(), PyEpbMap(map)))
+    }
+
+    /// Escape the Rbsp, also returning the applied 7.4.1 rules as (offset, rule) tuples.
+    #[pyfunction]
+    fn nalu_encode_with_report(
+        py: Python,
+        data: PyBuffer<u8>,
+    ) -> PyResult<(Py<PyBytes>, OffsetReport)> {
+        let (nal, rules) = emulation::encode_with_report(buffer_as_bytes(&data)?);
+        let rules = rules.iter().map(|r| (r.offset, r.rule.name())).collect();
+        Ok((PyBytes::new(py, &nal).into(), rules))
+    }
+
+    fn buffer_err(err: emulation::BufferTooSmall) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    fn check_no_overlap(a: &[u8], b: &[u8]) -> PyResult<()> {
+        let a_range = a.as_ptr_range();
+        let b_range = b.as_ptr_range();
+        if a_range.start < b_range.end && b_range.start < a_range.end {
+            return Err(PyValueError::new_err("input and output buffers overlap"));
+        }
+        Ok(())
+    }
+
+    /// Removes emulation prevention bytes from `data` into the writable buffer `out`.
+    /// Returns the number of bytes written.
+    #[pyfunction]
+    fn nalu_decode_into(data: PyBuffer<u8>, out: PyBuffer<u8>) -> PyResult<usize> {
+        let src = buffer_as_bytes(&data)?;
+        let dst = buffer_as_bytes_mut(&out)?;
+        check_no_overlap(src, dst)?;
+        emulation::decode_into(src, dst).map_err(buffer_err)
+    }
+
+    /// Removes emulation prevention bytes from the writable buffer `data` in place.
+    /// Returns the length of the RBSP left at the beginning of the buffer.
+    #[pyfunction]
+    fn nalu_decode_in_place(data: PyBuffer<u8>) -> PyResult<usize> {
+        Ok(emulation::decode_in_place(buffer_as_bytes_mut(&data)?))
+    }
+
+    /// Inserts emulation prevention bytes into `data`, writing into the writable buffer `out`.
+    /// Returns the number of bytes written.
+    #[pyfunction]
+    fn nalu_encode_into(data: PyBuffer<u8>, out: PyBuffer<u8>) -> PyResult<usize> {
+        let src = buffer_as_bytes(&data)?;
+        let dst = buffer_as_bytes_mut(&out)?;
+        check_no_overlap(src, dst)?;
+        emulation::encode_into(src, dst).map_err(buffer_err)
+    }
+
+    fn extension_dict(py: Python, extension: &nal::NalHeaderExtension) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        match extension {
+            nal::NalHeaderExtension::Svc(svc) => {
+                dict.set_item("type", "svc")?;
+                dict.set_item("idr_flag", svc.idr_flag)?;
+                dict.set_item("priority_id", svc.priority_id)?;
+                dict.set_item("no_inter_layer_pred_flag", svc.no_inter_layer_pred_flag)?;
+                dict.set_item("dependency_id", svc.dependency_id)?;
+                dict.set_item("quality_id", svc.quality_id)?;
+                dict.set_item("temporal_id", svc.temporal_id)?;
+                dict.set_item("use_ref_base_pic_flag", svc.use_ref_base_pic_flag)?;
+                dict.set_item("discardable_flag", svc.discardable_flag)?;
+                dict.set_item("output_flag", svc.output_flag)?;
+                dict.set_item("reserved_three_2bits", svc.reserved_three_2bits)?;
+            }
+            nal::NalHeaderExtension::Mvc(mvc) => {
+                dict.set_item("type", "mvc")?;
+                dict.set_item("non_idr_flag", mvc.non_idr_flag)?;
+                dict.set_item("priority_id", mvc.priority_id)?;
+                dict.set_item("view_id", mvc.view_id)?;
+                dict.set_item("temporal_id", mvc.temporal_id)?;
+                dict.set_item("anchor_pic_flag", mvc.anchor_pic_flag)?;
+                dict.set_item("inter_view_flag", mvc.inter_view_flag)?;
+                dict.set_item("reserved_one_bit", mvc.reserved_one_bit)?;
+            }
+            nal::NalHeaderExtension::Avc3d(avc_3d) => {
+                dict.set_item("type", "3davc")?;
+                dict.set_item("view_idx", avc_3d.view_idx)?;
+                dict.set_item("depth_flag", avc_3d.depth_flag)?;
+                dict.set_item("non_idr_flag", avc_3d.non_idr_flag)?;
+                dict.set_item("temporal_id", avc_3d.temporal_id)?;
+                dict.set_item("anchor_pic_flag", avc_3d.anchor_pic_flag)?;
+                dict.set_item("inter_view_flag", avc_3d.inter_view_flag)?;
+            }
+        }
+        Ok(dict.into())
+    }
+
+    /// An H.264 NAL unit header, with the SVC/MVC/3D-AVC extension of NAL unit types 14, 20 and 21.
+    #[pyclass(name = "NalHeader")]
+    struct PyNalHeader(nal::NalHeader);
+
+    #[pymethods]
+    impl PyNalHeader {
+        /// Parses the header at the beginning of a NAL unit.
+        #[staticmethod]
+        fn parse(data: PyBuffer<u8>) -> PyResult<Self> {
+            nal::NalHeader::parse(buffer_as_bytes(&data)?)
+                .map(PyNalHeader)
+                .ok_or_else(|| PyValueError::new_err("NAL unit header is truncated"))
+        }
+
+        /// Clear text description of a nal_unit_type value, e.g. "Sequence parameter set" for 7.
+        #[staticmethod]
+        fn type_description(nal_unit_type: u8) -> &'static str {
+            nal::NalUnitType::from_u8(nal_unit_type).description()
+        }
+
+        #[getter]
+        fn forbidden_zero_bit(&self) -> u8 {
+            self.0.forbidden_zero_bit
+        }
+
+        #[getter]
+        fn nal_ref_idc(&self) -> u8 {
+            self.0.nal_ref_idc
+        }
+
+        #[getter]
+        fn nal_unit_type(&self) -> u8 {
+            self.0.nal_unit_type.value()
+        }
+
+        /// Name of the NAL unit type, e.g. "SPS".
+        #[getter]
+        fn name(&self) -> &'static str {
+            self.0.nal_unit_type.name()
+        }
+
+        /// Clear text description of the NAL unit type, e.g. "Sequence parameter set".
+        #[getter]
+        fn description(&self) -> &'static str {
+            self.0.nal_unit_type.description()
+        }
+
+        /// The header extension fields as a dict with a "type" of "svc", "mvc" or "3davc", or None.
+        #[getter]
+        fn extension(&self, py: Python) -> PyResult<Option<PyObject>> {
+            self.0
+                .extension
+                .as_ref()
+                .map(|ext| extension_dict(py, ext))
+                .transpose()
+        }
+
+        /// Number of bytes taken by the header, extension included.
+        fn __len__(&self) -> usize {
+            self.0.size()
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "NalHeader(nal_ref_idc={}, nal_unit_type={} ({}))",
+                self.0.nal_ref_idc,
+                self.0.nal_unit_type.value(),
+                self.0.nal_unit_type.name()
+            )
+        }
+    }
+
+    /// The two-byte H.265 NAL unit header.
+    #[pyclass(name = "HevcNalHeader")]
+    struct PyHevcNalHeader(nal::HevcNalHeader);
+
+    #[pymethods]
+    impl PyHevcNalHeader {
+        /// Parses the header at the beginning of a NAL unit.
+        #[staticmethod]
+        fn parse(data: PyBuffer<u8>) -> PyResult<Self> {
+            nal::HevcNalHeader::parse(buffer_as_bytes(&data)?)
+                .map(PyHevcNalHeader)
+                .ok_or_else(|| PyValueError::new_err("NAL unit header is truncated"))
+        }
+
+        #[getter]
+        fn forbidden_zero_bit(&self) -> u8 {
+            self.0.forbidden_zero_bit
+        }
+
+        #[getter]
+        fn nal_unit_type(&self) -> u8 {
+            self.0.nal_unit_type.value()
+        }
+
+        #[getter]
+        fn nuh_layer_id(&self) -> u8 {
+            self.0.nuh_layer_id
+        }
+
+        #[getter]
+        fn nuh_temporal_id_plus1(&self) -> u8 {
+            self.0.nuh_temporal_id_plus1
+        }
+
+        #[getter]
+        fn temporal_id(&self) -> u8 {
+            self.0.temporal_id()
+        }
+
+        /// Name of the NAL unit type, e.g. "SPS_NUT".
+        #[getter]
+        fn name(&self) -> &'static str {
+            self.0.nal_unit_type.name()
+        }
+
+        #[getter]
+        fn description(&self) -> &'static str {
+            self.0.nal_unit_type.description()
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "HevcNalHeader(nal_unit_type={} ({}), nuh_layer_id={}, temporal_id={})",
+                self.0.nal_unit_type.value(),
+                self.0.nal_unit_type.name(),
+                self.0.nuh_layer_id,
+                self.0.temporal_id()
+            )
+        }
+    }
+
+    /// Clear text description of an H.264 NAL unit type, like nalutypes.get_description.
+    #[pyfunction]
+    fn nal_unit_type_description(nal_unit_type: u8) -> &'static str {
+        nal::NalUnitType::from_u8(nal_unit_type).description()
+    }
+
+    /// A NAL unit found by `scan_nal_units`.
+    #[pyclass(name = "NalUnit")]
+    struct PyNalUnit(annexb::NalUnit);
+
+    #[pymethods]
+    impl PyNalUnit {
+        /// Offset of the NAL header byte, i.e. the first byte after the start code.
+        #[getter]
+        fn start(&self) -> usize {
+            self.0.start
+        }
+
+        /// Offset one past the last byte of the NAL unit.
+        #[getter]
+        fn end(&self) -> usize {
+            self.0.end
+        }
+
+        #[getter]
+        fn start_code_len(&self) -> usize {
+            self.0.start_code_len
+        }
+
+        #[getter]
+        fn forbidden_zero_bit(&self) -> u8 {
+            self.0.header.forbidden_zero_bit
+        }
+
+        #[getter]
+        fn nal_ref_idc(&self) -> u8 {
+            self.0.header.nal_ref_idc
+        }
+
+        #[getter]
+        fn nal_unit_type(&self) -> u8 {
+            self.0.header.nal_unit_type.value()
+        }
+
+        /// The parsed NAL unit header, extension included.
+        #[getter]
+        fn header(&self) -> PyNalHeader {
+            PyNalHeader(self.0.header)
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "NalUnit(start={}, end={}, start_code_len={}, nal_ref_idc={}, nal_unit_type={})",
+                self.0.start,
+                self.0.end,
+                self.0.start_code_len,
+                self.0.header.nal_ref_idc,
+                self.0.header.nal_unit_type.value()
+            )
+        }
+    }
+
+    #[pyclass]
+    struct NalUnitIter {
+        inner: std::vec::IntoIter<annexb::NalUnit>,
+    }
+
+    #[pymethods]
+    impl NalUnitIter {
+        fn __iter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+            slf
+        }
+
+        fn __next__(mut slf: PyRefMut<'_, Self>) -> Option<PyNalUnit> {
+            slf.inner.next().map(PyNalUnit)
+        }
+
+        fn __len__(&self) -> usize {
+            self.inner.len()
+        }
+    }
+
+    /// Finds every NAL unit of an Annex B byte stream.
+    ///
+    /// With `threads` other than 1, start codes are searched on that many threads (0: one per CPU).
+    #[pyfunction]
+    #[pyo3(signature = (data, threads = 1))]
+    fn scan_nal_units(data: PyBuffer<u8>, threads: usize) -> PyResult<NalUnitIter> {
+        let data = buffer_as_bytes(&data)?;
+        let units = if threads == 1 {
+            annexb::find_nal_units(data)
         } else {
-            res.push(data[i]);
-            i += 1;
+            annexb::find_nal_units_parallel(data, threads)
+        };
+        Ok(NalUnitIter {
+            inner: units.into_iter(),
+        })
+    }
+
+    /// A byte_stream_nal_unit() found by `parse_byte_stream`, with its zero bytes accounted as in B.1.1.
+    #[pyclass(name = "ByteStreamNalUnit")]
+    struct PyByteStreamNalUnit(annexb::ByteStreamNalUnit);
+
+    #[pymethods]
+    impl PyByteStreamNalUnit {
+        /// Offset of the first byte of the unit, leading zero bytes included.
+        #[getter]
+        fn offset(&self) -> usize {
+            self.0.offset
+        }
+
+        /// Offset one past the last trailing zero byte.
+        #[getter]
+        fn end(&self) -> usize {
+            self.0.end()
+        }
+
+        #[getter]
+        fn leading_zero_8bits(&self) -> usize {
+            self.0.leading_zero_8bits
+        }
+
+        #[getter]
+        fn zero_byte(&self) -> bool {
+            self.0.zero_byte
+        }
+
+        #[getter]
+        fn nal(&self) -> PyNalUnit {
+            PyNalUnit(self.0.nal)
+        }
+
+        #[getter]
+        fn trailing_zero_8bits(&self) -> usize {
+            self.0.trailing_zero_8bits
+        }
+
+        /// Frames `nal` with the start code and zero bytes of this unit.
+        fn write(&self, py: Python, nal: PyBuffer<u8>) -> PyResult<Py<PyBytes>> {
+            let mut out = Vec::new();
+            self.0.write(buffer_as_bytes(&nal)?, &mut out);
+            Ok(PyBytes::new(py, &out).into())
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "ByteStreamNalUnit(offset={}, leading_zero_8bits={}, zero_byte={}, nal_start={}, nal_end={}, trailing_zero_8bits={})",
+                self.0.offset,
+                self.0.leading_zero_8bits,
+                self.0.zero_byte,
+                self.0.nal.start,
+                self.0.nal.end,
+                self.0.trailing_zero_8bits
+            )
         }
     }
-    PyBytes::new(py, &res).into()
-}
 
-/// A Python module implemented in Rust.
-#[pymodule]
-fn rust_utils(_py: Python, m: &PyModule) -> PyResult<()> {
-    m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
-    m.add_function(wrap_pyfunction!(nalu_decode, m)?)?;
-    m.add_function(wrap_pyfunction!(nalu_encode, m)?)?;
-    Ok(())
-}
\ No newline at end of file
+    /// Parses an Annex B byte stream following B.1.1, accounting for every zero byte.
+    #[pyfunction]
+    fn parse_byte_stream(data: PyBuffer<u8>) -> PyResult<Vec<PyByteStreamNalUnit>> {
+        let units = annexb::parse_byte_stream(buffer_as_bytes(&data)?)
+            .map_err(|err| PyValueError::new_err(err.to_string()))?;
+        Ok(units.into_iter().map(PyByteStreamNalUnit).collect())
+    }
+
+    /// Incremental Annex B splitter, fed with chunks as they arrive.
+    #[pyclass(name = "NalSplitter")]
+    #[derive(Default)]
+    struct PyNalSplitter(splitter::NalSplitter);
+
+    #[pymethods]
+    impl PyNalSplitter {
+        #[new]
+        fn new() -> Self {
+            Self::default()
+        }
+
+        /// Feeds a chunk and returns the NAL units it completed, without start codes.
+        fn push(&mut self, py: Python, data: PyBuffer<u8>) -> PyResult<Vec<Py<PyBytes>>> {
+            let nals = self.0.push(buffer_as_bytes(&data)?);
+            Ok(nals
+                .iter()
+                .map(|nal| PyBytes::new(py, nal).into())
+                .collect())
+        }
+
+        /// Ends the stream and returns the last NAL unit, or None.
+        fn flush(&mut self, py: Python) -> Option<Py<PyBytes>> {
+            self.0.flush().map(|nal| PyBytes::new(py, &nal).into())
+        }
+    }
+
+    fn avcc_err(err: avcc::AvccError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    /// Splits length-prefixed (AVCC) data into its NAL units.
+    #[pyfunction]
+    #[pyo3(signature = (data, length_size = 4))]
+    fn read_avcc_nal_units(
+        py: Python,
+        data: PyBuffer<u8>,
+        length_size: usize,
+    ) -> PyResult<Vec<Py<PyBytes>>> {
+        let nals = avcc::read_nal_units(buffer_as_bytes(&data)?, length_size).map_err(avcc_err)?;
+        Ok(nals
+            .iter()
+            .map(|nal| PyBytes::new(py, nal).into())
+            .collect())
+    }
+
+    /// Joins NAL units into length-prefixed (AVCC) data.
+    #[pyfunction]
+    #[pyo3(signature = (nals, length_size = 4))]
+    fn write_avcc_nal_units(
+        py: Python,
+        nals: Vec<PyBuffer<u8>>,
+        length_size: usize,
+    ) -> PyResult<Py<PyBytes>> {
+        let nals = nals
+            .iter()
+            .map(buffer_as_bytes)
+            .collect::<PyResult<Vec<_>>>()?;
+        let res = avcc::write_nal_units(nals, length_size).map_err(avcc_err)?;
+        Ok(PyBytes::new(py, &res).into())
+    }
+
+    /// Converts an Annex B byte stream into length-prefixed (AVCC) NAL units.
+    #[pyfunction]
+    #[pyo3(signature = (data, length_size = 4))]
+    fn annexb_to_avcc(py: Python, data: PyBuffer<u8>, length_size: usize) -> PyResult<Py<PyBytes>> {
+        let res = avcc::annexb_to_avcc(buffer_as_bytes(&data)?, length_size).map_err(avcc_err)?;
+        Ok(PyBytes::new(py, &res).into())
+    }
+
+    /// Converts length-prefixed (AVCC) NAL units into an Annex B byte stream.
+    #[pyfunction]
+    #[pyo3(signature = (data, length_size = 4))]
+    fn avcc_to_annexb(py: Python, data: PyBuffer<u8>, length_size: usize) -> PyResult<Py<PyBytes>> {
+        let res = avcc::avcc_to_annexb(buffer_as_bytes(&data)?, length_size).map_err(avcc_err)?;
+        Ok(PyBytes::new(py, &res).into())
+    }
+
+    /// An Annex B file mapped into memory; scanning and RBSP extraction run over the mapping
+    /// without reading the file into Python memory.
+    #[pyclass(name = "MappedStream")]
+    struct PyMappedStream(mapped::MappedStream);
+
+    impl PyMappedStream {
+        fn nal_bytes(&self, nal: &PyNalUnit) -> PyResult<&[u8]> {
+            self.0
+                .data()
+                .get(nal.0.start..nal.0.end)
+                .ok_or_else(|| PyValueError::new_err("NAL unit is outside of the mapped file"))
+        }
+    }
+
+    #[pymethods]
+    impl PyMappedStream {
+        #[new]
+        fn new(path: std::path::PathBuf) -> PyResult<Self> {
+            Ok(PyMappedStream(mapped::MappedStream::open(path)?))
+        }
+
+        /// Finds every NAL unit of the file. The GIL is released while scanning.
+        ///
+        /// With `threads` other than 1, start codes are searched on that many threads (0: one per CPU).
+        #[pyo3(signature = (threads = 1))]
+        fn nal_units(&self, py: Python, threads: usize) -> NalUnitIter {
+            let units = py.allow_threads(|| {
+                if threads == 1 {
+                    self.0.nal_units().collect()
+                } else {
+                    annexb::find_nal_units_parallel(self.0.data(), threads)
+                }
+            });
+            NalUnitIter {
+                inner: units.into_iter(),
+            }
+        }
+
+        /// Copies the bytes start..end of the file.
+        fn read(&self, py: Python, start: usize, end: usize) -> PyResult<Py<PyBytes>> {
+            let data = self
+                .0
+                .data()
+                .get(start..end)
+                .ok_or_else(|| PyValueError::new_err("range is outside of the mapped file"))?;
+            Ok(PyBytes::new(py, data).into())
+        }
+
+        /// The bytes of a NAL unit, header included.
+        fn nal(&self, py: Python, nal: PyRef<PyNalUnit>) -> PyResult<Py<PyBytes>> {
+            Ok(PyBytes::new(py, self.nal_bytes(&nal)?).into())
+        }
+
+        /// The RBSP of a NAL unit, header included.
+        fn rbsp(&self, py: Python, nal: PyRef<PyNalUnit>) -> PyResult<Py<PyBytes>> {
+            Ok(PyBytes::new(py, &emulation::decode(self.nal_bytes(&nal)?)).into())
+        }
+
+        fn __len__(&self) -> usize {
+            self.0.data().len()
+        }
+    }
+
+    create_exception!(
+        rust_utils,
+        ReadError,
+        PyIndexError,
+        "A read runs past the end of a BitStream or hits an invalid Exp-Golomb code."
+    );
+
+    fn bit_reader_err(err: BitReaderError) -> PyErr {
+        match err {
+            BitReaderError::InvalidWidth(_) => PyValueError::new_err(err.to_string()),
+            _ => ReadError::new_err(err.to_string()),
+        }
+    }
+
+    /// Bit reader over RBSP data, a drop-in for the subset of bitstring's BitStream used by
+    /// nalutypes.py: `read("ue")`, `read("se")`, `read("uint:n")`, `pos` and `len`.
+    #[pyclass(name = "BitStream")]
+    struct PyBitStream {
+        data: Vec<u8>,
+        pos: usize,
+        // computed once, so more_rbsp_data() is O(1) per call
+        stop_bit: Option<usize>,
+    }
+
+    impl PyBitStream {
+        /// Runs `op` on a reader at the current position and keeps the position it moved to.
+        fn with_reader<T>(
+            &mut self,
+            op: impl FnOnce(&mut BitReader) -> Result<T, BitReaderError>,
+        ) -> PyResult<T> {
+            let mut reader = BitReader::from_parts(&self.data, self.pos, self.stop_bit);
+            let value = op(&mut reader).map_err(bit_reader_err)?;
+            self.pos = reader.position();
+            Ok(value)
+        }
+
+        fn read_format(&mut self, py: Python, fmt: &str) -> PyResult<PyObject> {
+            let (name, width) = match fmt.split_once(':') {
+                Some((name, width)) => {
+                    let width = width.trim().parse::<u32>().map_err(|_| {
+                        PyValueError::new_err(format!("invalid bit width in {:?}", fmt))
+                    })?;
+                    (name.trim(), Some(width))
+                }
+                None => (fmt.trim(), None),
+            };
+            match (name, width) {
+                ("ue", None) => Ok(self.with_reader(|r| r.ue())?.into_py(py)),
+                ("se", None) => Ok(self.with_reader(|r| r.se())?.into_py(py)),
+                ("bool", None | Some(1)) => Ok(self.with_reader(|r| r.read_flag())?.into_py(py)),
+                ("uint" | "bits", Some(n)) => Ok(self.with_reader(|r| r.read_bits(n))?.into_py(py)),
+                ("int", Some(n)) => {
+                    let value = self.with_reader(|r| r.read_bits(n))?;
+                    // sign-extend the n-bit two's complement value
+                    let shift = 64 - n.max(1);
+                    Ok((((value << shift) as i64) >> shift).into_py(py))
+                }
+                _ => Err(PyValueError::new_err(format!(
+                    "unsupported format {:?}, expected ue, se, bool, uint:n or int:n",
+                    fmt
+                ))),
+            }
+        }
+    }
+
+    #[pymethods]
+    impl PyBitStream {
+        #[new]
+        fn new(data: PyBuffer<u8>) -> PyResult<Self> {
+            let data = buffer_as_bytes(&data)?.to_vec();
+            let stop_bit = bitreader::rbsp_stop_bit(&data);
+            Ok(PyBitStream {
+                data,
+                pos: 0,
+                stop_bit,
+            })
+        }
+
+        /// Reads a syntax element given in bitstring notation: "ue", "se", "bool", "uint:n" or
+        /// "int:n".
+        fn read(&mut self, py: Python, fmt: &str) -> PyResult<PyObject> {
+            self.read_format(py, fmt)
+        }
+
+        /// Like `read`, without moving the position.
+        fn peek(&mut self, py: Python, fmt: &str) -> PyResult<PyObject> {
+            let pos = self.pos;
+            let value = self.read_format(py, fmt);
+            self.pos = pos;
+            value
+        }
+
+        /// u(n)
+        fn u(&mut self, n: u32) -> PyResult<u32> {
+            self.with_reader(|r| r.u(n))
+        }
+
+        /// f(n)
+        fn f(&mut self, n: u32) -> PyResult<u32> {
+            self.with_reader(|r| r.f(n))
+        }
+
+        /// ue(v)
+        fn ue(&mut self) -> PyResult<u32> {
+            self.with_reader(|r| r.ue())
+        }
+
+        /// se(v)
+        fn se(&mut self) -> PyResult<i32> {
+            self.with_reader(|r| r.se())
+        }
+
+        /// te(v) with the syntax element's range 0..=range.
+        fn te(&mut self, range: u32) -> PyResult<u32> {
+            self.with_reader(|r| r.te(range))
+        }
+
+        // usize::is_multiple_of needs Rust 1.87
+        #[allow(clippy::manual_is_multiple_of)]
+        fn byte_aligned(&self) -> bool {
+            self.pos % 8 == 0
+        }
+
+        /// Moves to the next byte boundary and returns the number of bits skipped.
+        fn bytealign(&mut self) -> PyResult<usize> {
+            self.with_reader(|r| Ok(r.byte_align()))
+        }
+
+        /// more_rbsp_data(), in constant time: the rbsp_stop_one_bit is located once per stream.
+        fn more_rbsp_data(&self) -> bool {
+            self.stop_bit.is_some_and(|stop_bit| self.pos < stop_bit)
+        }
+
+        /// Bit position of the rbsp_stop_one_bit, or None if the data has no 1 bit.
+        #[getter]
+        fn stop_bit(&self) -> Option<usize> {
+            self.stop_bit
+        }
+
+        /// Bit position of the next read.
+        #[getter]
+        fn get_pos(&self) -> usize {
+            self.pos
+        }
+
+        #[setter]
+        fn set_pos(&mut self, pos: usize) -> PyResult<()> {
+            if pos > self.data.len() * 8 {
+                return Err(PyValueError::new_err(
+                    "position is past the end of the stream",
+                ));
+            }
+            self.pos = pos;
+            Ok(())
+        }
+
+        /// Length in bits.
+        #[getter]
+        fn len(&self) -> usize {
+            self.data.len() * 8
+        }
+
+        fn __len__(&self) -> usize {
+            self.data.len() * 8
+        }
+
+        fn bytes(&self, py: Python) -> Py<PyBytes> {
+            PyBytes::new(py, &self.data).into()
+        }
+    }
+
+    /// Bit position of the rbsp_stop_one_bit in RBSP data, skipping alignment zero bits and
+    /// cabac_zero_words, or None if the data has no 1 bit. more_rbsp_data() is `pos < stop_bit`.
+    #[pyfunction]
+    fn rbsp_stop_bit(data: PyBuffer<u8>) -> PyResult<Option<usize>> {
+        Ok(bitreader::rbsp_stop_bit(buffer_as_bytes(&data)?))
+    }
+
+    fn bit_writer_err(err: BitWriterError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    /// Bit writer producing RBSP data; `nal_payload()` returns it with emulation prevention bytes.
+    #[pyclass(name = "BitWriter")]
+    #[derive(Default)]
+    struct PyBitWriter(BitWriter);
+
+    #[pymethods]
+    impl PyBitWriter {
+        #[new]
+        fn new() -> Self {
+            Self::default()
+        }
+
+        /// u(n)
+        fn u(&mut self, n: u32, value: u32) -> PyResult<()> {
+            self.0.u(n, value).map_err(bit_writer_err)
+        }
+
+        /// f(n)
+        fn f(&mut self, n: u32, value: u32) -> PyResult<()> {
+            self.0.f(n, value).map_err(bit_writer_err)
+        }
+
+        /// ue(v)
+        fn ue(&mut self, value: u32) -> PyResult<()> {
+            self.0.ue(value).map_err(bit_writer_err)
+        }
+
+        /// se(v)
+        fn se(&mut self, value: i32) -> PyResult<()> {
+            self.0.se(value).map_err(bit_writer_err)
+        }
+
+        /// te(v) with the syntax element's range 0..=range.
+        fn te(&mut self, range: u32, value: u32) -> PyResult<()> {
+            self.0.te(range, value).map_err(bit_writer_err)
+        }
+
+        fn flag(&mut self, flag: bool) {
+            self.0.write_flag(flag)
+        }
+
+        fn byte_aligned(&self) -> bool {
+            self.0.byte_aligned()
+        }
+
+        fn rbsp_trailing_bits(&mut self) {
+            self.0.rbsp_trailing_bits()
+        }
+
+        #[pyo3(signature = (cabac_zero_words = 0))]
+        fn rbsp_slice_trailing_bits(&mut self, cabac_zero_words: usize) {
+            self.0.rbsp_slice_trailing_bits(cabac_zero_words)
+        }
+
+        /// Number of bits written.
+        #[getter]
+        fn pos(&self) -> usize {
+            self.0.position()
+        }
+
+        /// The RBSP written so far, with the last byte zero-padded.
+        fn rbsp(&self, py: Python) -> Py<PyBytes> {
+            PyBytes::new(py, self.0.rbsp()).into()
+        }
+
+        /// The RBSP with emulation prevention bytes inserted, as nalu_encode does.
+        fn nal_payload(&self, py: Python) -> Py<PyBytes> {
+            PyBytes::new(py, &self.0.to_nal_payload()).into()
+        }
+    }
+
+    /// One syntax element of a trace: name, bit offset in the NAL unit, length in bits,
+    /// descriptor, raw code and decoded value.
+    #[pyclass(name = "SyntaxElement")]
+    struct PySyntaxElement(syntax::SyntaxElement);
+
+    #[pymethods]
+    impl PySyntaxElement {
+        #[getter]
+        fn name(&self) -> &str {
+            &self.0.name
+        }
+
+        #[getter]
+        fn bit_offset(&self) -> usize {
+            self.0.bit_offset
+        }
+
+        #[getter]
+        fn bit_len(&self) -> usize {
+            self.0.bit_len
+        }
+
+        /// The descriptor, e.g. "u(8)" or "ue(v)".
+        #[getter]
+        fn descriptor(&self) -> String {
+            self.0.descriptor.to_string()
+        }
+
+        /// The code as read, as a string of binary digits.
+        #[getter]
+        fn code(&self) -> String {
+            self.0.code_bits()
+        }
+
+        #[getter]
+        fn value(&self) -> i64 {
+            self.0.value
+        }
+
+        fn __str__(&self) -> String {
+            self.0.to_string()
+        }
+
+        fn __repr__(&self) -> String {
+            format!(
+                "SyntaxElement(name={:?}, bit_offset={}, bit_len={}, descriptor={:?}, value={})",
+                self.0.name,
+                self.0.bit_offset,
+                self.0.bit_len,
+                self.0.descriptor.to_string(),
+                self.0.value
+            )
+        }
+    }
+
+    /// The syntax element trace of one NAL unit.
+    #[pyclass(name = "NalTrace")]
+    struct PyNalTrace(trace::NalTrace);
+
+    #[pymethods]
+    impl PyNalTrace {
+        #[getter]
+        fn start(&self) -> usize {
+            self.0.nal.start
+        }
+
+        #[getter]
+        fn end(&self) -> usize {
+            self.0.nal.end
+        }
+
+        #[getter]
+        fn nal_unit_type(&self) -> u8 {
+            self.0.nal.header.nal_unit_type.value()
+        }
+
+        #[getter]
+        fn elements(&self) -> Vec<PySyntaxElement> {
+            self.0
+                .elements
+                .iter()
+                .cloned()
+                .map(PySyntaxElement)
+                .collect()
+        }
+
+        /// Why parsing stopped early, or None.
+        #[getter]
+        fn error(&self) -> Option<String> {
+            self.0.error.as_ref().map(|err| err.to_string())
+        }
+
+        fn to_text(&self) -> String {
+            self.0.to_text()
+        }
+
+        fn to_json(&self) -> String {
+            self.0.to_json()
+        }
+    }
+
+    /// Traces the syntax elements of the SPS, SPS extension, subset SPS, PPS, SEI, AUD and slice
+    /// headers of an Annex B stream, like the JM reference decoder's trace_dec.txt.
+    #[pyfunction]
+    fn trace_nal_units(data: PyBuffer<u8>) -> PyResult<Vec<PyNalTrace>> {
+        Ok(trace::trace_stream(buffer_as_bytes(&data)?)
+            .into_iter()
+            .map(PyNalTrace)
+            .collect())
+    }
+
+    /// Like `trace_nal_units`, formatted as "text" or "json".
+    #[pyfunction]
+    #[pyo3(signature = (data, format = "text"))]
+    fn dump_trace(data: PyBuffer<u8>, format: &str) -> PyResult<String> {
+        let traces = trace::trace_stream(buffer_as_bytes(&data)?);
+        match format {
+            "text" => Ok(trace::stream_trace_to_text(&traces)),
+            "json" => Ok(trace::stream_trace_to_json(&traces)),
+            _ => Err(PyValueError::new_err(format!(
+                "unknown trace format {:?}, expected \"text\" or \"json\"",
+                format
+            ))),
+        }
+    }
+
+    fn parse_err(err: syntax::ParseError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    fn hrd_dict(py: Python, hrd: &vui::HrdParameters) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("cpb_cnt_minus1", hrd.cpbs.len().saturating_sub(1))?;
+        dict.set_item("bit_rate_scale", hrd.bit_rate_scale)?;
+        dict.set_item("cpb_size_scale", hrd.cpb_size_scale)?;
+        let mut cpbs = Vec::with_capacity(hrd.cpbs.len());
+        for (i, cpb) in hrd.cpbs.iter().enumerate() {
+            let entry = PyDict::new(py);
+            entry.set_item("bit_rate_value_minus1", cpb.bit_rate_value_minus1)?;
+            entry.set_item("cpb_size_value_minus1", cpb.cpb_size_value_minus1)?;
+            entry.set_item("cbr_flag", cpb.cbr_flag)?;
+            entry.set_item("BitRate", hrd.bit_rate(i))?;
+            entry.set_item("CpbSize", hrd.cpb_size(i))?;
+            cpbs.push(entry);
+        }
+        dict.set_item("cpbs", cpbs)?;
+        dict.set_item(
+            "initial_cpb_removal_delay_length_minus1",
+            hrd.initial_cpb_removal_delay_length_minus1,
+        )?;
+        dict.set_item(
+            "cpb_removal_delay_length_minus1",
+            hrd.cpb_removal_delay_length_minus1,
+        )?;
+        dict.set_item(
+            "dpb_output_delay_length_minus1",
+            hrd.dpb_output_delay_length_minus1,
+        )?;
+        dict.set_item("time_offset_length", hrd.time_offset_length)?;
+        Ok(dict.into())
+    }
+
+    fn vui_dict(py: Python, vui: &vui::VuiParameters) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item(
+            "aspect_ratio_info_present_flag",
+            vui.aspect_ratio_info_present_flag,
+        )?;
+        dict.set_item("aspect_ratio_idc", vui.aspect_ratio_idc)?;
+        dict.set_item("sar_width", vui.sar_width)?;
+        dict.set_item("sar_height", vui.sar_height)?;
+        dict.set_item("overscan_info_present_flag", vui.overscan_info_present_flag)?;
+        dict.set_item("overscan_appropriate_flag", vui.overscan_appropriate_flag)?;
+        dict.set_item(
+            "video_signal_type_present_flag",
+            vui.video_signal_type_present_flag,
+        )?;
+        dict.set_item("video_format", vui.video_format)?;
+        dict.set_item("video_full_range_flag", vui.video_full_range_flag)?;
+        dict.set_item(
+            "colour_description_present_flag",
+            vui.colour_description_present_flag,
+        )?;
+        dict.set_item("colour_primaries", vui.colour_primaries)?;
+        dict.set_item("transfer_characteristics", vui.transfer_characteristics)?;
+        dict.set_item("matrix_coefficients", vui.matrix_coefficients)?;
+        dict.set_item(
+            "chroma_loc_info_present_flag",
+            vui.chroma_loc_info_present_flag,
+        )?;
+        dict.set_item(
+            "chroma_sample_loc_type_top_field",
+            vui.chroma_sample_loc_type_top_field,
+        )?;
+        dict.set_item(
+            "chroma_sample_loc_type_bottom_field",
+            vui.chroma_sample_loc_type_bottom_field,
+        )?;
+        dict.set_item("timing_info_present_flag", vui.timing_info_present_flag)?;
+        dict.set_item("num_units_in_tick", vui.num_units_in_tick)?;
+        dict.set_item("time_scale", vui.time_scale)?;
+        dict.set_item("fixed_frame_rate_flag", vui.fixed_frame_rate_flag)?;
+        dict.set_item(
+            "nal_hrd_parameters_present_flag",
+            vui.nal_hrd_parameters.is_some(),
+        )?;
+        match &vui.nal_hrd_parameters {
+            Some(hrd) => dict.set_item("nal_hrd_parameters", hrd_dict(py, hrd)?)?,
+            None => dict.set_item("nal_hrd_parameters", py.None())?,
+        }
+        dict.set_item(
+            "vcl_hrd_parameters_present_flag",
+            vui.vcl_hrd_parameters.is_some(),
+        )?;
+        match &vui.vcl_hrd_parameters {
+            Some(hrd) => dict.set_item("vcl_hrd_parameters", hrd_dict(py, hrd)?)?,
+            None => dict.set_item("vcl_hrd_parameters", py.None())?,
+        }
+        dict.set_item("low_delay_hrd_flag", vui.low_delay_hrd_flag)?;
+        dict.set_item("pic_struct_present_flag", vui.pic_struct_present_flag)?;
+        dict.set_item("bitstream_restriction_flag", vui.bitstream_restriction_flag)?;
+        dict.set_item(
+            "motion_vectors_over_pic_boundaries_flag",
+            vui.motion_vectors_over_pic_boundaries_flag,
+        )?;
+        dict.set_item("max_bytes_per_pic_denom", vui.max_bytes_per_pic_denom)?;
+        dict.set_item("max_bits_per_mb_denom", vui.max_bits_per_mb_denom)?;
+        dict.set_item(
+            "log2_max_mv_length_horizontal",
+            vui.log2_max_mv_length_horizontal,
+        )?;
+        dict.set_item(
+            "log2_max_mv_length_vertical",
+            vui.log2_max_mv_length_vertical,
+        )?;
+        dict.set_item("max_num_reorder_frames", vui.max_num_reorder_frames)?;
+        dict.set_item("max_dec_frame_buffering", vui.max_dec_frame_buffering)?;
+
+        // derived values
+        dict.set_item("sample_aspect_ratio", vui.sample_aspect_ratio())?;
+        dict.set_item("frame_rate", vui.frame_rate())?;
+        dict.set_item("video_format_name", vui.video_format_name())?;
+        dict.set_item("colour_primaries_name", vui.colour_primaries_name())?;
+        dict.set_item(
+            "transfer_characteristics_name",
+            vui.transfer_characteristics_name(),
+        )?;
+        dict.set_item("matrix_coefficients_name", vui.matrix_coefficients_name())?;
+        Ok(dict.into())
+    }
+
+    fn sps_dict(py: Python, sps: &sps::Sps) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("profile_idc", sps.profile_idc)?;
+        dict.set_item("constraint_set0_flag", sps.constraint_set0_flag)?;
+        dict.set_item("constraint_set1_flag", sps.constraint_set1_flag)?;
+        dict.set_item("constraint_set2_flag", sps.constraint_set2_flag)?;
+        dict.set_item("constraint_set3_flag", sps.constraint_set3_flag)?;
+        dict.set_item("constraint_set4_flag", sps.constraint_set4_flag)?;
+        dict.set_item("constraint_set5_flag", sps.constraint_set5_flag)?;
+        dict.set_item("reserved_zero_2bits", sps.reserved_zero_2bits)?;
+        dict.set_item("level_idc", sps.level_idc)?;
+        dict.set_item("seq_parameter_set_id", sps.seq_parameter_set_id)?;
+        dict.set_item("chroma_format_idc", sps.chroma_format_idc)?;
+        dict.set_item("separate_colour_plane_flag", sps.separate_colour_plane_flag)?;
+        dict.set_item("bit_depth_luma_minus8", sps.bit_depth_luma_minus8)?;
+        dict.set_item("bit_depth_chroma_minus8", sps.bit_depth_chroma_minus8)?;
+        dict.set_item(
+            "qpprime_y_zero_transform_bypass_flag",
+            sps.qpprime_y_zero_transform_bypass_flag,
+        )?;
+        dict.set_item(
+            "seq_scaling_matrix_present_flag",
+            sps.seq_scaling_matrix_present_flag,
+        )?;
+        let lists: Vec<Option<Vec<u8>>> = sps
+            .seq_scaling_lists
+            .iter()
+            .map(|list| list.as_ref().map(|list| list.values.clone()))
+            .collect();
+        dict.set_item("seq_scaling_lists", lists)?;
+        dict.set_item("log2_max_frame_num_minus4", sps.log2_max_frame_num_minus4)?;
+        dict.set_item("pic_order_cnt_type", sps.pic_order_cnt_type)?;
+        dict.set_item(
+            "log2_max_pic_order_cnt_lsb_minus4",
+            sps.log2_max_pic_order_cnt_lsb_minus4,
+        )?;
+        dict.set_item(
+            "delta_pic_order_always_zero_flag",
+            sps.delta_pic_order_always_zero_flag,
+        )?;
+        dict.set_item("offset_for_non_ref_pic", sps.offset_for_non_ref_pic)?;
+        dict.set_item(
+            "offset_for_top_to_bottom_field",
+            sps.offset_for_top_to_bottom_field,
+        )?;
+        dict.set_item(
+            "num_ref_frames_in_pic_order_cnt_cycle",
+            sps.offset_for_ref_frame.len(),
+        )?;
+        dict.set_item("offset_for_ref_frame", sps.offset_for_ref_frame.clone())?;
+        dict.set_item("max_num_ref_frames", sps.max_num_ref_frames)?;
+        dict.set_item(
+            "gaps_in_frame_num_value_allowed_flag",
+            sps.gaps_in_frame_num_value_allowed_flag,
+        )?;
+        dict.set_item("pic_width_in_mbs_minus1", sps.pic_width_in_mbs_minus1)?;
+        dict.set_item(
+            "pic_height_in_map_units_minus1",
+            sps.pic_height_in_map_units_minus1,
+        )?;
+        dict.set_item("frame_mbs_only_flag", sps.frame_mbs_only_flag)?;
+        dict.set_item(
+            "mb_adaptive_frame_field_flag",
+            sps.mb_adaptive_frame_field_flag,
+        )?;
+        dict.set_item("direct_8x8_inference_flag", sps.direct_8x8_inference_flag)?;
+        dict.set_item("frame_cropping_flag", sps.frame_cropping_flag)?;
+        dict.set_item("frame_crop_left_offset", sps.frame_crop_left_offset)?;
+        dict.set_item("frame_crop_right_offset", sps.frame_crop_right_offset)?;
+        dict.set_item("frame_crop_top_offset", sps.frame_crop_top_offset)?;
+        dict.set_item("frame_crop_bottom_offset", sps.frame_crop_bottom_offset)?;
+        dict.set_item(
+            "vui_parameters_present_flag",
+            sps.vui_parameters_present_flag,
+        )?;
+        match &sps.vui_parameters {
+            Some(vui) => dict.set_item("vui_parameters", vui_dict(py, vui)?)?,
+            None => dict.set_item("vui_parameters", py.None())?,
+        }
+
+        // derived values, under their names in the spec
+        dict.set_item("ChromaArrayType", sps.chroma_array_type())?;
+        dict.set_item("BitDepthY", sps.bit_depth_luma())?;
+        dict.set_item("BitDepthC", sps.bit_depth_chroma())?;
+        dict.set_item("MaxFrameNum", sps.max_frame_num())?;
+        dict.set_item("MaxPicOrderCntLsb", sps.max_pic_order_cnt_lsb())?;
+        dict.set_item("PicWidthInMbs", sps.pic_width_in_mbs())?;
+        dict.set_item("PicHeightInMapUnits", sps.pic_height_in_map_units())?;
+        dict.set_item("FrameHeightInMbs", sps.frame_height_in_mbs())?;
+        let (crop_unit_x, crop_unit_y) = sps.crop_units();
+        dict.set_item("CropUnitX", crop_unit_x)?;
+        dict.set_item("CropUnitY", crop_unit_y)?;
+        dict.set_item("width", sps.width())?;
+        dict.set_item("height", sps.height())?;
+        dict.set_item("display_aspect_ratio", sps.display_aspect_ratio())?;
+        dict.set_item("frame_rate", sps.frame_rate())?;
+        Ok(dict.into())
+    }
+
+    /// Parses an SPS NAL unit, header included and emulation prevention bytes not yet removed,
+    /// into a dict of its syntax elements and derived values (ChromaArrayType, PicWidthInMbs,
+    /// FrameHeightInMbs, MaxFrameNum, cropped width and height, ...). The VUI, if present, is a
+    /// nested dict under "vui_parameters".
+    #[pyfunction]
+    fn parse_sps(py: Python, data: PyBuffer<u8>) -> PyResult<PyObject> {
+        let rbsp = emulation::decode(buffer_as_bytes(&data)?);
+        let sps = sps::Sps::from_rbsp(&rbsp).map_err(parse_err)?;
+        sps_dict(py, &sps)
+    }
+
+    fn pps_dict(py: Python, pps: &pps::Pps) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("pic_parameter_set_id", pps.pic_parameter_set_id)?;
+        dict.set_item("seq_parameter_set_id", pps.seq_parameter_set_id)?;
+        dict.set_item("entropy_coding_mode_flag", pps.entropy_coding_mode_flag)?;
+        dict.set_item(
+            "bottom_field_pic_order_in_frame_present_flag",
+            pps.bottom_field_pic_order_in_frame_present_flag,
+        )?;
+        dict.set_item("num_slice_groups_minus1", pps.num_slice_groups_minus1)?;
+        dict.set_item("slice_group_map_type", pps.slice_group_map_type)?;
+        dict.set_item("run_length_minus1", pps.run_length_minus1.clone())?;
+        dict.set_item("top_left", pps.top_left.clone())?;
+        dict.set_item("bottom_right", pps.bottom_right.clone())?;
+        dict.set_item(
+            "slice_group_change_direction_flag",
+            pps.slice_group_change_direction_flag,
+        )?;
+        dict.set_item(
+            "slice_group_change_rate_minus1",
+            pps.slice_group_change_rate_minus1,
+        )?;
+        dict.set_item(
+            "pic_size_in_map_units_minus1",
+            pps.pic_size_in_map_units_minus1,
+        )?;
+        dict.set_item("slice_group_id", pps.slice_group_id.clone())?;
+        dict.set_item(
+            "num_ref_idx_l0_default_active_minus1",
+            pps.num_ref_idx_l0_default_active_minus1,
+        )?;
+        dict.set_item(
+            "num_ref_idx_l1_default_active_minus1",
+            pps.num_ref_idx_l1_default_active_minus1,
+        )?;
+        dict.set_item("weighted_pred_flag", pps.weighted_pred_flag)?;
+        dict.set_item("weighted_bipred_idc", pps.weighted_bipred_idc)?;
+        dict.set_item("pic_init_qp_minus26", pps.pic_init_qp_minus26)?;
+        dict.set_item("pic_init_qs_minus26", pps.pic_init_qs_minus26)?;
+        dict.set_item("chroma_qp_index_offset", pps.chroma_qp_index_offset)?;
+        dict.set_item(
+            "deblocking_filter_control_present_flag",
+            pps.deblocking_filter_control_present_flag,
+        )?;
+        dict.set_item(
+            "constrained_intra_pred_flag",
+            pps.constrained_intra_pred_flag,
+        )?;
+        dict.set_item(
+            "redundant_pic_cnt_present_flag",
+            pps.redundant_pic_cnt_present_flag,
+        )?;
+        dict.set_item("transform_8x8_mode_flag", pps.transform_8x8_mode_flag)?;
+        dict.set_item(
+            "pic_scaling_matrix_present_flag",
+            pps.pic_scaling_matrix_present_flag,
+        )?;
+        let lists: Vec<Option<Vec<u8>>> = pps
+            .pic_scaling_lists
+            .iter()
+            .map(|list| list.as_ref().map(|list| list.values.clone()))
+            .collect();
+        dict.set_item("pic_scaling_lists", lists)?;
+        dict.set_item(
+            "second_chroma_qp_index_offset",
+            pps.second_chroma_qp_index_offset,
+        )?;
+        Ok(dict.into())
+    }
+
+    /// Parses a PPS NAL unit into a dict of its syntax elements. `sps` is the NAL unit of the
+    /// SPS it refers to, needed to read the pic_scaling_matrix of the transform_8x8_mode_flag
+    /// extension.
+    #[pyfunction]
+    #[pyo3(signature = (data, sps = None))]
+    fn parse_pps(py: Python, data: PyBuffer<u8>, sps: Option<PyBuffer<u8>>) -> PyResult<PyObject> {
+        let sps = match &sps {
+            Some(sps) => Some(
+                sps::Sps::from_rbsp(&emulation::decode(buffer_as_bytes(sps)?))
+                    .map_err(parse_err)?,
+            ),
+            None => None,
+        };
+        let rbsp = emulation::decode(buffer_as_bytes(&data)?);
+        let pps = pps::Pps::from_rbsp(&rbsp, |id| match &sps {
+            Some(sps) if sps.seq_parameter_set_id == id => Ok(sps),
+            _ => Err(syntax::ParseError::MissingSps(id)),
+        })
+        .map_err(parse_err)?;
+        pps_dict(py, &pps)
+    }
+
+    /// The SPS and PPS of a stream by id, with activation tracking.
+    #[pyclass(name = "ParameterSets")]
+    struct PyParameterSets(parameter_sets::ParameterSets);
+
+    #[pymethods]
+    impl PyParameterSets {
+        #[new]
+        fn new() -> Self {
+            PyParameterSets(parameter_sets::ParameterSets::new())
+        }
+
+        /// Stores an SPS or PPS NAL unit, or activates the parameter sets a slice refers to.
+        #[pyo3(signature = (data, position = 0))]
+        fn process_nal(&mut self, data: PyBuffer<u8>, position: usize) -> PyResult<()> {
+            self.0
+                .process_nal(buffer_as_bytes(&data)?, position)
+                .map_err(parse_err)
+        }
+
+        /// Processes every NAL unit of an Annex B stream, positioned by byte offset. Returns
+        /// the (offset, message) of the NAL units that could not be processed.
+        fn process_stream(&mut self, data: PyBuffer<u8>) -> PyResult<Vec<(usize, String)>> {
+            let data = buffer_as_bytes(&data)?;
+            let mut errors = Vec::new();
+            for nal in annexb::nal_units(data) {
+                if let Err(err) = self.0.process_nal(&data[nal.start..nal.end], nal.start) {
+                    errors.push((nal.start, err.to_string()));
+                }
+            }
+            Ok(errors)
+        }
+
+        /// Activates the PPS with the given id and its SPS, returning them as dicts.
+        #[pyo3(signature = (pic_parameter_set_id, idr_pic, first_slice_of_picture, position = 0))]
+        fn activate(
+            &mut self,
+            py: Python,
+            pic_parameter_set_id: u32,
+            idr_pic: bool,
+            first_slice_of_picture: bool,
+            position: usize,
+        ) -> PyResult<(PyObject, PyObject)> {
+            let (sps, pps) = self
+                .0
+                .activate(
+                    pic_parameter_set_id,
+                    idr_pic,
+                    first_slice_of_picture,
+                    position,
+                )
+                .map_err(parse_err)?;
+            Ok((sps_dict(py, sps)?, pps_dict(py, pps)?))
+        }
+
+        fn sps(&self, py: Python, id: u32) -> PyResult<Option<PyObject>> {
+            self.0.sps(id).map(|sps| sps_dict(py, sps)).transpose()
+        }
+
+        fn pps(&self, py: Python, id: u32) -> PyResult<Option<PyObject>> {
+            self.0.pps(id).map(|pps| pps_dict(py, pps)).transpose()
+        }
+
+        #[getter]
+        fn active_sps_id(&self) -> Option<u32> {
+            self.0.active_sps().map(|sps| sps.seq_parameter_set_id)
+        }
+
+        #[getter]
+        fn active_pps_id(&self) -> Option<u32> {
+            self.0.active_pps().map(|pps| pps.pic_parameter_set_id)
+        }
+
+        /// (kind, id, position) of every activation, kind being "SPS" or "PPS".
+        #[getter]
+        fn activations(&self) -> Vec<(&'static str, u32, usize)> {
+            self.0
+                .activations()
+                .iter()
+                .map(|a| (a.kind.name(), a.id, a.position))
+                .collect()
+        }
+
+        /// (kind, id, position, slice_position) of every disallowed redefinition of an active
+        /// parameter set.
+        #[getter]
+        fn redefinitions(&self) -> Vec<(&'static str, u32, usize, usize)> {
+            self.0
+                .redefinitions()
+                .iter()
+                .map(|r| (r.kind.name(), r.id, r.position, r.slice_position))
+                .collect()
+        }
+    }
+
+    /// Parses an SPS extension NAL unit (type 13) into a dict of its syntax elements.
+    #[pyfunction]
+    fn parse_sps_extension(py: Python, data: PyBuffer<u8>) -> PyResult<PyObject> {
+        let rbsp = emulation::decode(buffer_as_bytes(&data)?);
+        let ext = sps_ext::SpsExtension::from_rbsp(&rbsp).map_err(parse_err)?;
+        let dict = PyDict::new(py);
+        dict.set_item("seq_parameter_set_id", ext.seq_parameter_set_id)?;
+        dict.set_item("aux_format_idc", ext.aux_format_idc)?;
+        dict.set_item("bit_depth_aux_minus8", ext.bit_depth_aux_minus8)?;
+        dict.set_item("alpha_incr_flag", ext.alpha_incr_flag)?;
+        dict.set_item("alpha_opaque_value", ext.alpha_opaque_value)?;
+        dict.set_item("alpha_transparent_value", ext.alpha_transparent_value)?;
+        dict.set_item("additional_extension_flag", ext.additional_extension_flag)?;
+        Ok(dict.into())
+    }
+
+    fn vui_ext_timing_dict<'py>(
+        py: Python<'py>,
+        timing: &sps_ext::VuiExtTiming,
+    ) -> PyResult<&'py PyDict> {
+        let dict = PyDict::new(py);
+        dict.set_item("timing_info_present_flag", timing.timing_info_present_flag)?;
+        dict.set_item("num_units_in_tick", timing.num_units_in_tick)?;
+        dict.set_item("time_scale", timing.time_scale)?;
+        dict.set_item("fixed_frame_rate_flag", timing.fixed_frame_rate_flag)?;
+        match &timing.nal_hrd_parameters {
+            Some(hrd) => dict.set_item("nal_hrd_parameters", hrd_dict(py, hrd)?)?,
+            None => dict.set_item("nal_hrd_parameters", py.None())?,
+        }
+        match &timing.vcl_hrd_parameters {
+            Some(hrd) => dict.set_item("vcl_hrd_parameters", hrd_dict(py, hrd)?)?,
+            None => dict.set_item("vcl_hrd_parameters", py.None())?,
+        }
+        dict.set_item("low_delay_hrd_flag", timing.low_delay_hrd_flag)?;
+        dict.set_item("pic_struct_present_flag", timing.pic_struct_present_flag)?;
+        Ok(dict)
+    }
+
+    fn svc_extension_dict(py: Python, svc: &sps_ext::SvcSpsExtension) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item(
+            "inter_layer_deblocking_filter_control_present_flag",
+            svc.inter_layer_deblocking_filter_control_present_flag,
+        )?;
+        dict.set_item(
+            "extended_spatial_scalability_idc",
+            svc.extended_spatial_scalability_idc,
+        )?;
+        dict.set_item("chroma_phase_x_plus1_flag", svc.chroma_phase_x_plus1_flag)?;
+        dict.set_item("chroma_phase_y_plus1", svc.chroma_phase_y_plus1)?;
+        dict.set_item(
+            "seq_ref_layer_chroma_phase_x_plus1_flag",
+            svc.seq_ref_layer_chroma_phase_x_plus1_flag,
+        )?;
+        dict.set_item(
+            "seq_ref_layer_chroma_phase_y_plus1",
+            svc.seq_ref_layer_chroma_phase_y_plus1,
+        )?;
+        dict.set_item(
+            "seq_scaled_ref_layer_left_offset",
+            svc.seq_scaled_ref_layer_left_offset,
+        )?;
+        dict.set_item(
+            "seq_scaled_ref_layer_top_offset",
+            svc.seq_scaled_ref_layer_top_offset,
+        )?;
+        dict.set_item(
+            "seq_scaled_ref_layer_right_offset",
+            svc.seq_scaled_ref_layer_right_offset,
+        )?;
+        dict.set_item(
+            "seq_scaled_ref_layer_bottom_offset",
+            svc.seq_scaled_ref_layer_bottom_offset,
+        )?;
+        dict.set_item(
+            "seq_tcoeff_level_prediction_flag",
+            svc.seq_tcoeff_level_prediction_flag,
+        )?;
+        dict.set_item(
+            "adaptive_tcoeff_level_prediction_flag",
+            svc.adaptive_tcoeff_level_prediction_flag,
+        )?;
+        dict.set_item(
+            "slice_header_restriction_flag",
+            svc.slice_header_restriction_flag,
+        )?;
+        match &svc.vui {
+            Some(entries) => {
+                let mut list = Vec::with_capacity(entries.len());
+                for entry in entries {
+                    let item = vui_ext_timing_dict(py, &entry.timing)?;
+                    item.set_item("dependency_id", entry.dependency_id)?;
+                    item.set_item("quality_id", entry.quality_id)?;
+                    item.set_item("temporal_id", entry.temporal_id)?;
+                    list.push(item);
+                }
+                dict.set_item("vui", list)?;
+            }
+            None => dict.set_item("vui", py.None())?,
+        }
+        Ok(dict.into())
+    }
+
+    fn mvc_extension_dict(py: Python, mvc: &sps_ext::MvcSpsExtension) -> PyResult<PyObject> {
+        let dict = PyDict::new(py);
+        dict.set_item("num_views_minus1", mvc.view_ids.len().saturating_sub(1))?;
+        dict.set_item("view_id", mvc.view_ids.clone())?;
+        let mut dependencies = Vec::with_capacity(mvc.dependencies.len());
+        for deps in &mvc.dependencies {
+            let item = PyDict::new(py);
+            item.set_item("anchor_ref_l0", deps.anchor_ref_l0.clone())?;
+            item.set_item("anchor_ref_l1", deps.anchor_ref_l1.clone())?;
+            item.set_item("non_anchor_ref_l0", deps.non_anchor_ref_l0.clone())?;
+            item.set_item("non_anchor_ref_l1", deps.non_anchor_ref_l1.clone())?;
+            dependencies.push(item);
+        }
+        dict.set_item("dependencies", dependencies)?;
+        let mut levels = Vec::with_capacity(mvc.levels.len());
+        for level in &mvc.levels {
+            let item = PyDict::new(py);
+            item.set_item("level_idc", level.level_idc)?;
+            let mut ops = Vec::with_capacity(level.applicable_ops.len());
+            for op in &level.applicable_ops {
+                let op_item = PyDict::new(py);
+                op_item.set_item("temporal_id", op.temporal_id)?;
+                op_item.set_item("target_view_id", op.target_view_ids.clone())?;
+                op_item.set_item("num_views_minus1", op.num_views_minus1)?;
+                ops.push(op_item);
+            }
+            item.set_item("applicable_ops", ops)?;
+            levels.push(item);
+        }
+        dict.set_item("levels", levels)?;
+        match &mvc.mfc_format {
+            Some(mfc) => {
+                let item = PyDict::new(py);
+                item.set_item("mfc_format_idc", mfc.mfc_format_idc)?;
+                item.set_item("default_grid_position_flag", mfc.default_grid_position_flag)?;
+                item.set_item("view0_grid_position_x", mfc.view0_grid_position_x)?;
+                item.set_item("view0_grid_position_y", mfc.view0_grid_position_y)?;
+                item.set_item("view1_grid_position_x", mfc.view1_grid_position_x)?;
+                item.set_item("view1_grid_position_y", mfc.view1_grid_position_y)?;
+                item.set_item("rpu_filter_enabled_flag", mfc.rpu_filter_enabled_flag)?;
+                item.set_item("rpu_field_processing_flag", mfc.rpu_field_processing_flag)?;
+                dict.set_item("mfc_format", item)?;
+            }
+            None => dict.set_item("mfc_format", py.None())?,
+        }
+        match &mvc.vui {
+            Some(vui_ops) => {
+                let mut list = Vec::with_capacity(vui_ops.len());
+                for op in vui_ops {
+                    let item = vui_ext_timing_dict(py, &op.timing)?;
+                    item.set_item("temporal_id", op.temporal_id)?;
+                    item.set_item("target_output_view_id", op.target_output_view_ids.clone())?;
+                    list.push(item);
+                }
+                dict.set_item("vui", list)?;
+            }
+            None => dict.set_item("vui", py.None())?,
+        }
+        Ok(dict.into())
+    }
+
+    /// Parses a subset SPS NAL unit (type 15) into the dict of `parse_sps` for its SPS data,
+    /// plus "extension": "svc", "mvc" or None when the profile's extension is not parsed, and
+    /// the extension's fields under "svc_extension" or "mvc_extension".
+    #[pyfunction]
+    fn parse_subset_sps(py: Python, data: PyBuffer<u8>) -> PyResult<PyObject> {
+        let rbsp = emulation::decode(buffer_as_bytes(&data)?);
+        let subset = sps_ext::SubsetSps::from_rbsp(&rbsp).map_err(parse_err)?;
+        let dict = sps_dict(py, &subset.sps)?;
+        let fields: &PyDict = dict.downcast(py)?;
+        match &subset.extension {
+            sps_ext::SubsetSpsExtension::Svc(svc) => {
+                fields.set_item("extension", "svc")?;
+                fields.set_item("svc_extension", svc_extension_dict(py, svc)?)?;
+            }
+            sps_ext::SubsetSpsExtension::Mvc(mvc) => {
+                fields.set_item("extension", "mvc")?;
+                fields.set_item("mvc_extension", mvc_extension_dict(py, mvc)?)?;
+            }
+            sps_ext::SubsetSpsExtension::Unparsed(_) => fields.set_item("extension", py.None())?,
+        }
+        fields.set_item(
+            "additional_extension2_flag",
+            subset.additional_extension2_flag,
+        )?;
+        Ok(dict)
+    }
+
+    fn violation_tuple(v: conformance::Violation) -> (Option<usize>, &'static str, String) {
+        (v.position, v.rule, v.message)
+    }
+
+    /// Checks an Annex B stream against the Annex A limits of its level and the constraints of
+    /// its profile. Returns (offset, rule, message) tuples, rule being the Table A-1 limit or
+    /// the profile whose constraint is not met, or "syntax" for a NAL unit failing to parse.
+    #[pyfunction]
+    fn check_conformance(
+        data: PyBuffer<u8>,
+    ) -> PyResult<Vec<(Option<usize>, &'static str, String)>> {
+        let data = buffer_as_bytes(&data)?;
+        Ok(conformance::check_stream(data)
+            .into_iter()
+            .map(violation_tuple)
+            .collect())
+    }
+
+    /// Checks an SPS NAL unit against the limits of its level and the constraints of its
+    /// profile, as (None, rule, message) tuples.
+    #[pyfunction]
+    fn check_sps_conformance(
+        data: PyBuffer<u8>,
+    ) -> PyResult<Vec<(Option<usize>, &'static str, String)>> {
+        let rbsp = emulation::decode(buffer_as_bytes(&data)?);
+        let sps = sps::Sps::from_rbsp(&rbsp).map_err(parse_err)?;
+        Ok(conformance::check_sps(&sps)
+            .into_iter()
+            .map(violation_tuple)
+            .collect())
+    }
+
+    fn config_err(err: decoder_config::ConfigRecordError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    fn nal_list(py: Python, nals: &[Vec<u8>]) -> Vec<Py<PyBytes>> {
+        nals.iter()
+            .map(|nal| PyBytes::new(py, nal).into())
+            .collect()
+    }
+
+    /// Builds the avcC record (AVCDecoderConfigurationRecord) from the SPS, PPS and SPS
+    /// extension NAL units of an H.264 Annex B stream.
+    #[pyfunction]
+    fn avc_decoder_configuration_record(py: Python, data: PyBuffer<u8>) -> PyResult<Py<PyBytes>> {
+        let record =
+            decoder_config::AvcDecoderConfigurationRecord::from_stream(buffer_as_bytes(&data)?)
+                .map_err(config_err)?;
+        Ok(PyBytes::new(py, &record.to_bytes().map_err(config_err)?).into())
+    }
+
+    /// Builds the hvcC record (HEVCDecoderConfigurationRecord) from the VPS, SPS and PPS NAL
+    /// units of an H.265 Annex B stream.
+    #[pyfunction]
+    fn hevc_decoder_configuration_record(py: Python, data: PyBuffer<u8>) -> PyResult<Py<PyBytes>> {
+        let record =
+            decoder_config::HevcDecoderConfigurationRecord::from_stream(buffer_as_bytes(&data)?)
+                .map_err(config_err)?;
+        Ok(PyBytes::new(py, &record.to_bytes().map_err(config_err)?).into())
+    }
+
+    /// Parses an avcC record into a dict of its fields, the SPS, PPS and SPS extension NAL
+    /// units as lists of bytes and the "codec" string.
+    #[pyfunction]
+    fn parse_avc_decoder_configuration_record(
+        py: Python,
+        data: PyBuffer<u8>,
+    ) -> PyResult<PyObject> {
+        let record = decoder_config::AvcDecoderConfigurationRecord::parse(buffer_as_bytes(&data)?)
+            .map_err(config_err)?;
+        let dict = PyDict::new(py);
+        dict.set_item("AVCProfileIndication", record.avc_profile_indication)?;
+        dict.set_item("profile_compatibility", record.profile_compatibility)?;
+        dict.set_item("AVCLevelIndication", record.avc_level_indication)?;
+        dict.set_item("lengthSizeMinusOne", record.length_size_minus_one)?;
+        dict.set_item("sps", nal_list(py, &record.sequence_parameter_sets))?;
+        dict.set_item("pps", nal_list(py, &record.picture_parameter_sets))?;
+        match &record.high_profile_fields {
+            Some(high) => {
+                dict.set_item("chroma_format", high.chroma_format)?;
+                dict.set_item("bit_depth_luma_minus8", high.bit_depth_luma_minus8)?;
+                dict.set_item("bit_depth_chroma_minus8", high.bit_depth_chroma_minus8)?;
+                dict.set_item("sps_ext", nal_list(py, &high.sequence_parameter_set_ext))?;
+            }
+            None => dict.set_item("sps_ext", nal_list(py, &[]))?,
+        }
+        dict.set_item("codec", record.codec_string())?;
+        Ok(dict.into())
+    }
+
+    /// Parses an hvcC record into a dict of its fields, "arrays" as a list of
+    /// (array_completeness, nal_unit_type, [bytes]) tuples and the "codec" string.
+    #[pyfunction]
+    fn parse_hevc_decoder_configuration_record(
+        py: Python,
+        data: PyBuffer<u8>,
+    ) -> PyResult<PyObject> {
+        let record = decoder_config::HevcDecoderConfigurationRecord::parse(buffer_as_bytes(&data)?)
+            .map_err(config_err)?;
+        let ptl = &record.profile_tier_level;
+        let dict = PyDict::new(py);
+        dict.set_item("general_profile_space", ptl.general_profile_space)?;
+        dict.set_item("general_tier_flag", ptl.general_tier_flag)?;
+        dict.set_item("general_profile_idc", ptl.general_profile_idc)?;
+        dict.set_item(
+            "general_profile_compatibility_flags",
+            ptl.general_profile_compatibility_flags,
+        )?;
+        dict.set_item(
+            "general_constraint_indicator_flags",
+            ptl.general_constraint_indicator_flags,
+        )?;
+        dict.set_item("general_level_idc", ptl.general_level_idc)?;
+        dict.set_item(
+            "min_spatial_segmentation_idc",
+            record.min_spatial_segmentation_idc,
+        )?;
+        dict.set_item("parallelismType", record.parallelism_type)?;
+        dict.set_item("chromaFormat", record.chroma_format_idc)?;
+        dict.set_item("bitDepthLumaMinus8", record.bit_depth_luma_minus8)?;
+        dict.set_item("bitDepthChromaMinus8", record.bit_depth_chroma_minus8)?;
+        dict.set_item("avgFrameRate", record.avg_frame_rate)?;
+        dict.set_item("constantFrameRate", record.constant_frame_rate)?;
+        dict.set_item("numTemporalLayers", record.num_temporal_layers)?;
+        dict.set_item("temporalIdNested", record.temporal_id_nested)?;
+        dict.set_item("lengthSizeMinusOne", record.length_size_minus_one)?;
+        let arrays: Vec<_> = record
+            .arrays
+            .iter()
+            .map(|array| {
+                (
+                    array.array_completeness,
+                    array.nal_unit_type,
+                    nal_list(py, &array.nal_units),
+                )
+            })
+            .collect();
+        dict.set_item("arrays", arrays)?;
+        dict.set_item("codec", record.codec_string())?;
+        Ok(dict.into())
+    }
+
+    /// The RFC 6381 codecs parameter of an Annex B stream, avc1.PPCCLL for H.264 or
+    /// hvc1.x.y.Lz.B0 style for H.265, from its first SPS.
+    #[pyfunction]
+    #[pyo3(signature = (data, hevc = false))]
+    fn codec_string(data: PyBuffer<u8>, hevc: bool) -> PyResult<String> {
+        let data = buffer_as_bytes(&data)?;
+        if hevc {
+            decoder_config::HevcDecoderConfigurationRecord::from_stream(data)
+                .map(|record| record.codec_string())
+        } else {
+            decoder_config::AvcDecoderConfigurationRecord::from_stream(data)
+                .map(|record| record.codec_string())
+        }
+        .map_err(config_err)
+    }
+
+    fn edit_err(err: edit::EditError) -> PyErr {
+        PyValueError::new_err(err.to_string())
+    }
+
+    /// The (name, value) pairs of a dict, in its order: a field set later may override a
+    /// present flag set by an earlier one.
+    fn edit_fields(fields: &PyDict) -> PyResult<Vec<(String, i64)>> {
+        fields
+            .iter()
+            .map(|(k, v)| Ok((k.extract()?, v.extract()?)))
+            .collect()
+    }
+
+    fn field_refs(fields: &[(String, i64)]) -> Vec<(&str, i64)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.as_str(), *value))
+            .collect()
+    }
+
+    /// Sets the fields of an SPS NAL unit, given as a dict of syntax element names and
+    /// values, and returns the NAL unit written again.
+    #[pyfunction]
+    fn edit_sps(py: Python, data: PyBuffer<u8>, fields: &PyDict) -> PyResult<Py<PyBytes>> {
+        let fields = edit_fields(fields)?;
+        let nal =
+            edit::edit_sps_nal(buffer_as_bytes(&data)?, &field_refs(&fields)).map_err(edit_err)?;
+        Ok(PyBytes::new(py, &nal).into())
+    }
+
+    /// Sets the fields of a PPS NAL unit, which refers to the given SPS NAL unit, and returns
+    /// the NAL unit written again.
+    #[pyfunction]
+    fn edit_pps(
+        py: Python,
+        data: PyBuffer<u8>,
+        sps: PyBuffer<u8>,
+        fields: &PyDict,
+    ) -> PyResult<Py<PyBytes>> {
+        let fields = edit_fields(fields)?;
+        let sps =
+            sps::Sps::from_rbsp(&emulation::decode(buffer_as_bytes(&sps)?)).map_err(parse_err)?;
+        let nal = edit::edit_pps_nal(buffer_as_bytes(&data)?, &sps, &field_refs(&fields))
+            .map_err(edit_err)?;
+        Ok(PyBytes::new(py, &nal).into())
+    }
+
+    /// Sets the fields of every SPS with the given id in an Annex B stream. Returns the new
+    /// stream and the offsets of the SPS NAL units replaced.
+    #[pyfunction]
+    fn rewrite_sps(
+        py: Python,
+        data: PyBuffer<u8>,
+        seq_parameter_set_id: u32,
+        fields: &PyDict,
+    ) -> PyResult<(Py<PyBytes>, Vec<usize>)> {
+        let fields = edit_fields(fields)?;
+        let rewrite = edit::rewrite_sps(
+            buffer_as_bytes(&data)?,
+            seq_parameter_set_id,
+            &field_refs(&fields),
+        )
+        .map_err(edit_err)?;
+        Ok((PyBytes::new(py, &rewrite.data).into(), rewrite.positions))
+    }
+
+    /// Sets the fields of every PPS with the given id in an Annex B stream. Returns the new
+    /// stream and the offsets of the PPS NAL units replaced.
+    #[pyfunction]
+    fn rewrite_pps(
+        py: Python,
+        data: PyBuffer<u8>,
+        pic_parameter_set_id: u32,
+        fields: &PyDict,
+    ) -> PyResult<(Py<PyBytes>, Vec<usize>)> {
+        let fields = edit_fields(fields)?;
+        let rewrite = edit::rewrite_pps(
+            buffer_as_bytes(&data)?,
+            pic_parameter_set_id,
+            &field_refs(&fields),
+        )
+        .map_err(edit_err)?;
+        Ok((PyBytes::new(py, &rewrite.data).into(), rewrite.positions))
+    }
+
+    /// A Python module implemented in Rust.
+    #[pymodule]
+    fn rust_utils(py: Python, m: &PyModule) -> PyResult<()> {
+        m.add("NaluValidationError", py.get_type::<NaluValidationError>())?;
+        m.add("ReadError", py.get_type::<ReadError>())?;
+        m.add_function(wrap_pyfunction!(sum_as_string, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_decode, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_encode, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_decode_into, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_decode_in_place, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_encode_into, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_decode_with_map, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_validate, m)?)?;
+        m.add_function(wrap_pyfunction!(nalu_encode_with_report, m)?)?;
+        m.add_function(wrap_pyfunction!(scan_nal_units, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_byte_stream, m)?)?;
+        m.add_function(wrap_pyfunction!(read_avcc_nal_units, m)?)?;
+        m.add_function(wrap_pyfunction!(write_avcc_nal_units, m)?)?;
+        m.add_function(wrap_pyfunction!(annexb_to_avcc, m)?)?;
+        m.add_function(wrap_pyfunction!(avcc_to_annexb, m)?)?;
+        m.add_function(wrap_pyfunction!(nal_unit_type_description, m)?)?;
+        m.add_function(wrap_pyfunction!(rbsp_stop_bit, m)?)?;
+        m.add_function(wrap_pyfunction!(trace_nal_units, m)?)?;
+        m.add_function(wrap_pyfunction!(dump_trace, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_sps, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_pps, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_sps_extension, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_subset_sps, m)?)?;
+        m.add_function(wrap_pyfunction!(check_conformance, m)?)?;
+        m.add_function(wrap_pyfunction!(check_sps_conformance, m)?)?;
+        m.add_function(wrap_pyfunction!(avc_decoder_configuration_record, m)?)?;
+        m.add_function(wrap_pyfunction!(hevc_decoder_configuration_record, m)?)?;
+        m.add_function(wrap_pyfunction!(parse_avc_decoder_configuration_record, m)?)?;
+        m.add_function(wrap_pyfunction!(
+            parse_hevc_decoder_configuration_record,
+            m
+        )?)?;
+        m.add_function(wrap_pyfunction!(codec_string, m)?)?;
+        m.add_function(wrap_pyfunction!(edit_sps, m)?)?;
+        m.add_function(wrap_pyfunction!(edit_pps, m)?)?;
+        m.add_function(wrap_pyfunction!(rewrite_sps, m)?)?;
+        m.add_function(wrap_pyfunction!(rewrite_pps, m)?)?;
+        m.add_class::<PyNalHeader>()?;
+        m.add_class::<PyHevcNalHeader>()?;
+        m.add_class::<PyNalUnit>()?;
+        m.add_class::<NalUnitIter>()?;
+        m.add_class::<PyByteStreamNalUnit>()?;
+        m.add_class::<PyNalSplitter>()?;
+        m.add_class::<PyEpbMap>()?;
+        m.add_class::<PyMappedStream>()?;
+        m.add_class::<PyBitStream>()?;
+        m.add_class::<PyBitWriter>()?;
+        m.add_class::<PySyntaxElement>()?;
+        m.add_class::<PyNalTrace>()?;
+        m.add_class::<PyParameterSets>()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::annexb::{self, NalUnit, NalUnits};
+use crate::emulation;
+
+/// An Annex B file mapped into memory, so that scanning and RBSP extraction work on the
+/// page cache instead of a copy of the whole file.
+pub struct MappedStream {
+    mmap: Mmap,
+}
+
+impl MappedStream {
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // SAFETY: the mapping is read-only; as with any mapped file, truncating it from another
+        // process while it is mapped is not supported.
+        let mmap = unsafe { Mmap::map(&file)? };
+        Ok(MappedStream { mmap })
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    pub fn nal_units(&self) -> NalUnits<'_> {
+        annexb::nal_units(&self.mmap)
+    }
+
+    /// The bytes of a NAL unit found in this stream, header included.
+    pub fn nal_bytes(&self, nal: &NalUnit) -> &[u8] {
+        &self.mmap[nal.start..nal.end]
+    }
+
+    /// The RBSP of a NAL unit found in this stream, header included.
+    pub fn rbsp(&self, nal: &NalUnit) -> Vec<u8> {
+        emulation::decode(self.nal_bytes(nal))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mapped_stream() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.h264");
+        let data = std::fs::read(path).unwrap();
+        let stream = MappedStream::open(path).unwrap();
+        assert_eq!(stream.data(), &data[..]);
+
+        let nals: Vec<NalUnit> = stream.nal_units().collect();
+        assert_eq!(nals, annexb::find_nal_units(&data));
+        assert_eq!(stream.nal_bytes(&nals[0]), &data[4..29]);
+        assert_eq!(stream.rbsp(&nals[0]), emulation::decode(&data[4..29]));
+
+        assert!(MappedStream::open("does/not/exist.h264").is_err());
+    }
+}
@@ -0,0 +1,539 @@
+/// nal_unit_type values of H.264 (Table 7-1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NalUnitType {
+    Unspecified(u8),
+    SliceNonIdr,
+    SliceDataPartitionA,
+    SliceDataPartitionB,
+    SliceDataPartitionC,
+    SliceIdr,
+    Sei,
+    Sps,
+    Pps,
+    Aud,
+    EndOfSequence,
+    EndOfStream,
+    FillerData,
+    SpsExtension,
+    PrefixNalUnit,
+    SubsetSps,
+    DepthParameterSet,
+    SliceAux,
+    SliceExtension,
+    SliceExtensionDepth,
+    Reserved(u8),
+}
+
+impl NalUnitType {
+    pub fn from_u8(value: u8) -> Self {
+        match value & 0x1f {
+            1 => NalUnitType::SliceNonIdr,
+            2 => NalUnitType::SliceDataPartitionA,
+            3 => NalUnitType::SliceDataPartitionB,
+            4 => NalUnitType::SliceDataPartitionC,
+            5 => NalUnitType::SliceIdr,
+            6 => NalUnitType::Sei,
+            7 => NalUnitType::Sps,
+            8 => NalUnitType::Pps,
+            9 => NalUnitType::Aud,
+            10 => NalUnitType::EndOfSequence,
+            11 => NalUnitType::EndOfStream,
+            12 => NalUnitType::FillerData,
+            13 => NalUnitType::SpsExtension,
+            14 => NalUnitType::PrefixNalUnit,
+            15 => NalUnitType::SubsetSps,
+            16 => NalUnitType::DepthParameterSet,
+            19 => NalUnitType::SliceAux,
+            20 => NalUnitType::SliceExtension,
+            21 => NalUnitType::SliceExtensionDepth,
+            v @ (0 | 24..=31) => NalUnitType::Unspecified(v),
+            v => NalUnitType::Reserved(v),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            NalUnitType::Unspecified(v) | NalUnitType::Reserved(v) => v,
+            NalUnitType::SliceNonIdr => 1,
+            NalUnitType::SliceDataPartitionA => 2,
+            NalUnitType::SliceDataPartitionB => 3,
+            NalUnitType::SliceDataPartitionC => 4,
+            NalUnitType::SliceIdr => 5,
+            NalUnitType::Sei => 6,
+            NalUnitType::Sps => 7,
+            NalUnitType::Pps => 8,
+            NalUnitType::Aud => 9,
+            NalUnitType::EndOfSequence => 10,
+            NalUnitType::EndOfStream => 11,
+            NalUnitType::FillerData => 12,
+            NalUnitType::SpsExtension => 13,
+            NalUnitType::PrefixNalUnit => 14,
+            NalUnitType::SubsetSps => 15,
+            NalUnitType::DepthParameterSet => 16,
+            NalUnitType::SliceAux => 19,
+            NalUnitType::SliceExtension => 20,
+            NalUnitType::SliceExtensionDepth => 21,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NalUnitType::Unspecified(_) => "UNSPECIFIED",
+            NalUnitType::SliceNonIdr => "CODED_SLICE_NON_IDR",
+            NalUnitType::SliceDataPartitionA => "CODED_SLICE_DATA_PARTITION_A",
+            NalUnitType::SliceDataPartitionB => "CODED_SLICE_DATA_PARTITION_B",
+            NalUnitType::SliceDataPartitionC => "CODED_SLICE_DATA_PARTITION_C",
+            NalUnitType::SliceIdr => "CODED_SLICE_IDR",
+            NalUnitType::Sei => "SEI",
+            NalUnitType::Sps => "SPS",
+            NalUnitType::Pps => "PPS",
+            NalUnitType::Aud => "AUD",
+            NalUnitType::EndOfSequence => "END_OF_SEQUENCE",
+            NalUnitType::EndOfStream => "END_OF_STREAM",
+            NalUnitType::FillerData => "FILLER",
+            NalUnitType::SpsExtension => "SPS_EXT",
+            NalUnitType::PrefixNalUnit => "PREFIX",
+            NalUnitType::SubsetSps => "SUBSET_SPS",
+            NalUnitType::DepthParameterSet => "DPS",
+            NalUnitType::SliceAux => "CODED_SLICE_AUX",
+            NalUnitType::SliceExtension => "CODED_SLICE_EXTENSION",
+            NalUnitType::SliceExtensionDepth => "CODED_SLICE_EXTENSION_DEPTH",
+            NalUnitType::Reserved(_) => "RESERVED",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            NalUnitType::Unspecified(_) => "Unspecified",
+            NalUnitType::SliceNonIdr => "Coded slice of a non-IDR picture",
+            NalUnitType::SliceDataPartitionA => "Coded slice data partition A",
+            NalUnitType::SliceDataPartitionB => "Coded slice data partition B",
+            NalUnitType::SliceDataPartitionC => "Coded slice data partition C",
+            NalUnitType::SliceIdr => "Coded slice of an IDR picture",
+            NalUnitType::Sei => "Supplemental enhancement information (SEI)",
+            NalUnitType::Sps => "Sequence parameter set",
+            NalUnitType::Pps => "Picture parameter set",
+            NalUnitType::Aud => "Access unit delimiter",
+            NalUnitType::EndOfSequence => "End of sequence",
+            NalUnitType::EndOfStream => "End of stream",
+            NalUnitType::FillerData => "Filler data",
+            NalUnitType::SpsExtension => "Sequence parameter set extension",
+            NalUnitType::PrefixNalUnit => "Prefix NAL unit",
+            NalUnitType::SubsetSps => "Subset sequence parameter set",
+            NalUnitType::DepthParameterSet => "Depth parameter set",
+            NalUnitType::SliceAux => {
+                "Coded slice of an auxiliary coded picture without partitioning"
+            }
+            NalUnitType::SliceExtension => "Coded slice extension",
+            NalUnitType::SliceExtensionDepth => {
+                "Coded slice extension for a depth view component or a 3D-AVC texture view component"
+            }
+            NalUnitType::Reserved(_) => "Reserved",
+        }
+    }
+
+    /// Whether the type is followed by a nal_unit_header_svc/mvc/3davc_extension().
+    pub fn has_header_extension(&self) -> bool {
+        matches!(
+            self,
+            NalUnitType::PrefixNalUnit
+                | NalUnitType::SliceExtension
+                | NalUnitType::SliceExtensionDepth
+        )
+    }
+}
+
+/// nal_unit_header_svc_extension() (G.7.3.1.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SvcExtension {
+    pub idr_flag: u8,
+    pub priority_id: u8,
+    pub no_inter_layer_pred_flag: u8,
+    pub dependency_id: u8,
+    pub quality_id: u8,
+    pub temporal_id: u8,
+    pub use_ref_base_pic_flag: u8,
+    pub discardable_flag: u8,
+    pub output_flag: u8,
+    pub reserved_three_2bits: u8,
+}
+
+/// nal_unit_header_mvc_extension() (H.7.3.1.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MvcExtension {
+    pub non_idr_flag: u8,
+    pub priority_id: u8,
+    pub view_id: u16,
+    pub temporal_id: u8,
+    pub anchor_pic_flag: u8,
+    pub inter_view_flag: u8,
+    pub reserved_one_bit: u8,
+}
+
+/// nal_unit_header_3davc_extension() (J.7.3.1.1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Avc3dExtension {
+    pub view_idx: u8,
+    pub depth_flag: u8,
+    pub non_idr_flag: u8,
+    pub temporal_id: u8,
+    pub anchor_pic_flag: u8,
+    pub inter_view_flag: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NalHeaderExtension {
+    Svc(SvcExtension),
+    Mvc(MvcExtension),
+    Avc3d(Avc3dExtension),
+}
+
+/// An H.264 NAL unit header, including the extension of NAL unit types 14, 20 and 21.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NalHeader {
+    pub forbidden_zero_bit: u8,
+    pub nal_ref_idc: u8,
+    pub nal_unit_type: NalUnitType,
+    pub extension: Option<NalHeaderExtension>,
+}
+
+/// `len` bits of `value`, after skipping `skip` bits from the top of a `width` bit field.
+fn bits(value: u32, width: u32, skip: u32, len: u32) -> u32 {
+    (value >> (width - skip - len)) & ((1 << len) - 1)
+}
+
+impl NalHeader {
+    /// Splits the first byte of a NAL unit into its 1+2+5 bit fields, ignoring any extension.
+    pub fn from_byte(byte: u8) -> Self {
+        NalHeader {
+            forbidden_zero_bit: byte >> 7,
+            nal_ref_idc: (byte >> 5) & 0x03,
+            nal_unit_type: NalUnitType::from_u8(byte),
+            extension: None,
+        }
+    }
+
+    /// Parses the header at the beginning of a NAL unit. Returns None if `data` is too short
+    /// for it.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let mut header = Self::from_byte(*data.first()?);
+        if !header.nal_unit_type.has_header_extension() {
+            return Some(header);
+        }
+
+        let flag = data.get(1)? >> 7;
+        let extension = if header.nal_unit_type == NalUnitType::SliceExtensionDepth && flag == 1 {
+            let v = u16::from_be_bytes([data[1], *data.get(2)?]) as u32;
+            NalHeaderExtension::Avc3d(Avc3dExtension {
+                view_idx: bits(v, 16, 1, 8) as u8,
+                depth_flag: bits(v, 16, 9, 1) as u8,
+                non_idr_flag: bits(v, 16, 10, 1) as u8,
+                temporal_id: bits(v, 16, 11, 3) as u8,
+                anchor_pic_flag: bits(v, 16, 14, 1) as u8,
+                inter_view_flag: bits(v, 16, 15, 1) as u8,
+            })
+        } else {
+            let v = u32::from_be_bytes([0, data[1], *data.get(2)?, *data.get(3)?]);
+            if flag == 1 {
+                NalHeaderExtension::Svc(SvcExtension {
+                    idr_flag: bits(v, 24, 1, 1) as u8,
+                    priority_id: bits(v, 24, 2, 6) as u8,
+                    no_inter_layer_pred_flag: bits(v, 24, 8, 1) as u8,
+                    dependency_id: bits(v, 24, 9, 3) as u8,
+                    quality_id: bits(v, 24, 12, 4) as u8,
+                    temporal_id: bits(v, 24, 16, 3) as u8,
+                    use_ref_base_pic_flag: bits(v, 24, 19, 1) as u8,
+                    discardable_flag: bits(v, 24, 20, 1) as u8,
+                    output_flag: bits(v, 24, 21, 1) as u8,
+                    reserved_three_2bits: bits(v, 24, 22, 2) as u8,
+                })
+            } else {
+                NalHeaderExtension::Mvc(MvcExtension {
+                    non_idr_flag: bits(v, 24, 1, 1) as u8,
+                    priority_id: bits(v, 24, 2, 6) as u8,
+                    view_id: bits(v, 24, 8, 10) as u16,
+                    temporal_id: bits(v, 24, 18, 3) as u8,
+                    anchor_pic_flag: bits(v, 24, 21, 1) as u8,
+                    inter_view_flag: bits(v, 24, 22, 1) as u8,
+                    reserved_one_bit: bits(v, 24, 23, 1) as u8,
+                })
+            }
+        };
+        header.extension = Some(extension);
+        Some(header)
+    }
+
+    /// Number of bytes taken by the header, extension included.
+    pub fn size(&self) -> usize {
+        match self.extension {
+            None => 1,
+            Some(NalHeaderExtension::Avc3d(_)) => 3,
+            Some(_) => 4,
+        }
+    }
+}
+
+/// nal_unit_type values of H.265 (Table 7-1).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HevcNalUnitType {
+    TrailN,
+    TrailR,
+    TsaN,
+    TsaR,
+    StsaN,
+    StsaR,
+    RadlN,
+    RadlR,
+    RaslN,
+    RaslR,
+    BlaWLp,
+    BlaWRadl,
+    BlaNLp,
+    IdrWRadl,
+    IdrNLp,
+    CraNut,
+    Vps,
+    Sps,
+    Pps,
+    Aud,
+    Eos,
+    Eob,
+    Fd,
+    PrefixSei,
+    SuffixSei,
+    Reserved(u8),
+    Unspecified(u8),
+}
+
+impl HevcNalUnitType {
+    pub fn from_u8(value: u8) -> Self {
+        match value & 0x3f {
+            0 => HevcNalUnitType::TrailN,
+            1 => HevcNalUnitType::TrailR,
+            2 => HevcNalUnitType::TsaN,
+            3 => HevcNalUnitType::TsaR,
+            4 => HevcNalUnitType::StsaN,
+            5 => HevcNalUnitType::StsaR,
+            6 => HevcNalUnitType::RadlN,
+            7 => HevcNalUnitType::RadlR,
+            8 => HevcNalUnitType::RaslN,
+            9 => HevcNalUnitType::RaslR,
+            16 => HevcNalUnitType::BlaWLp,
+            17 => HevcNalUnitType::BlaWRadl,
+            18 => HevcNalUnitType::BlaNLp,
+            19 => HevcNalUnitType::IdrWRadl,
+            20 => HevcNalUnitType::IdrNLp,
+            21 => HevcNalUnitType::CraNut,
+            32 => HevcNalUnitType::Vps,
+            33 => HevcNalUnitType::Sps,
+            34 => HevcNalUnitType::Pps,
+            35 => HevcNalUnitType::Aud,
+            36 => HevcNalUnitType::Eos,
+            37 => HevcNalUnitType::Eob,
+            38 => HevcNalUnitType::Fd,
+            39 => HevcNalUnitType::PrefixSei,
+            40 => HevcNalUnitType::SuffixSei,
+            v @ 48..=63 => HevcNalUnitType::Unspecified(v),
+            v => HevcNalUnitType::Reserved(v),
+        }
+    }
+
+    pub fn value(&self) -> u8 {
+        match *self {
+            HevcNalUnitType::TrailN => 0,
+            HevcNalUnitType::TrailR => 1,
+            HevcNalUnitType::TsaN => 2,
+            HevcNalUnitType::TsaR => 3,
+            HevcNalUnitType::StsaN => 4,
+            HevcNalUnitType::StsaR => 5,
+            HevcNalUnitType::RadlN => 6,
+            HevcNalUnitType::RadlR => 7,
+            HevcNalUnitType::RaslN => 8,
+            HevcNalUnitType::RaslR => 9,
+            HevcNalUnitType::BlaWLp => 16,
+            HevcNalUnitType::BlaWRadl => 17,
+            HevcNalUnitType::BlaNLp => 18,
+            HevcNalUnitType::IdrWRadl => 19,
+            HevcNalUnitType::IdrNLp => 20,
+            HevcNalUnitType::CraNut => 21,
+            HevcNalUnitType::Vps => 32,
+            HevcNalUnitType::Sps => 33,
+            HevcNalUnitType::Pps => 34,
+            HevcNalUnitType::Aud => 35,
+            HevcNalUnitType::Eos => 36,
+            HevcNalUnitType::Eob => 37,
+            HevcNalUnitType::Fd => 38,
+            HevcNalUnitType::PrefixSei => 39,
+            HevcNalUnitType::SuffixSei => 40,
+            HevcNalUnitType::Reserved(v) | HevcNalUnitType::Unspecified(v) => v,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HevcNalUnitType::TrailN => "TRAIL_N",
+            HevcNalUnitType::TrailR => "TRAIL_R",
+            HevcNalUnitType::TsaN => "TSA_N",
+            HevcNalUnitType::TsaR => "TSA_R",
+            HevcNalUnitType::StsaN => "STSA_N",
+            HevcNalUnitType::StsaR => "STSA_R",
+            HevcNalUnitType::RadlN => "RADL_N",
+            HevcNalUnitType::RadlR => "RADL_R",
+            HevcNalUnitType::RaslN => "RASL_N",
+            HevcNalUnitType::RaslR => "RASL_R",
+            HevcNalUnitType::BlaWLp => "BLA_W_LP",
+            HevcNalUnitType::BlaWRadl => "BLA_W_RADL",
+            HevcNalUnitType::BlaNLp => "BLA_N_LP",
+            HevcNalUnitType::IdrWRadl => "IDR_W_RADL",
+            HevcNalUnitType::IdrNLp => "IDR_N_LP",
+            HevcNalUnitType::CraNut => "CRA_NUT",
+            HevcNalUnitType::Vps => "VPS_NUT",
+            HevcNalUnitType::Sps => "SPS_NUT",
+            HevcNalUnitType::Pps => "PPS_NUT",
+            HevcNalUnitType::Aud => "AUD_NUT",
+            HevcNalUnitType::Eos => "EOS_NUT",
+            HevcNalUnitType::Eob => "EOB_NUT",
+            HevcNalUnitType::Fd => "FD_NUT",
+            HevcNalUnitType::PrefixSei => "PREFIX_SEI_NUT",
+            HevcNalUnitType::SuffixSei => "SUFFIX_SEI_NUT",
+            HevcNalUnitType::Reserved(_) => "RESERVED",
+            HevcNalUnitType::Unspecified(_) => "UNSPECIFIED",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            HevcNalUnitType::TrailN | HevcNalUnitType::TrailR => {
+                "Coded slice segment of a non-TSA, non-STSA trailing picture"
+            }
+            HevcNalUnitType::TsaN | HevcNalUnitType::TsaR => "Coded slice segment of a TSA picture",
+            HevcNalUnitType::StsaN | HevcNalUnitType::StsaR => {
+                "Coded slice segment of an STSA picture"
+            }
+            HevcNalUnitType::RadlN | HevcNalUnitType::RadlR => {
+                "Coded slice segment of a RADL picture"
+            }
+            HevcNalUnitType::RaslN | HevcNalUnitType::RaslR => {
+                "Coded slice segment of a RASL picture"
+            }
+            HevcNalUnitType::BlaWLp | HevcNalUnitType::BlaWRadl | HevcNalUnitType::BlaNLp => {
+                "Coded slice segment of a BLA picture"
+            }
+            HevcNalUnitType::IdrWRadl | HevcNalUnitType::IdrNLp => {
+                "Coded slice segment of an IDR picture"
+            }
+            HevcNalUnitType::CraNut => "Coded slice segment of a CRA picture",
+            HevcNalUnitType::Vps => "Video parameter set",
+            HevcNalUnitType::Sps => "Sequence parameter set",
+            HevcNalUnitType::Pps => "Picture parameter set",
+            HevcNalUnitType::Aud => "Access unit delimiter",
+            HevcNalUnitType::Eos => "End of sequence",
+            HevcNalUnitType::Eob => "End of bitstream",
+            HevcNalUnitType::Fd => "Filler data",
+            HevcNalUnitType::PrefixSei => "Supplemental enhancement information (prefix SEI)",
+            HevcNalUnitType::SuffixSei => "Supplemental enhancement information (suffix SEI)",
+            HevcNalUnitType::Reserved(_) => "Reserved",
+            HevcNalUnitType::Unspecified(_) => "Unspecified",
+        }
+    }
+}
+
+/// The two-byte H.265 NAL unit header (7.3.1.2).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HevcNalHeader {
+    pub forbidden_zero_bit: u8,
+    pub nal_unit_type: HevcNalUnitType,
+    pub nuh_layer_id: u8,
+    pub nuh_temporal_id_plus1: u8,
+}
+
+impl HevcNalHeader {
+    /// Parses the header at the beginning of a NAL unit. Returns None if `data` is too short.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let v = u16::from_be_bytes([*data.first()?, *data.get(1)?]) as u32;
+        Some(HevcNalHeader {
+            forbidden_zero_bit: bits(v, 16, 0, 1) as u8,
+            nal_unit_type: HevcNalUnitType::from_u8(bits(v, 16, 1, 6) as u8),
+            nuh_layer_id: bits(v, 16, 7, 6) as u8,
+            nuh_temporal_id_plus1: bits(v, 16, 13, 3) as u8,
+        })
+    }
+
+    /// TemporalId, derived as nuh_temporal_id_plus1 - 1.
+    pub fn temporal_id(&self) -> u8 {
+        self.nuh_temporal_id_plus1.saturating_sub(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nal_unit_type() {
+        for value in 0..32 {
+            assert_eq!(NalUnitType::from_u8(value).value(), value);
+        }
+        for value in 0..64 {
+            assert_eq!(HevcNalUnitType::from_u8(value).value(), value);
+        }
+        assert_eq!(NalUnitType::from_u8(7), NalUnitType::Sps);
+        assert_eq!(NalUnitType::from_u8(17), NalUnitType::Reserved(17));
+        assert_eq!(NalUnitType::from_u8(30), NalUnitType::Unspecified(30));
+        assert_eq!(NalUnitType::Sps.description(), "Sequence parameter set");
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header = NalHeader::parse(b"\x67\x64").unwrap();
+        assert_eq!(header.nal_ref_idc, 3);
+        assert_eq!(header.nal_unit_type, NalUnitType::Sps);
+        assert_eq!(header.extension, None);
+        assert_eq!(header.size(), 1);
+        assert_eq!(NalHeader::parse(b""), None);
+
+        // prefix NAL unit, svc_extension_flag = 1
+        let header = NalHeader::parse(b"\x6e\xc1\x23\x45").unwrap();
+        assert_eq!(header.nal_unit_type, NalUnitType::PrefixNalUnit);
+        assert_eq!(header.size(), 4);
+        let Some(NalHeaderExtension::Svc(svc)) = header.extension else {
+            panic!("expected an SVC extension, got {:?}", header.extension)
+        };
+        assert_eq!(svc.idr_flag, 1);
+        assert_eq!(svc.priority_id, 1);
+        assert_eq!(svc.no_inter_layer_pred_flag, 0);
+        assert_eq!(svc.dependency_id, 2);
+        assert_eq!(svc.quality_id, 3);
+        assert_eq!(svc.temporal_id, 2);
+        assert_eq!(svc.output_flag, 1);
+        assert_eq!(svc.reserved_three_2bits, 1);
+
+        // MVC coded slice extension of an anchor picture in view 5
+        let header = NalHeader::parse(b"\x74\x40\x01\x47").unwrap();
+        let Some(NalHeaderExtension::Mvc(mvc)) = header.extension else {
+            panic!("expected an MVC extension, got {:?}", header.extension)
+        };
+        assert_eq!(mvc.non_idr_flag, 1);
+        assert_eq!(mvc.priority_id, 0);
+        assert_eq!(mvc.view_id, 5);
+        assert_eq!(mvc.temporal_id, 0);
+        assert_eq!(mvc.anchor_pic_flag, 1);
+        assert_eq!(mvc.inter_view_flag, 1);
+        assert_eq!(mvc.reserved_one_bit, 1);
+        assert_eq!(NalHeader::parse(b"\x74\x40\x01"), None);
+    }
+
+    #[test]
+    fn test_parse_hevc_header() {
+        let header = HevcNalHeader::parse(b"\x40\x01").unwrap();
+        assert_eq!(header.nal_unit_type, HevcNalUnitType::Vps);
+        assert_eq!(header.nuh_layer_id, 0);
+        assert_eq!(header.temporal_id(), 0);
+
+        let header = HevcNalHeader::parse(b"\x02\x0b").unwrap();
+        assert_eq!(header.nal_unit_type, HevcNalUnitType::TrailR);
+        assert_eq!(header.nuh_layer_id, 1);
+        assert_eq!(header.temporal_id(), 2);
+        assert_eq!(HevcNalHeader::parse(b"\x40"), None);
+    }
+}
@@ -0,0 +1,356 @@
+//! Parameter set store: the SPS and PPS of a stream by id, which of them are active, and
+//! redefinitions that change an active parameter set where 7.4.1.2.1 does not allow it.
+
+use crate::emulation;
+use crate::nal::{NalHeader, NalUnitType};
+use crate::pps::Pps;
+use crate::slice;
+use crate::sps::Sps;
+use crate::syntax::ParseError;
+
+/// Number of seq_parameter_set_id values.
+pub const MAX_SPS: usize = 32;
+/// Number of pic_parameter_set_id values.
+pub const MAX_PPS: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParameterSetKind {
+    Sps,
+    Pps,
+}
+
+impl ParameterSetKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ParameterSetKind::Sps => "SPS",
+            ParameterSetKind::Pps => "PPS",
+        }
+    }
+}
+
+/// A parameter set becoming active for the slice at `position`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Activation {
+    pub kind: ParameterSetKind,
+    pub id: u32,
+    pub position: usize,
+}
+
+/// An active parameter set whose content was changed by the NAL unit at `position`, then used
+/// by the slice at `slice_position`: an SPS changed within a coded video sequence, or a PPS
+/// changed within a picture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Redefinition {
+    pub kind: ParameterSetKind,
+    pub id: u32,
+    pub position: usize,
+    pub slice_position: usize,
+}
+
+/// The SPS and PPS received so far, keyed by id.
+///
+/// Positions are whatever the caller uses to identify NAL units, usually their byte offsets
+/// in the stream.
+#[derive(Debug)]
+pub struct ParameterSets {
+    sps: Vec<Option<Sps>>,
+    pps: Vec<Option<Pps>>,
+    active_sps: Option<u32>,
+    active_pps: Option<u32>,
+    // changes of the active SPS or PPS not checked against the next slice yet
+    pending: Vec<(ParameterSetKind, u32, usize)>,
+    activations: Vec<Activation>,
+    redefinitions: Vec<Redefinition>,
+}
+
+impl Default for ParameterSets {
+    fn default() -> Self {
+        ParameterSets {
+            sps: vec![None; MAX_SPS],
+            pps: vec![None; MAX_PPS],
+            active_sps: None,
+            active_pps: None,
+            pending: Vec::new(),
+            activations: Vec::new(),
+            redefinitions: Vec::new(),
+        }
+    }
+}
+
+impl ParameterSets {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn mark_pending(&mut self, kind: ParameterSetKind, id: u32, position: usize) {
+        self.pending.retain(|&(k, i, _)| (k, i) != (kind, id));
+        self.pending.push((kind, id, position));
+    }
+
+    /// Stores an SPS received at `position`, replacing the one with the same id.
+    pub fn insert_sps(&mut self, sps: Sps, position: usize) -> Result<(), ParseError> {
+        let id = sps.seq_parameter_set_id;
+        let slot = self
+            .sps
+            .get_mut(id as usize)
+            .ok_or(ParseError::OutOfRange {
+                name: "seq_parameter_set_id",
+                value: id as i64,
+            })?;
+        let changed = slot.as_ref().is_some_and(|old| *old != sps);
+        *slot = Some(sps);
+        if changed && self.active_sps == Some(id) {
+            self.mark_pending(ParameterSetKind::Sps, id, position);
+        }
+        Ok(())
+    }
+
+    /// Stores a PPS received at `position`, replacing the one with the same id.
+    pub fn insert_pps(&mut self, pps: Pps, position: usize) -> Result<(), ParseError> {
+        let id = pps.pic_parameter_set_id;
+        let slot = self
+            .pps
+            .get_mut(id as usize)
+            .ok_or(ParseError::OutOfRange {
+                name: "pic_parameter_set_id",
+                value: id as i64,
+            })?;
+        let changed = slot.as_ref().is_some_and(|old| *old != pps);
+        *slot = Some(pps);
+        if changed && self.active_pps == Some(id) {
+            self.mark_pending(ParameterSetKind::Pps, id, position);
+        }
+        Ok(())
+    }
+
+    pub fn sps(&self, id: u32) -> Option<&Sps> {
+        self.sps.get(id as usize)?.as_ref()
+    }
+
+    pub fn pps(&self, id: u32) -> Option<&Pps> {
+        self.pps.get(id as usize)?.as_ref()
+    }
+
+    pub fn active_sps(&self) -> Option<&Sps> {
+        self.sps(self.active_sps?)
+    }
+
+    pub fn active_pps(&self) -> Option<&Pps> {
+        self.pps(self.active_pps?)
+    }
+
+    /// The PPS with the given id and the SPS it refers to, without activating them.
+    pub fn resolve(&self, pic_parameter_set_id: u32) -> Result<(&Sps, &Pps), ParseError> {
+        let pps = self
+            .pps(pic_parameter_set_id)
+            .ok_or(ParseError::MissingPps(pic_parameter_set_id))?;
+        let sps = self
+            .sps(pps.seq_parameter_set_id)
+            .ok_or(ParseError::MissingSps(pps.seq_parameter_set_id))?;
+        Ok((sps, pps))
+    }
+
+    /// Activates the parameter sets referred to by the slice at `position`.
+    ///
+    /// `idr_pic` tells whether the slice starts a coded video sequence, where the active SPS
+    /// may change content, and `first_slice_of_picture` whether it starts a picture, where the
+    /// active PPS may.
+    pub fn activate(
+        &mut self,
+        pic_parameter_set_id: u32,
+        idr_pic: bool,
+        first_slice_of_picture: bool,
+        position: usize,
+    ) -> Result<(&Sps, &Pps), ParseError> {
+        let sps_id = self.resolve(pic_parameter_set_id)?.1.seq_parameter_set_id;
+
+        let mut sps_changed = false;
+        let mut pps_changed = false;
+        for (kind, id, redefined_at) in std::mem::take(&mut self.pending) {
+            let allowed = match kind {
+                ParameterSetKind::Sps => {
+                    sps_changed |= id == sps_id;
+                    idr_pic
+                }
+                ParameterSetKind::Pps => {
+                    pps_changed |= id == pic_parameter_set_id;
+                    first_slice_of_picture
+                }
+            };
+            if !allowed {
+                self.redefinitions.push(Redefinition {
+                    kind,
+                    id,
+                    position: redefined_at,
+                    slice_position: position,
+                });
+            }
+        }
+
+        if sps_changed || self.active_sps != Some(sps_id) {
+            self.active_sps = Some(sps_id);
+            self.activations.push(Activation {
+                kind: ParameterSetKind::Sps,
+                id: sps_id,
+                position,
+            });
+        }
+        if pps_changed || self.active_pps != Some(pic_parameter_set_id) {
+            self.active_pps = Some(pic_parameter_set_id);
+            self.activations.push(Activation {
+                kind: ParameterSetKind::Pps,
+                id: pic_parameter_set_id,
+                position,
+            });
+        }
+        self.resolve(pic_parameter_set_id)
+    }
+
+    /// Stores the SPS or PPS, or activates the parameter sets of the slice, in the NAL unit at
+    /// `position`, given without its start code. Other NAL unit types are ignored.
+    pub fn process_nal(&mut self, nal: &[u8], position: usize) -> Result<(), ParseError> {
+        let Some(header) = NalHeader::parse(nal) else {
+            return Ok(());
+        };
+        match header.nal_unit_type {
+            NalUnitType::Sps => {
+                let sps = Sps::from_rbsp(&emulation::decode(nal))?;
+                self.insert_sps(sps, position)
+            }
+            NalUnitType::Pps => {
+                let pps = Pps::from_rbsp(&emulation::decode(nal), |id| {
+                    self.sps(id).ok_or(ParseError::MissingSps(id))
+                })?;
+                self.insert_pps(pps, position)
+            }
+            NalUnitType::SliceNonIdr | NalUnitType::SliceIdr => {
+                let (first_mb_in_slice, _, pic_parameter_set_id) = slice::slice_ids(nal)?;
+                let idr_pic = header.nal_unit_type == NalUnitType::SliceIdr;
+                self.activate(
+                    pic_parameter_set_id,
+                    idr_pic,
+                    first_mb_in_slice == 0,
+                    position,
+                )?;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Every activation so far, in stream order.
+    pub fn activations(&self) -> &[Activation] {
+        &self.activations
+    }
+
+    /// Every disallowed redefinition so far, in stream order.
+    pub fn redefinitions(&self) -> &[Redefinition] {
+        &self.redefinitions
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+
+    #[test]
+    fn test_activation() {
+        let mut sets = ParameterSets::new();
+        sets.process_nal(&HIGH_SPS, 0).unwrap();
+        assert_eq!(
+            sets.process_nal(&[0x65, 0x88, 0x84], 10),
+            Err(ParseError::MissingPps(0))
+        );
+        sets.process_nal(&CABAC_PPS, 40).unwrap();
+        // a second PPS with id 1
+        let mut pps = sets.pps(0).unwrap().clone();
+        pps.pic_parameter_set_id = 1;
+        sets.insert_pps(pps, 50).unwrap();
+
+        sets.process_nal(&[0x65, 0x88, 0x84], 60).unwrap();
+        sets.process_nal(&[0x41, 0x9a, 0x00], 70).unwrap();
+        // first_mb_in_slice 0, slice_type 0, pic_parameter_set_id 1
+        sets.process_nal(&[0x41, 0xd0], 80).unwrap();
+        assert_eq!(sets.active_pps().unwrap().pic_parameter_set_id, 1);
+        assert_eq!(
+            sets.activations(),
+            [
+                Activation {
+                    kind: ParameterSetKind::Sps,
+                    id: 0,
+                    position: 60
+                },
+                Activation {
+                    kind: ParameterSetKind::Pps,
+                    id: 0,
+                    position: 60
+                },
+                Activation {
+                    kind: ParameterSetKind::Pps,
+                    id: 1,
+                    position: 80
+                },
+            ]
+        );
+
+        // repeating the same content is no redefinition
+        sets.process_nal(&HIGH_SPS, 90).unwrap();
+        sets.process_nal(&[0x41, 0xd0], 100).unwrap();
+        assert_eq!(sets.activations().len(), 3);
+        assert!(sets.redefinitions().is_empty());
+    }
+
+    #[test]
+    fn test_redefinition() {
+        let mut sets = ParameterSets::new();
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+        sets.insert_sps(sps.clone(), 0).unwrap();
+        sets.process_nal(&CABAC_PPS, 1).unwrap();
+        sets.activate(0, true, true, 2).unwrap();
+
+        // a new PPS content before the next picture is fine, within a picture it is not
+        let mut pps = sets.pps(0).unwrap().clone();
+        pps.pic_init_qp_minus26 = 0;
+        sets.insert_pps(pps.clone(), 3).unwrap();
+        sets.activate(0, false, true, 4).unwrap();
+        assert!(sets.redefinitions().is_empty());
+        pps.pic_init_qp_minus26 = 1;
+        sets.insert_pps(pps, 5).unwrap();
+        sets.activate(0, false, false, 6).unwrap();
+        assert_eq!(
+            sets.redefinitions(),
+            [Redefinition {
+                kind: ParameterSetKind::Pps,
+                id: 0,
+                position: 5,
+                slice_position: 6
+            }]
+        );
+
+        // a new SPS content needs an IDR picture
+        let resized = Sps {
+            pic_width_in_mbs_minus1: 79,
+            ..sps
+        };
+        sets.insert_sps(resized.clone(), 7).unwrap();
+        sets.activate(0, false, true, 8).unwrap();
+        assert_eq!(sets.redefinitions().len(), 2);
+        assert_eq!(sets.redefinitions()[1].kind, ParameterSetKind::Sps);
+        assert_eq!(sets.active_sps().unwrap().width(), 1280);
+        assert_eq!(
+            sets.insert_sps(
+                Sps {
+                    seq_parameter_set_id: 32,
+                    ..resized
+                },
+                9
+            ),
+            Err(ParseError::OutOfRange {
+                name: "seq_parameter_set_id",
+                value: 32
+            })
+        );
+    }
+}
@@ -0,0 +1,435 @@
+//! Picture parameter set RBSP, 7.3.2.2.
+
+use crate::bitwriter::{BitWriter, BitWriterError};
+use crate::emulation;
+use crate::sps::{scaling_lists, write_scaling_lists, ScalingList, Sps};
+use crate::syntax::{ParseError, SyntaxReader};
+
+/// pic_parameter_set_rbsp(), 7.3.2.2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pps {
+    pub pic_parameter_set_id: u32,
+    pub seq_parameter_set_id: u32,
+    pub entropy_coding_mode_flag: bool,
+    pub bottom_field_pic_order_in_frame_present_flag: bool,
+    pub num_slice_groups_minus1: u32,
+    pub slice_group_map_type: u32,
+    /// run_length_minus1[iGroup], slice group map type 0.
+    pub run_length_minus1: Vec<u32>,
+    /// top_left[iGroup] and bottom_right[iGroup], slice group map type 2.
+    pub top_left: Vec<u32>,
+    pub bottom_right: Vec<u32>,
+    /// Slice group map types 3 to 5.
+    pub slice_group_change_direction_flag: bool,
+    pub slice_group_change_rate_minus1: u32,
+    /// Slice group map type 6.
+    pub pic_size_in_map_units_minus1: u32,
+    pub slice_group_id: Vec<u32>,
+    pub num_ref_idx_l0_default_active_minus1: u32,
+    pub num_ref_idx_l1_default_active_minus1: u32,
+    pub weighted_pred_flag: bool,
+    pub weighted_bipred_idc: u32,
+    pub pic_init_qp_minus26: i32,
+    pub pic_init_qs_minus26: i32,
+    pub chroma_qp_index_offset: i32,
+    pub deblocking_filter_control_present_flag: bool,
+    pub constrained_intra_pred_flag: bool,
+    pub redundant_pic_cnt_present_flag: bool,
+    pub transform_8x8_mode_flag: bool,
+    pub pic_scaling_matrix_present_flag: bool,
+    /// The lists for which pic_scaling_list_present_flag[i] is set; empty without a matrix.
+    pub pic_scaling_lists: Vec<Option<ScalingList>>,
+    /// Inferred to be chroma_qp_index_offset when not present.
+    pub second_chroma_qp_index_offset: i32,
+}
+
+/// Ceil(Log2(n)), for n >= 1.
+pub(crate) fn ceil_log2(n: u64) -> u32 {
+    64 - n.saturating_sub(1).leading_zeros()
+}
+
+impl Pps {
+    /// Parses pic_parameter_set_rbsp() from the reader's position, i.e. after the NAL header,
+    /// up to the rbsp_trailing_bits.
+    ///
+    /// The pic_scaling_matrix depends on the chroma_format_idc of the referenced SPS, looked up
+    /// with `sps` by seq_parameter_set_id. It is only called when the PPS carries the
+    /// transform_8x8_mode_flag extension.
+    pub fn parse<'p>(
+        s: &mut SyntaxReader,
+        sps: impl FnOnce(u32) -> Result<&'p Sps, ParseError>,
+    ) -> Result<Pps, ParseError> {
+        let pic_parameter_set_id = s.ue_max("pic_parameter_set_id", 255)?;
+        let seq_parameter_set_id = s.ue_max("seq_parameter_set_id", 31)?;
+        let entropy_coding_mode_flag = s.flag("entropy_coding_mode_flag")?;
+        let bottom_field_pic_order_in_frame_present_flag =
+            s.flag("bottom_field_pic_order_in_frame_present_flag")?;
+        let num_slice_groups_minus1 = s.ue_max("num_slice_groups_minus1", 7)?;
+
+        let mut slice_group_map_type = 0;
+        let mut run_length_minus1 = Vec::new();
+        let mut top_left = Vec::new();
+        let mut bottom_right = Vec::new();
+        let mut slice_group_change_direction_flag = false;
+        let mut slice_group_change_rate_minus1 = 0;
+        let mut pic_size_in_map_units_minus1 = 0;
+        let mut slice_group_id = Vec::new();
+        if num_slice_groups_minus1 > 0 {
+            slice_group_map_type = s.ue_max("slice_group_map_type", 6)?;
+            match slice_group_map_type {
+                0 => {
+                    for i_group in 0..=num_slice_groups_minus1 {
+                        run_length_minus1
+                            .push(s.ue(format_args!("run_length_minus1[{}]", i_group))?);
+                    }
+                }
+                2 => {
+                    for i_group in 0..num_slice_groups_minus1 {
+                        top_left.push(s.ue(format_args!("top_left[{}]", i_group))?);
+                        bottom_right.push(s.ue(format_args!("bottom_right[{}]", i_group))?);
+                    }
+                }
+                3..=5 => {
+                    slice_group_change_direction_flag =
+                        s.flag("slice_group_change_direction_flag")?;
+                    slice_group_change_rate_minus1 = s.ue("slice_group_change_rate_minus1")?;
+                }
+                6 => {
+                    pic_size_in_map_units_minus1 = s.ue("pic_size_in_map_units_minus1")?;
+                    let bits = ceil_log2(num_slice_groups_minus1 as u64 + 1);
+                    for i in 0..=pic_size_in_map_units_minus1 {
+                        slice_group_id.push(s.u(format_args!("slice_group_id[{}]", i), bits)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let num_ref_idx_l0_default_active_minus1 =
+            s.ue_max("num_ref_idx_l0_default_active_minus1", 31)?;
+        let num_ref_idx_l1_default_active_minus1 =
+            s.ue_max("num_ref_idx_l1_default_active_minus1", 31)?;
+        let weighted_pred_flag = s.flag("weighted_pred_flag")?;
+        let weighted_bipred_idc = s.u("weighted_bipred_idc", 2)?;
+        let pic_init_qp_minus26 = s.se("pic_init_qp_minus26")?;
+        let pic_init_qs_minus26 = s.se("pic_init_qs_minus26")?;
+        let chroma_qp_index_offset = s.se("chroma_qp_index_offset")?;
+        let deblocking_filter_control_present_flag =
+            s.flag("deblocking_filter_control_present_flag")?;
+        let constrained_intra_pred_flag = s.flag("constrained_intra_pred_flag")?;
+        let redundant_pic_cnt_present_flag = s.flag("redundant_pic_cnt_present_flag")?;
+
+        let mut transform_8x8_mode_flag = false;
+        let mut pic_scaling_matrix_present_flag = false;
+        let mut pic_scaling_lists = Vec::new();
+        let mut second_chroma_qp_index_offset = chroma_qp_index_offset;
+        if s.more_rbsp_data() {
+            let chroma_format_idc = sps(seq_parameter_set_id)?.chroma_format_idc;
+            transform_8x8_mode_flag = s.flag("transform_8x8_mode_flag")?;
+            pic_scaling_matrix_present_flag = s.flag("pic_scaling_matrix_present_flag")?;
+            if pic_scaling_matrix_present_flag {
+                let lists_8x8 = match (transform_8x8_mode_flag, chroma_format_idc) {
+                    (false, _) => 0,
+                    (true, 3) => 6,
+                    (true, _) => 2,
+                };
+                pic_scaling_lists = scaling_lists(s, "pic", 6 + lists_8x8)?;
+            }
+            second_chroma_qp_index_offset = s.se("second_chroma_qp_index_offset")?;
+        }
+
+        Ok(Pps {
+            pic_parameter_set_id,
+            seq_parameter_set_id,
+            entropy_coding_mode_flag,
+            bottom_field_pic_order_in_frame_present_flag,
+            num_slice_groups_minus1,
+            slice_group_map_type,
+            run_length_minus1,
+            top_left,
+            bottom_right,
+            slice_group_change_direction_flag,
+            slice_group_change_rate_minus1,
+            pic_size_in_map_units_minus1,
+            slice_group_id,
+            num_ref_idx_l0_default_active_minus1,
+            num_ref_idx_l1_default_active_minus1,
+            weighted_pred_flag,
+            weighted_bipred_idc,
+            pic_init_qp_minus26,
+            pic_init_qs_minus26,
+            chroma_qp_index_offset,
+            deblocking_filter_control_present_flag,
+            constrained_intra_pred_flag,
+            redundant_pic_cnt_present_flag,
+            transform_8x8_mode_flag,
+            pic_scaling_matrix_present_flag,
+            pic_scaling_lists,
+            second_chroma_qp_index_offset,
+        })
+    }
+
+    /// Parses pic_parameter_set_rbsp() from the RBSP of a PPS NAL unit, header included.
+    pub fn from_rbsp<'p>(
+        rbsp: &[u8],
+        sps: impl FnOnce(u32) -> Result<&'p Sps, ParseError>,
+    ) -> Result<Pps, ParseError> {
+        let mut s = SyntaxReader::new(rbsp);
+        s.skip_to(8);
+        Pps::parse(&mut s, sps)
+    }
+
+    /// Writes pic_parameter_set_rbsp() up to the rbsp_trailing_bits, the inverse of `parse`.
+    ///
+    /// The transform_8x8_mode_flag extension is written when one of its elements differs
+    /// from its inferred value, with the pic_scaling_lists as stored.
+    pub fn write(&self, w: &mut BitWriter) -> Result<(), BitWriterError> {
+        w.ue(self.pic_parameter_set_id)?;
+        w.ue(self.seq_parameter_set_id)?;
+        w.write_flag(self.entropy_coding_mode_flag);
+        w.write_flag(self.bottom_field_pic_order_in_frame_present_flag);
+        w.ue(self.num_slice_groups_minus1)?;
+        if self.num_slice_groups_minus1 > 0 {
+            w.ue(self.slice_group_map_type)?;
+            match self.slice_group_map_type {
+                0 => {
+                    for &run_length_minus1 in &self.run_length_minus1 {
+                        w.ue(run_length_minus1)?;
+                    }
+                }
+                2 => {
+                    for (&top_left, &bottom_right) in self.top_left.iter().zip(&self.bottom_right) {
+                        w.ue(top_left)?;
+                        w.ue(bottom_right)?;
+                    }
+                }
+                3..=5 => {
+                    w.write_flag(self.slice_group_change_direction_flag);
+                    w.ue(self.slice_group_change_rate_minus1)?;
+                }
+                6 => {
+                    w.ue(self.pic_size_in_map_units_minus1)?;
+                    let bits = ceil_log2(self.num_slice_groups_minus1 as u64 + 1);
+                    for &id in &self.slice_group_id {
+                        w.u(bits, id)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        w.ue(self.num_ref_idx_l0_default_active_minus1)?;
+        w.ue(self.num_ref_idx_l1_default_active_minus1)?;
+        w.write_flag(self.weighted_pred_flag);
+        w.u(2, self.weighted_bipred_idc)?;
+        w.se(self.pic_init_qp_minus26)?;
+        w.se(self.pic_init_qs_minus26)?;
+        w.se(self.chroma_qp_index_offset)?;
+        w.write_flag(self.deblocking_filter_control_present_flag);
+        w.write_flag(self.constrained_intra_pred_flag);
+        w.write_flag(self.redundant_pic_cnt_present_flag);
+
+        if self.has_transform_8x8_extension() {
+            w.write_flag(self.transform_8x8_mode_flag);
+            w.write_flag(self.pic_scaling_matrix_present_flag);
+            if self.pic_scaling_matrix_present_flag {
+                write_scaling_lists(w, &self.pic_scaling_lists)?;
+            }
+            w.se(self.second_chroma_qp_index_offset)?;
+        }
+        Ok(())
+    }
+
+    /// Whether an element of the transform_8x8_mode_flag extension differs from its inferred
+    /// value, so that `write` writes the extension.
+    pub fn has_transform_8x8_extension(&self) -> bool {
+        self.transform_8x8_mode_flag
+            || self.pic_scaling_matrix_present_flag
+            || self.second_chroma_qp_index_offset != self.chroma_qp_index_offset
+    }
+
+    /// The PPS NAL unit: header, pic_parameter_set_rbsp() and emulation prevention bytes.
+    pub fn to_nal(&self, nal_ref_idc: u8) -> Result<Vec<u8>, BitWriterError> {
+        let mut w = BitWriter::new();
+        w.u(8, (nal_ref_idc as u32 & 3) << 5 | 8)?;
+        self.write(&mut w)?;
+        w.rbsp_trailing_bits();
+        Ok(emulation::encode(w.rbsp()))
+    }
+
+    /// The number of slice groups, num_slice_groups_minus1 + 1.
+    pub fn num_slice_groups(&self) -> u32 {
+        self.num_slice_groups_minus1 + 1
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+    use crate::emulation;
+    use crate::sps::tests::HIGH_SPS;
+
+    /// The PPS of the CABAC v/input streams.
+    pub(crate) const CABAC_PPS: [u8; 6] = [0x68, 0xeb, 0xe3, 0xcb, 0x22, 0xc0];
+
+    #[test]
+    fn test_parse_pps() {
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+        let pps = Pps::from_rbsp(&CABAC_PPS, |_| Ok(&sps)).unwrap();
+        assert_eq!(pps.to_nal(3).unwrap(), CABAC_PPS);
+        assert_eq!(pps.pic_parameter_set_id, 0);
+        assert_eq!(pps.seq_parameter_set_id, 0);
+        assert!(pps.entropy_coding_mode_flag);
+        assert_eq!(pps.num_slice_groups_minus1, 0);
+        assert!(pps.weighted_pred_flag);
+        assert_eq!(pps.weighted_bipred_idc, 2);
+        assert_eq!(pps.pic_init_qp_minus26, -3);
+        assert_eq!(pps.chroma_qp_index_offset, -2);
+        assert!(pps.deblocking_filter_control_present_flag);
+        assert!(pps.transform_8x8_mode_flag);
+        assert!(!pps.pic_scaling_matrix_present_flag);
+        assert_eq!(pps.second_chroma_qp_index_offset, -2);
+
+        // the CAVLC stream differs in entropy_coding_mode_flag only
+        let cavlc = Pps::from_rbsp(&[0x68, 0xcb, 0xe3, 0xcb, 0x22, 0xc0], |_| Ok(&sps)).unwrap();
+        assert_eq!(
+            cavlc,
+            Pps {
+                entropy_coding_mode_flag: false,
+                ..pps
+            }
+        );
+    }
+
+    /// A PPS with the given slice group map, then the 8x8 transform extension with a scaling
+    /// matrix carrying the 4x4 list 0 and the last list only.
+    fn write_pps(w: &mut BitWriter, slice_groups: impl FnOnce(&mut BitWriter), lists: usize) {
+        w.u(8, 0x68).unwrap();
+        w.ue(3).unwrap(); // pic_parameter_set_id
+        w.ue(0).unwrap(); // seq_parameter_set_id
+        w.write_flag(true);
+        w.write_flag(false);
+        slice_groups(w);
+        w.ue(2).unwrap();
+        w.ue(0).unwrap();
+        w.write_flag(false);
+        w.u(2, 0).unwrap();
+        w.se(0).unwrap();
+        w.se(0).unwrap();
+        w.se(3).unwrap(); // chroma_qp_index_offset
+        w.write_flag(true);
+        w.write_flag(false);
+        w.write_flag(false);
+        w.write_flag(true); // transform_8x8_mode_flag
+        w.write_flag(true); // pic_scaling_matrix_present_flag
+        for i in 0..lists {
+            let present = i == 0 || i == lists - 1;
+            w.write_flag(present);
+            if present {
+                w.se(8).unwrap(); // delta_scale[0], then repeated
+                w.se(-16).unwrap();
+            }
+        }
+        w.se(-5).unwrap(); // second_chroma_qp_index_offset
+        w.rbsp_trailing_bits();
+    }
+
+    #[test]
+    fn test_parse_slice_groups() {
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+
+        // two foreground rectangles, the third group is the background left over
+        let mut w = BitWriter::new();
+        write_pps(
+            &mut w,
+            |w| {
+                w.ue(2).unwrap(); // num_slice_groups_minus1
+                w.ue(2).unwrap(); // slice_group_map_type
+                for (top_left, bottom_right) in [(0, 241), (362, 839)] {
+                    w.ue(top_left).unwrap();
+                    w.ue(bottom_right).unwrap();
+                }
+            },
+            8,
+        );
+        let pps = Pps::from_rbsp(w.rbsp(), |id| {
+            assert_eq!(id, 0);
+            Ok(&sps)
+        })
+        .unwrap();
+        assert_eq!(pps.pic_parameter_set_id, 3);
+        assert_eq!(pps.num_slice_groups(), 3);
+        assert_eq!(pps.top_left, [0, 362]);
+        assert_eq!(pps.bottom_right, [241, 839]);
+        assert_eq!(pps.num_ref_idx_l0_default_active_minus1, 2);
+        assert!(pps.transform_8x8_mode_flag);
+        assert!(pps.pic_scaling_matrix_present_flag);
+        assert_eq!(pps.pic_scaling_lists.len(), 8);
+        let first = pps.pic_scaling_lists[0].as_ref().unwrap();
+        assert_eq!(first.values[..2], [16, 16]);
+        assert!(pps.pic_scaling_lists[1].is_none());
+        assert_eq!(pps.pic_scaling_lists[7].as_ref().unwrap().values.len(), 64);
+        assert_eq!(pps.chroma_qp_index_offset, 3);
+        assert_eq!(pps.second_chroma_qp_index_offset, -5);
+
+        // explicit map units, two bits per slice_group_id with 4 groups
+        let mut w = BitWriter::new();
+        write_pps(
+            &mut w,
+            |w| {
+                w.ue(3).unwrap();
+                w.ue(6).unwrap();
+                w.ue(4).unwrap(); // pic_size_in_map_units_minus1
+                for id in [0, 1, 2, 3, 1] {
+                    w.u(2, id).unwrap();
+                }
+            },
+            8,
+        );
+        let pps = Pps::from_rbsp(w.rbsp(), |_| Ok(&sps)).unwrap();
+        assert_eq!(pps.slice_group_map_type, 6);
+        assert_eq!(pps.slice_group_id, [0, 1, 2, 3, 1]);
+        assert_eq!(pps.second_chroma_qp_index_offset, -5);
+        let mut out = BitWriter::new();
+        out.u(8, 0x68).unwrap();
+        pps.write(&mut out).unwrap();
+        out.rbsp_trailing_bits();
+        assert_eq!(out.rbsp(), w.rbsp());
+
+        // the 8x8 extension needs the SPS
+        assert_eq!(
+            Pps::from_rbsp(w.rbsp(), |id| Err(ParseError::MissingSps(id))),
+            Err(ParseError::MissingSps(0))
+        );
+    }
+
+    #[test]
+    fn test_parse_scaling_matrix_444() {
+        let sps = Sps {
+            chroma_format_idc: 3,
+            ..Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap()
+        };
+        let mut w = BitWriter::new();
+        write_pps(
+            &mut w,
+            |w| {
+                w.ue(1).unwrap();
+                w.ue(0).unwrap(); // interleaved
+                w.ue(9).unwrap();
+                w.ue(19).unwrap();
+            },
+            12,
+        );
+        let pps = Pps::from_rbsp(w.rbsp(), |_| Ok(&sps)).unwrap();
+        assert_eq!(pps.run_length_minus1, [9, 19]);
+        assert_eq!(pps.pic_scaling_lists.len(), 12);
+        assert!(pps.pic_scaling_lists[11].is_some());
+        assert_eq!(pps.second_chroma_qp_index_offset, -5);
+    }
+
+    #[test]
+    fn test_ceil_log2() {
+        assert_eq!([1, 2, 3, 4, 5, 8, 9].map(ceil_log2), [0, 1, 2, 2, 3, 3, 4]);
+    }
+}
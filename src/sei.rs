@@ -0,0 +1,117 @@
+//! Supplemental enhancement information RBSP, 7.3.2.3. Payloads are located but not parsed.
+
+use std::ops::Range;
+
+use crate::syntax::{ParseError, SyntaxReader};
+
+/// Name of an SEI payload type, D.1.1.
+pub fn payload_type_name(payload_type: u32) -> &'static str {
+    match payload_type {
+        0 => "buffering_period",
+        1 => "pic_timing",
+        2 => "pan_scan_rect",
+        3 => "filler_payload",
+        4 => "user_data_registered_itu_t_t35",
+        5 => "user_data_unregistered",
+        6 => "recovery_point",
+        7 => "dec_ref_pic_marking_repetition",
+        8 => "spare_pic",
+        9 => "scene_info",
+        10 => "sub_seq_info",
+        11 => "sub_seq_layer_characteristics",
+        12 => "sub_seq_characteristics",
+        13 => "full_frame_freeze",
+        14 => "full_frame_freeze_release",
+        15 => "full_frame_snapshot",
+        16 => "progressive_refinement_segment_start",
+        17 => "progressive_refinement_segment_end",
+        18 => "motion_constrained_slice_group_set",
+        19 => "film_grain_characteristics",
+        20 => "deblocking_filter_display_preference",
+        21 => "stereo_video_info",
+        22 => "post_filter_hint",
+        23 => "tone_mapping_info",
+        45 => "frame_packing_arrangement",
+        47 => "display_orientation",
+        137 => "mastering_display_colour_volume",
+        144 => "content_light_level_info",
+        147 => "alternative_transfer_characteristics",
+        _ => "reserved_sei_message",
+    }
+}
+
+/// sei_message(), 7.3.2.3.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeiMessage {
+    pub payload_type: u32,
+    pub payload_size: u32,
+    /// Byte range of sei_payload() in the RBSP.
+    pub payload: Range<usize>,
+}
+
+impl SeiMessage {
+    pub fn name(&self) -> &'static str {
+        payload_type_name(self.payload_type)
+    }
+}
+
+/// Reads a payloadType or payloadSize: ff_byte repeated, then the last byte.
+fn read_sum(s: &mut SyntaxReader, last_name: &str) -> Result<u32, ParseError> {
+    let mut value = 0u32;
+    while s.next_bits(8) == Some(0xff) {
+        s.f("ff_byte", 8)?;
+        value = value.saturating_add(255);
+    }
+    Ok(value.saturating_add(s.u(last_name, 8)?))
+}
+
+/// Parses sei_rbsp() from the reader's position, i.e. after the NAL header.
+pub fn parse_sei(s: &mut SyntaxReader) -> Result<Vec<SeiMessage>, ParseError> {
+    let mut messages = Vec::new();
+    loop {
+        let payload_type = read_sum(s, "last_payload_type_byte")?;
+        let payload_size = read_sum(s, "last_payload_size_byte")?;
+        let start = s.reader().position() / 8;
+        s.skip_bytes(payload_type_name(payload_type), payload_size as usize)?;
+        messages.push(SeiMessage {
+            payload_type,
+            payload_size,
+            payload: start..start + payload_size as usize,
+        });
+        if !s.more_rbsp_data() {
+            break;
+        }
+    }
+    s.rbsp_trailing_bits()?;
+    Ok(messages)
+}
+
+/// Parses sei_rbsp() from the RBSP of an SEI NAL unit, header included.
+pub fn parse_sei_rbsp(rbsp: &[u8]) -> Result<Vec<SeiMessage>, ParseError> {
+    let mut s = SyntaxReader::new(rbsp);
+    s.skip_to(8);
+    parse_sei(&mut s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_sei() {
+        // recovery_point with 2 bytes, then a 300 byte user_data_unregistered
+        let mut rbsp = vec![0x06, 0x06, 0x02, 0x88, 0x40, 0x05, 0xff, 0x2d];
+        rbsp.extend([0xaa; 300]);
+        rbsp.push(0x80);
+        let messages = parse_sei_rbsp(&rbsp).unwrap();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].name(), "recovery_point");
+        assert_eq!(messages[0].payload, 3..5);
+        assert_eq!(messages[1].payload_type, 5);
+        assert_eq!(messages[1].payload_size, 300);
+        assert_eq!(messages[1].payload, 8..308);
+
+        rbsp.truncate(100);
+        assert!(parse_sei_rbsp(&rbsp).is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! Slice header, 7.3.3.
+
+use crate::nal::NalUnitType;
+use crate::pps::{ceil_log2, Pps};
+use crate::sps::Sps;
+use crate::syntax::{self, ParseError, SyntaxReader};
+
+/// slice_type % 5, Table 7-6.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SliceType {
+    P,
+    B,
+    I,
+    Sp,
+    Si,
+}
+
+impl SliceType {
+    pub fn from_slice_type(slice_type: u32) -> Self {
+        match slice_type % 5 {
+            0 => SliceType::P,
+            1 => SliceType::B,
+            2 => SliceType::I,
+            3 => SliceType::Sp,
+            _ => SliceType::Si,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SliceType::P => "P",
+            SliceType::B => "B",
+            SliceType::I => "I",
+            SliceType::Sp => "SP",
+            SliceType::Si => "SI",
+        }
+    }
+}
+
+/// One entry of ref_pic_list_modification(), 7.3.3.1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefPicListModification {
+    pub modification_of_pic_nums_idc: u32,
+    /// abs_diff_pic_num_minus1 or long_term_pic_num, depending on the idc.
+    pub value: u32,
+}
+
+/// Weights of one reference index in pred_weight_table(), 7.3.3.2.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PredWeight {
+    /// luma_weight and luma_offset, when luma_weight_lX_flag is set.
+    pub luma: Option<(i32, i32)>,
+    /// chroma_weight and chroma_offset of Cb and Cr, when chroma_weight_lX_flag is set.
+    pub chroma: Option<[(i32, i32); 2]>,
+}
+
+/// pred_weight_table(), 7.3.3.2.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PredWeightTable {
+    pub luma_log2_weight_denom: u32,
+    pub chroma_log2_weight_denom: u32,
+    pub l0: Vec<PredWeight>,
+    pub l1: Vec<PredWeight>,
+}
+
+/// One memory_management_control_operation of dec_ref_pic_marking(), 7.3.3.3.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryManagementOperation {
+    pub memory_management_control_operation: u32,
+    pub difference_of_pic_nums_minus1: u32,
+    pub long_term_pic_num: u32,
+    pub long_term_frame_idx: u32,
+    pub max_long_term_frame_idx_plus1: u32,
+}
+
+/// dec_ref_pic_marking(), 7.3.3.3.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DecRefPicMarking {
+    pub no_output_of_prior_pics_flag: bool,
+    pub long_term_reference_flag: bool,
+    pub adaptive_ref_pic_marking_mode_flag: bool,
+    pub operations: Vec<MemoryManagementOperation>,
+}
+
+/// slice_header(), 7.3.3. Elements that are not present hold 0 or false.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SliceHeader {
+    pub first_mb_in_slice: u32,
+    pub slice_type: u32,
+    pub pic_parameter_set_id: u32,
+    pub colour_plane_id: u32,
+    pub frame_num: u32,
+    pub field_pic_flag: bool,
+    pub bottom_field_flag: bool,
+    pub idr_pic_id: u32,
+    pub pic_order_cnt_lsb: u32,
+    pub delta_pic_order_cnt_bottom: i32,
+    pub delta_pic_order_cnt: [i32; 2],
+    pub redundant_pic_cnt: u32,
+    pub direct_spatial_mv_pred_flag: bool,
+    pub num_ref_idx_active_override_flag: bool,
+    /// The PPS defaults unless overridden.
+    pub num_ref_idx_l0_active_minus1: u32,
+    pub num_ref_idx_l1_active_minus1: u32,
+    pub ref_pic_list_modification_l0: Vec<RefPicListModification>,
+    pub ref_pic_list_modification_l1: Vec<RefPicListModification>,
+    pub pred_weight_table: Option<PredWeightTable>,
+    pub dec_ref_pic_marking: Option<DecRefPicMarking>,
+    pub cabac_init_idc: u32,
+    pub slice_qp_delta: i32,
+    pub sp_for_switch_flag: bool,
+    pub slice_qs_delta: i32,
+    pub disable_deblocking_filter_idc: u32,
+    pub slice_alpha_c0_offset_div2: i32,
+    pub slice_beta_offset_div2: i32,
+    pub slice_group_change_cycle: u32,
+}
+
+fn ref_pic_list_modification(
+    s: &mut SyntaxReader,
+    list: u32,
+) -> Result<Vec<RefPicListModification>, ParseError> {
+    let mut modifications = Vec::new();
+    if !s.flag(format_args!("ref_pic_list_modification_flag_l{}", list))? {
+        return Ok(modifications);
+    }
+    loop {
+        let idc = s.ue_max("modification_of_pic_nums_idc", 3)?;
+        let value = match idc {
+            0 | 1 => s.ue("abs_diff_pic_num_minus1")?,
+            2 => s.ue("long_term_pic_num")?,
+            _ => break,
+        };
+        modifications.push(RefPicListModification {
+            modification_of_pic_nums_idc: idc,
+            value,
+        });
+    }
+    Ok(modifications)
+}
+
+fn pred_weights(
+    s: &mut SyntaxReader,
+    list: u32,
+    count: u32,
+    chroma: bool,
+) -> Result<Vec<PredWeight>, ParseError> {
+    let mut weights = Vec::with_capacity(count as usize);
+    for i in 0..count {
+        let mut weight = PredWeight::default();
+        if s.flag(format_args!("luma_weight_l{}_flag[{}]", list, i))? {
+            weight.luma = Some((
+                s.se(format_args!("luma_weight_l{}[{}]", list, i))?,
+                s.se(format_args!("luma_offset_l{}[{}]", list, i))?,
+            ));
+        }
+        if chroma && s.flag(format_args!("chroma_weight_l{}_flag[{}]", list, i))? {
+            let mut cb_cr = [(0, 0); 2];
+            for (j, entry) in cb_cr.iter_mut().enumerate() {
+                *entry = (
+                    s.se(format_args!("chroma_weight_l{}[{}][{}]", list, i, j))?,
+                    s.se(format_args!("chroma_offset_l{}[{}][{}]", list, i, j))?,
+                );
+            }
+            weight.chroma = Some(cb_cr);
+        }
+        weights.push(weight);
+    }
+    Ok(weights)
+}
+
+fn dec_ref_pic_marking(s: &mut SyntaxReader, idr: bool) -> Result<DecRefPicMarking, ParseError> {
+    let mut marking = DecRefPicMarking::default();
+    if idr {
+        marking.no_output_of_prior_pics_flag = s.flag("no_output_of_prior_pics_flag")?;
+        marking.long_term_reference_flag = s.flag("long_term_reference_flag")?;
+        return Ok(marking);
+    }
+    marking.adaptive_ref_pic_marking_mode_flag = s.flag("adaptive_ref_pic_marking_mode_flag")?;
+    if marking.adaptive_ref_pic_marking_mode_flag {
+        loop {
+            let mut op = MemoryManagementOperation {
+                memory_management_control_operation: s
+                    .ue_max("memory_management_control_operation", 6)?,
+                ..Default::default()
+            };
+            match op.memory_management_control_operation {
+                0 => break,
+                1 | 3 => {
+                    op.difference_of_pic_nums_minus1 = s.ue("difference_of_pic_nums_minus1")?
+                }
+                2 => op.long_term_pic_num = s.ue("long_term_pic_num")?,
+                4 => op.max_long_term_frame_idx_plus1 = s.ue("max_long_term_frame_idx_plus1")?,
+                _ => {}
+            }
+            if matches!(op.memory_management_control_operation, 3 | 6) {
+                op.long_term_frame_idx = s.ue("long_term_frame_idx")?;
+            }
+            marking.operations.push(op);
+        }
+    }
+    Ok(marking)
+}
+
+impl SliceHeader {
+    /// Parses slice_header() from the reader's position, i.e. after the NAL header of a slice
+    /// with `nal_unit_type` and `nal_ref_idc`.
+    ///
+    /// `parameter_sets` looks up the PPS of the given pic_parameter_set_id and its SPS.
+    pub fn parse<'p>(
+        s: &mut SyntaxReader,
+        nal_unit_type: NalUnitType,
+        nal_ref_idc: u8,
+        parameter_sets: impl FnOnce(u32) -> Result<(&'p Sps, &'p Pps), ParseError>,
+    ) -> Result<SliceHeader, ParseError> {
+        let mut h = SliceHeader {
+            first_mb_in_slice: s.ue("first_mb_in_slice")?,
+            slice_type: s.ue_max("slice_type", 9)?,
+            pic_parameter_set_id: s.ue_max("pic_parameter_set_id", 255)?,
+            ..Default::default()
+        };
+        let (sps, pps) = parameter_sets(h.pic_parameter_set_id)?;
+        let slice_type = h.kind();
+        let idr = nal_unit_type == NalUnitType::SliceIdr;
+
+        if sps.separate_colour_plane_flag {
+            h.colour_plane_id = s.u("colour_plane_id", 2)?;
+        }
+        h.frame_num = s.u("frame_num", sps.log2_max_frame_num_minus4 + 4)?;
+        if !sps.frame_mbs_only_flag {
+            h.field_pic_flag = s.flag("field_pic_flag")?;
+            if h.field_pic_flag {
+                h.bottom_field_flag = s.flag("bottom_field_flag")?;
+            }
+        }
+        if idr {
+            h.idr_pic_id = s.ue_max("idr_pic_id", 65535)?;
+        }
+        let bottom_field_pic_order =
+            pps.bottom_field_pic_order_in_frame_present_flag && !h.field_pic_flag;
+        if sps.pic_order_cnt_type == 0 {
+            h.pic_order_cnt_lsb = s.u(
+                "pic_order_cnt_lsb",
+                sps.log2_max_pic_order_cnt_lsb_minus4 + 4,
+            )?;
+            if bottom_field_pic_order {
+                h.delta_pic_order_cnt_bottom = s.se("delta_pic_order_cnt_bottom")?;
+            }
+        }
+        if sps.pic_order_cnt_type == 1 && !sps.delta_pic_order_always_zero_flag {
+            h.delta_pic_order_cnt[0] = s.se("delta_pic_order_cnt[0]")?;
+            if bottom_field_pic_order {
+                h.delta_pic_order_cnt[1] = s.se("delta_pic_order_cnt[1]")?;
+            }
+        }
+        if pps.redundant_pic_cnt_present_flag {
+            h.redundant_pic_cnt = s.ue_max("redundant_pic_cnt", 127)?;
+        }
+        if slice_type == SliceType::B {
+            h.direct_spatial_mv_pred_flag = s.flag("direct_spatial_mv_pred_flag")?;
+        }
+        h.num_ref_idx_l0_active_minus1 = pps.num_ref_idx_l0_default_active_minus1;
+        h.num_ref_idx_l1_active_minus1 = pps.num_ref_idx_l1_default_active_minus1;
+        if matches!(slice_type, SliceType::P | SliceType::Sp | SliceType::B) {
+            h.num_ref_idx_active_override_flag = s.flag("num_ref_idx_active_override_flag")?;
+            if h.num_ref_idx_active_override_flag {
+                h.num_ref_idx_l0_active_minus1 = s.ue_max("num_ref_idx_l0_active_minus1", 31)?;
+                if slice_type == SliceType::B {
+                    h.num_ref_idx_l1_active_minus1 =
+                        s.ue_max("num_ref_idx_l1_active_minus1", 31)?;
+                }
+            }
+        }
+
+        if !matches!(slice_type, SliceType::I | SliceType::Si) {
+            h.ref_pic_list_modification_l0 = ref_pic_list_modification(s, 0)?;
+        }
+        if slice_type == SliceType::B {
+            h.ref_pic_list_modification_l1 = ref_pic_list_modification(s, 1)?;
+        }
+
+        if (pps.weighted_pred_flag && matches!(slice_type, SliceType::P | SliceType::Sp))
+            || (pps.weighted_bipred_idc == 1 && slice_type == SliceType::B)
+        {
+            let chroma = sps.chroma_array_type() != 0;
+            let mut table = PredWeightTable {
+                luma_log2_weight_denom: s.ue_max("luma_log2_weight_denom", 7)?,
+                ..Default::default()
+            };
+            if chroma {
+                table.chroma_log2_weight_denom = s.ue_max("chroma_log2_weight_denom", 7)?;
+            }
+            table.l0 = pred_weights(s, 0, h.num_ref_idx_l0_active_minus1 + 1, chroma)?;
+            if slice_type == SliceType::B {
+                table.l1 = pred_weights(s, 1, h.num_ref_idx_l1_active_minus1 + 1, chroma)?;
+            }
+            h.pred_weight_table = Some(table);
+        }
+
+        if nal_ref_idc != 0 {
+            h.dec_ref_pic_marking = Some(dec_ref_pic_marking(s, idr)?);
+        }
+        if pps.entropy_coding_mode_flag && !matches!(slice_type, SliceType::I | SliceType::Si) {
+            h.cabac_init_idc = s.ue_max("cabac_init_idc", 2)?;
+        }
+        h.slice_qp_delta = s.se("slice_qp_delta")?;
+        if matches!(slice_type, SliceType::Sp | SliceType::Si) {
+            if slice_type == SliceType::Sp {
+                h.sp_for_switch_flag = s.flag("sp_for_switch_flag")?;
+            }
+            h.slice_qs_delta = s.se("slice_qs_delta")?;
+        }
+        if pps.deblocking_filter_control_present_flag {
+            h.disable_deblocking_filter_idc = s.ue_max("disable_deblocking_filter_idc", 2)?;
+            if h.disable_deblocking_filter_idc != 1 {
+                h.slice_alpha_c0_offset_div2 = s.se("slice_alpha_c0_offset_div2")?;
+                h.slice_beta_offset_div2 = s.se("slice_beta_offset_div2")?;
+            }
+        }
+        if pps.num_slice_groups_minus1 > 0 && (3..=5).contains(&pps.slice_group_map_type) {
+            let pic_size_in_map_units = (sps.pic_width_in_mbs_minus1 as u64 + 1)
+                * (sps.pic_height_in_map_units_minus1 as u64 + 1);
+            let rate = pps.slice_group_change_rate_minus1 as u64 + 1;
+            // Ceil(Log2(PicSizeInMapUnits ÷ SliceGroupChangeRate + 1)), with an exact division
+            let bits = ceil_log2(pic_size_in_map_units.div_ceil(rate) + 1);
+            h.slice_group_change_cycle = s.u("slice_group_change_cycle", bits)?;
+        }
+        Ok(h)
+    }
+
+    pub fn kind(&self) -> SliceType {
+        SliceType::from_slice_type(self.slice_type)
+    }
+}
+
+/// first_mb_in_slice, slice type and pic_parameter_set_id of a slice NAL unit, read without
+/// the parameter sets the rest of the header depends on.
+pub fn slice_ids(nal: &[u8]) -> Result<(u32, SliceType, u32), ParseError> {
+    syntax::read_leading(nal, |s| {
+        let first_mb_in_slice = s.ue("first_mb_in_slice")?;
+        let slice_type = SliceType::from_slice_type(s.ue_max("slice_type", 9)?);
+        Ok((
+            first_mb_in_slice,
+            slice_type,
+            s.ue_max("pic_parameter_set_id", 255)?,
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+    use crate::emulation;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+
+    #[test]
+    fn test_parse_slice_header() {
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+        let pps = Pps::from_rbsp(&CABAC_PPS, |_| Ok(&sps)).unwrap();
+
+        // a non-IDR reference P slice with a weight table and an MMCO
+        let mut w = BitWriter::new();
+        w.ue(0).unwrap(); // first_mb_in_slice
+        w.ue(5).unwrap(); // slice_type P
+        w.ue(0).unwrap(); // pic_parameter_set_id
+        w.u(4, 3).unwrap(); // frame_num
+        w.u(6, 10).unwrap(); // pic_order_cnt_lsb
+        w.write_flag(true); // num_ref_idx_active_override_flag
+        w.ue(0).unwrap(); // num_ref_idx_l0_active_minus1
+        w.write_flag(true); // ref_pic_list_modification_flag_l0
+        w.ue(0).unwrap();
+        w.ue(4).unwrap();
+        w.ue(3).unwrap();
+        w.ue(6).unwrap(); // luma_log2_weight_denom
+        w.ue(5).unwrap(); // chroma_log2_weight_denom
+        w.write_flag(true); // luma_weight_l0_flag[0]
+        w.se(-3).unwrap();
+        w.se(2).unwrap();
+        w.write_flag(false); // chroma_weight_l0_flag[0]
+        w.write_flag(true); // adaptive_ref_pic_marking_mode_flag
+        w.ue(3).unwrap();
+        w.ue(7).unwrap();
+        w.ue(1).unwrap();
+        w.ue(0).unwrap();
+        w.ue(1).unwrap(); // cabac_init_idc
+        w.se(-4).unwrap(); // slice_qp_delta
+        w.ue(0).unwrap(); // disable_deblocking_filter_idc
+        w.se(1).unwrap();
+        w.se(-1).unwrap();
+        let end = w.position();
+        w.rbsp_trailing_bits();
+
+        let mut s = SyntaxReader::new(w.rbsp());
+        let h = SliceHeader::parse(&mut s, NalUnitType::SliceNonIdr, 2, |id| {
+            assert_eq!(id, 0);
+            Ok((&sps, &pps))
+        })
+        .unwrap();
+        assert_eq!(s.reader().position(), end);
+        assert_eq!(h.kind(), SliceType::P);
+        let mut nal = vec![0x41];
+        nal.extend(w.to_nal_payload());
+        assert_eq!(slice_ids(&nal), Ok((0, SliceType::P, 0)));
+        assert_eq!(h.frame_num, 3);
+        assert_eq!(h.pic_order_cnt_lsb, 10);
+        assert_eq!(
+            h.ref_pic_list_modification_l0,
+            [RefPicListModification {
+                modification_of_pic_nums_idc: 0,
+                value: 4
+            }]
+        );
+        let table = h.pred_weight_table.unwrap();
+        assert_eq!(table.l0[0].luma, Some((-3, 2)));
+        assert_eq!(table.l0[0].chroma, None);
+        let ops = h.dec_ref_pic_marking.unwrap().operations;
+        assert_eq!(ops.len(), 1);
+        assert_eq!(ops[0].difference_of_pic_nums_minus1, 7);
+        assert_eq!(ops[0].long_term_frame_idx, 1);
+        assert_eq!(h.cabac_init_idc, 1);
+        assert_eq!(h.slice_qp_delta, -4);
+        assert_eq!(h.slice_beta_offset_div2, -1);
+
+        let mut s = SyntaxReader::new(w.rbsp());
+        assert_eq!(
+            SliceHeader::parse(&mut s, NalUnitType::SliceNonIdr, 2, |id| Err(
+                ParseError::MissingPps(id)
+            )),
+            Err(ParseError::MissingPps(0))
+        );
+    }
+}
@@ -0,0 +1,113 @@
+use memchr::memmem;
+
+/// Push-based Annex B splitter for streams that arrive in arbitrary chunks.
+///
+/// Bytes are buffered until the start code of the following NAL unit arrives, so a NAL unit is
+/// emitted as soon as it is known to be complete. Start codes may straddle chunk boundaries.
+/// Call [`NalSplitter::flush`] at the end of the stream to get the last NAL unit.
+#[derive(Debug, Default)]
+pub struct NalSplitter {
+    buf: Vec<u8>,
+    // offset of the current NAL unit in `buf`, None until the first start code is seen
+    nal_start: Option<usize>,
+    // offset in `buf` from which the next start code search begins
+    scan_pos: usize,
+}
+
+impl NalSplitter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feeds a chunk of the byte stream and returns the NAL units completed by it.
+    ///
+    /// Returned NAL units include the header byte but neither the start code nor trailing zero bytes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Vec<u8>> {
+        self.buf.extend_from_slice(chunk);
+
+        let mut nals = Vec::new();
+        let finder = memmem::Finder::new(b"\x00\x00\x01");
+        while let Some(found) = finder.find(&self.buf[self.scan_pos..]) {
+            let pos = self.scan_pos + found;
+            if let Some(start) = self.nal_start {
+                if let Some(nal) = trimmed(&self.buf[start..pos]) {
+                    nals.push(nal.to_vec());
+                }
+            }
+            self.nal_start = Some(pos + 3);
+            self.scan_pos = pos + 3;
+        }
+        // the last two bytes may be the beginning of a start code completed by the next chunk
+        self.scan_pos = self.scan_pos.max(self.buf.len().saturating_sub(2));
+        self.compact();
+        nals
+    }
+
+    /// Ends the stream and returns the NAL unit still buffered, if any.
+    pub fn flush(&mut self) -> Option<Vec<u8>> {
+        let nal = self
+            .nal_start
+            .and_then(|start| trimmed(&self.buf[start..]))
+            .map(|nal| nal.to_vec());
+        *self = Self::default();
+        nal
+    }
+
+    /// Drops the bytes that can no longer be part of a NAL unit.
+    fn compact(&mut self) {
+        let keep_from = self.nal_start.unwrap_or(self.scan_pos);
+        if keep_from == 0 {
+            return;
+        }
+        self.buf.drain(..keep_from);
+        self.scan_pos -= keep_from;
+        if let Some(start) = self.nal_start.as_mut() {
+            *start -= keep_from;
+        }
+    }
+}
+
+/// Strips the zero bytes that follow a NAL unit, returns None if nothing is left.
+fn trimmed(nal: &[u8]) -> Option<&[u8]> {
+    let len = nal.iter().rposition(|&b| b != 0)? + 1;
+    Some(&nal[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::annexb;
+
+    #[test]
+    fn test_splitter_straddling_start_code() {
+        let mut splitter = NalSplitter::new();
+        assert!(splitter.push(b"\x00\x00").is_empty());
+        assert!(splitter.push(b"\x00\x01\x67\x64\x00").is_empty());
+        assert!(splitter.push(b"\x00").is_empty());
+        assert_eq!(
+            splitter.push(b"\x01\x68\xeb\x00\x00\x00"),
+            vec![b"\x67\x64".to_vec()]
+        );
+        assert_eq!(splitter.push(b"\x01\x65"), vec![b"\x68\xeb".to_vec()]);
+        assert_eq!(splitter.flush(), Some(b"\x65".to_vec()));
+        assert_eq!(splitter.flush(), None);
+    }
+
+    #[test]
+    fn test_splitter_matches_scanner() {
+        let data = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/test/test.h264")).unwrap();
+        let expected: Vec<Vec<u8>> = annexb::nal_units(&data)
+            .map(|nal| data[nal.start..nal.end].to_vec())
+            .collect();
+
+        for chunk_size in [1, 2, 3, 7, 1000] {
+            let mut splitter = NalSplitter::new();
+            let mut nals = Vec::new();
+            for chunk in data.chunks(chunk_size) {
+                nals.extend(splitter.push(chunk));
+            }
+            nals.extend(splitter.flush());
+            assert_eq!(nals, expected, "chunk size {}", chunk_size);
+        }
+    }
+}
@@ -0,0 +1,764 @@
+//! Sequence parameter set RBSP, 7.3.2.1.
+
+use std::fmt;
+
+use crate::bitwriter::{BitWriter, BitWriterError};
+use crate::emulation;
+use crate::syntax::{ParseError, SyntaxReader};
+use crate::vui::VuiParameters;
+
+/// Profiles whose SPS carries chroma_format_idc, bit depths and the scaling matrix.
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// MaxDpbMbs of Table A-1 by level_idc, level 1b under 9.
+const MAX_DPB_MBS: [(u8, u64); 20] = [
+    (10, 396),
+    (9, 396),
+    (11, 900),
+    (12, 2376),
+    (13, 2376),
+    (20, 2376),
+    (21, 4752),
+    (22, 8100),
+    (30, 8100),
+    (31, 18000),
+    (32, 20480),
+    (40, 32768),
+    (41, 32768),
+    (42, 34816),
+    (50, 110400),
+    (51, 184320),
+    (52, 184320),
+    (60, 696320),
+    (61, 696320),
+    (62, 696320),
+];
+
+/// A scaling list as transmitted, 7.3.2.1.1.1, in zig-zag scan order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScalingList {
+    pub values: Vec<u8>,
+    /// useDefaultScalingMatrixFlag: the list is replaced by the default list of Table 7-3/7-4.
+    pub use_default: bool,
+}
+
+/// scaling_list(): reads a list of `size` entries (16 or 64) named `name` in the trace.
+pub fn scaling_list(
+    s: &mut SyntaxReader,
+    name: impl fmt::Display,
+    size: usize,
+) -> Result<ScalingList, ParseError> {
+    let mut values = Vec::with_capacity(size);
+    let mut use_default = false;
+    let mut last_scale = 8i32;
+    let mut next_scale = 8i32;
+    for j in 0..size {
+        if next_scale != 0 {
+            let delta_scale = s.se(format_args!("{}: delta_scale[{}]", name, j))?;
+            if !(-128..=127).contains(&delta_scale) {
+                return Err(ParseError::OutOfRange {
+                    name: "delta_scale",
+                    value: delta_scale as i64,
+                });
+            }
+            next_scale = (last_scale + delta_scale + 256) % 256;
+            use_default = j == 0 && next_scale == 0;
+        }
+        let value = if next_scale == 0 {
+            last_scale
+        } else {
+            next_scale
+        };
+        values.push(value as u8);
+        last_scale = value;
+    }
+    Ok(ScalingList {
+        values,
+        use_default,
+    })
+}
+
+/// Reads the `count` scaling_list_present_flag[i] and the lists that are present: 4x4 lists
+/// for i < 6, 8x8 lists after.
+pub(crate) fn scaling_lists(
+    s: &mut SyntaxReader,
+    prefix: &str,
+    count: usize,
+) -> Result<Vec<Option<ScalingList>>, ParseError> {
+    let mut lists = Vec::with_capacity(count);
+    for i in 0..count {
+        let present = s.flag(format_args!("{}_scaling_list_present_flag[{}]", prefix, i))?;
+        lists.push(if present {
+            let size = if i < 6 { 16 } else { 64 };
+            Some(scaling_list(
+                s,
+                format_args!("{}_scaling_list[{}]", prefix, i),
+                size,
+            )?)
+        } else {
+            None
+        });
+    }
+    Ok(lists)
+}
+
+/// Writes scaling_list(), ending the list early with a nextScale of 0 when the remaining
+/// values repeat the last one.
+pub fn write_scaling_list(w: &mut BitWriter, list: &ScalingList) -> Result<(), BitWriterError> {
+    let delta = |from: i32, to: i32| (to - from + 128).rem_euclid(256) - 128;
+    if list.use_default {
+        return w.se(delta(8, 0));
+    }
+    let values = &list.values;
+    let end = (1..values.len())
+        .rev()
+        .take_while(|&j| values[j] == values[j - 1])
+        .last()
+        .unwrap_or(values.len());
+    let mut last_scale = 8;
+    for &value in &values[..end] {
+        w.se(delta(last_scale, value as i32))?;
+        last_scale = value as i32;
+    }
+    if end < values.len() {
+        w.se(delta(last_scale, 0))?;
+    }
+    Ok(())
+}
+
+/// Writes scaling_list_present_flag[i] for every list and the lists that are present.
+pub(crate) fn write_scaling_lists(
+    w: &mut BitWriter,
+    lists: &[Option<ScalingList>],
+) -> Result<(), BitWriterError> {
+    for list in lists {
+        w.write_flag(list.is_some());
+        if let Some(list) = list {
+            write_scaling_list(w, list)?;
+        }
+    }
+    Ok(())
+}
+
+/// seq_parameter_set_data(), 7.3.2.1.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sps {
+    pub profile_idc: u8,
+    pub constraint_set0_flag: bool,
+    pub constraint_set1_flag: bool,
+    pub constraint_set2_flag: bool,
+    pub constraint_set3_flag: bool,
+    pub constraint_set4_flag: bool,
+    pub constraint_set5_flag: bool,
+    pub reserved_zero_2bits: u8,
+    pub level_idc: u8,
+    pub seq_parameter_set_id: u32,
+    /// Inferred to be 1 (4:2:0) when not present.
+    pub chroma_format_idc: u32,
+    pub separate_colour_plane_flag: bool,
+    pub bit_depth_luma_minus8: u32,
+    pub bit_depth_chroma_minus8: u32,
+    pub qpprime_y_zero_transform_bypass_flag: bool,
+    pub seq_scaling_matrix_present_flag: bool,
+    /// The lists for which seq_scaling_list_present_flag[i] is set; empty without a matrix.
+    pub seq_scaling_lists: Vec<Option<ScalingList>>,
+    pub log2_max_frame_num_minus4: u32,
+    pub pic_order_cnt_type: u32,
+    pub log2_max_pic_order_cnt_lsb_minus4: u32,
+    pub delta_pic_order_always_zero_flag: bool,
+    pub offset_for_non_ref_pic: i32,
+    pub offset_for_top_to_bottom_field: i32,
+    pub offset_for_ref_frame: Vec<i32>,
+    pub max_num_ref_frames: u32,
+    pub gaps_in_frame_num_value_allowed_flag: bool,
+    pub pic_width_in_mbs_minus1: u32,
+    pub pic_height_in_map_units_minus1: u32,
+    pub frame_mbs_only_flag: bool,
+    pub mb_adaptive_frame_field_flag: bool,
+    pub direct_8x8_inference_flag: bool,
+    pub frame_cropping_flag: bool,
+    pub frame_crop_left_offset: u32,
+    pub frame_crop_right_offset: u32,
+    pub frame_crop_top_offset: u32,
+    pub frame_crop_bottom_offset: u32,
+    pub vui_parameters_present_flag: bool,
+    pub vui_parameters: Option<VuiParameters>,
+}
+
+impl Sps {
+    /// Parses seq_parameter_set_data() from the reader's position, i.e. after the NAL header,
+    /// up to the rbsp_trailing_bits.
+    pub fn parse(s: &mut SyntaxReader) -> Result<Sps, ParseError> {
+        let profile_idc = s.u("profile_idc", 8)? as u8;
+        let constraint_set0_flag = s.flag("constraint_set0_flag")?;
+        let constraint_set1_flag = s.flag("constraint_set1_flag")?;
+        let constraint_set2_flag = s.flag("constraint_set2_flag")?;
+        let constraint_set3_flag = s.flag("constraint_set3_flag")?;
+        let constraint_set4_flag = s.flag("constraint_set4_flag")?;
+        let constraint_set5_flag = s.flag("constraint_set5_flag")?;
+        let reserved_zero_2bits = s.u("reserved_zero_2bits", 2)? as u8;
+        let level_idc = s.u("level_idc", 8)? as u8;
+        let seq_parameter_set_id = s.ue_max("seq_parameter_set_id", 31)?;
+
+        let mut chroma_format_idc = 1;
+        let mut separate_colour_plane_flag = false;
+        let mut bit_depth_luma_minus8 = 0;
+        let mut bit_depth_chroma_minus8 = 0;
+        let mut qpprime_y_zero_transform_bypass_flag = false;
+        let mut seq_scaling_matrix_present_flag = false;
+        let mut seq_scaling_lists = Vec::new();
+        if HIGH_PROFILES.contains(&profile_idc) {
+            chroma_format_idc = s.ue_max("chroma_format_idc", 3)?;
+            if chroma_format_idc == 3 {
+                separate_colour_plane_flag = s.flag("separate_colour_plane_flag")?;
+            }
+            bit_depth_luma_minus8 = s.ue_max("bit_depth_luma_minus8", 6)?;
+            bit_depth_chroma_minus8 = s.ue_max("bit_depth_chroma_minus8", 6)?;
+            qpprime_y_zero_transform_bypass_flag =
+                s.flag("qpprime_y_zero_transform_bypass_flag")?;
+            seq_scaling_matrix_present_flag = s.flag("seq_scaling_matrix_present_flag")?;
+            if seq_scaling_matrix_present_flag {
+                let count = if chroma_format_idc != 3 { 8 } else { 12 };
+                seq_scaling_lists = scaling_lists(s, "seq", count)?;
+            }
+        }
+
+        let log2_max_frame_num_minus4 = s.ue_max("log2_max_frame_num_minus4", 12)?;
+        let pic_order_cnt_type = s.ue_max("pic_order_cnt_type", 2)?;
+        let mut log2_max_pic_order_cnt_lsb_minus4 = 0;
+        let mut delta_pic_order_always_zero_flag = false;
+        let mut offset_for_non_ref_pic = 0;
+        let mut offset_for_top_to_bottom_field = 0;
+        let mut offset_for_ref_frame = Vec::new();
+        if pic_order_cnt_type == 0 {
+            log2_max_pic_order_cnt_lsb_minus4 =
+                s.ue_max("log2_max_pic_order_cnt_lsb_minus4", 12)?;
+        } else if pic_order_cnt_type == 1 {
+            delta_pic_order_always_zero_flag = s.flag("delta_pic_order_always_zero_flag")?;
+            offset_for_non_ref_pic = s.se("offset_for_non_ref_pic")?;
+            offset_for_top_to_bottom_field = s.se("offset_for_top_to_bottom_field")?;
+            let num_ref_frames_in_pic_order_cnt_cycle =
+                s.ue_max("num_ref_frames_in_pic_order_cnt_cycle", 255)?;
+            for i in 0..num_ref_frames_in_pic_order_cnt_cycle {
+                offset_for_ref_frame.push(s.se(format_args!("offset_for_ref_frame[{}]", i))?);
+            }
+        }
+
+        let max_num_ref_frames = s.ue("max_num_ref_frames")?;
+        let gaps_in_frame_num_value_allowed_flag =
+            s.flag("gaps_in_frame_num_value_allowed_flag")?;
+        let pic_width_in_mbs_minus1 = s.ue("pic_width_in_mbs_minus1")?;
+        let pic_height_in_map_units_minus1 = s.ue("pic_height_in_map_units_minus1")?;
+        let frame_mbs_only_flag = s.flag("frame_mbs_only_flag")?;
+        let mut mb_adaptive_frame_field_flag = false;
+        if !frame_mbs_only_flag {
+            mb_adaptive_frame_field_flag = s.flag("mb_adaptive_frame_field_flag")?;
+        }
+        let direct_8x8_inference_flag = s.flag("direct_8x8_inference_flag")?;
+        let frame_cropping_flag = s.flag("frame_cropping_flag")?;
+        let (mut left, mut right, mut top, mut bottom) = (0, 0, 0, 0);
+        if frame_cropping_flag {
+            left = s.ue("frame_crop_left_offset")?;
+            right = s.ue("frame_crop_right_offset")?;
+            top = s.ue("frame_crop_top_offset")?;
+            bottom = s.ue("frame_crop_bottom_offset")?;
+        }
+        let vui_parameters_present_flag = s.flag("vui_parameters_present_flag")?;
+        let mut vui_parameters = None;
+        if vui_parameters_present_flag {
+            vui_parameters = Some(VuiParameters::parse(s)?);
+        }
+
+        let mut sps = Sps {
+            profile_idc,
+            constraint_set0_flag,
+            constraint_set1_flag,
+            constraint_set2_flag,
+            constraint_set3_flag,
+            constraint_set4_flag,
+            constraint_set5_flag,
+            reserved_zero_2bits,
+            level_idc,
+            seq_parameter_set_id,
+            chroma_format_idc,
+            separate_colour_plane_flag,
+            bit_depth_luma_minus8,
+            bit_depth_chroma_minus8,
+            qpprime_y_zero_transform_bypass_flag,
+            seq_scaling_matrix_present_flag,
+            seq_scaling_lists,
+            log2_max_frame_num_minus4,
+            pic_order_cnt_type,
+            log2_max_pic_order_cnt_lsb_minus4,
+            delta_pic_order_always_zero_flag,
+            offset_for_non_ref_pic,
+            offset_for_top_to_bottom_field,
+            offset_for_ref_frame,
+            max_num_ref_frames,
+            gaps_in_frame_num_value_allowed_flag,
+            pic_width_in_mbs_minus1,
+            pic_height_in_map_units_minus1,
+            frame_mbs_only_flag,
+            mb_adaptive_frame_field_flag,
+            direct_8x8_inference_flag,
+            frame_cropping_flag,
+            frame_crop_left_offset: left,
+            frame_crop_right_offset: right,
+            frame_crop_top_offset: top,
+            frame_crop_bottom_offset: bottom,
+            vui_parameters_present_flag,
+            vui_parameters,
+        };
+        let dpb_frames = sps.inferred_dpb_frames();
+        if let Some(vui) = sps.vui_parameters.as_mut() {
+            if !vui.bitstream_restriction_flag {
+                vui.max_num_reorder_frames = dpb_frames;
+                vui.max_dec_frame_buffering = dpb_frames;
+            }
+        }
+        Ok(sps)
+    }
+
+    /// Parses seq_parameter_set_rbsp() from the RBSP of an SPS NAL unit, header included.
+    pub fn from_rbsp(rbsp: &[u8]) -> Result<Sps, ParseError> {
+        let mut s = SyntaxReader::new(rbsp);
+        s.skip_to(8);
+        Sps::parse(&mut s)
+    }
+
+    /// Writes seq_parameter_set_data(), the inverse of `parse`. The chroma format, bit depths
+    /// and scaling matrix are written for the profiles that carry them, and the VUI when
+    /// vui_parameters_present_flag is set.
+    pub fn write(&self, w: &mut BitWriter) -> Result<(), BitWriterError> {
+        w.u(8, self.profile_idc as u32)?;
+        for flag in [
+            self.constraint_set0_flag,
+            self.constraint_set1_flag,
+            self.constraint_set2_flag,
+            self.constraint_set3_flag,
+            self.constraint_set4_flag,
+            self.constraint_set5_flag,
+        ] {
+            w.write_flag(flag);
+        }
+        w.u(2, self.reserved_zero_2bits as u32)?;
+        w.u(8, self.level_idc as u32)?;
+        w.ue(self.seq_parameter_set_id)?;
+
+        if HIGH_PROFILES.contains(&self.profile_idc) {
+            w.ue(self.chroma_format_idc)?;
+            if self.chroma_format_idc == 3 {
+                w.write_flag(self.separate_colour_plane_flag);
+            }
+            w.ue(self.bit_depth_luma_minus8)?;
+            w.ue(self.bit_depth_chroma_minus8)?;
+            w.write_flag(self.qpprime_y_zero_transform_bypass_flag);
+            w.write_flag(self.seq_scaling_matrix_present_flag);
+            if self.seq_scaling_matrix_present_flag {
+                write_scaling_lists(w, &self.seq_scaling_lists)?;
+            }
+        }
+
+        w.ue(self.log2_max_frame_num_minus4)?;
+        w.ue(self.pic_order_cnt_type)?;
+        if self.pic_order_cnt_type == 0 {
+            w.ue(self.log2_max_pic_order_cnt_lsb_minus4)?;
+        } else if self.pic_order_cnt_type == 1 {
+            w.write_flag(self.delta_pic_order_always_zero_flag);
+            w.se(self.offset_for_non_ref_pic)?;
+            w.se(self.offset_for_top_to_bottom_field)?;
+            w.ue(self.offset_for_ref_frame.len() as u32)?;
+            for &offset in &self.offset_for_ref_frame {
+                w.se(offset)?;
+            }
+        }
+
+        w.ue(self.max_num_ref_frames)?;
+        w.write_flag(self.gaps_in_frame_num_value_allowed_flag);
+        w.ue(self.pic_width_in_mbs_minus1)?;
+        w.ue(self.pic_height_in_map_units_minus1)?;
+        w.write_flag(self.frame_mbs_only_flag);
+        if !self.frame_mbs_only_flag {
+            w.write_flag(self.mb_adaptive_frame_field_flag);
+        }
+        w.write_flag(self.direct_8x8_inference_flag);
+        w.write_flag(self.frame_cropping_flag);
+        if self.frame_cropping_flag {
+            w.ue(self.frame_crop_left_offset)?;
+            w.ue(self.frame_crop_right_offset)?;
+            w.ue(self.frame_crop_top_offset)?;
+            w.ue(self.frame_crop_bottom_offset)?;
+        }
+        let vui = self
+            .vui_parameters
+            .as_ref()
+            .filter(|_| self.vui_parameters_present_flag);
+        w.write_flag(vui.is_some());
+        if let Some(vui) = vui {
+            vui.write(w)?;
+        }
+        Ok(())
+    }
+
+    /// The SPS NAL unit: header, seq_parameter_set_rbsp() and emulation prevention bytes.
+    pub fn to_nal(&self, nal_ref_idc: u8) -> Result<Vec<u8>, BitWriterError> {
+        let mut w = BitWriter::new();
+        w.u(8, (nal_ref_idc as u32 & 3) << 5 | 7)?;
+        self.write(&mut w)?;
+        w.rbsp_trailing_bits();
+        Ok(emulation::encode(w.rbsp()))
+    }
+
+    /// ChromaArrayType: chroma_format_idc, or 0 when the colour planes are coded separately.
+    pub fn chroma_array_type(&self) -> u32 {
+        if self.separate_colour_plane_flag {
+            0
+        } else {
+            self.chroma_format_idc
+        }
+    }
+
+    /// BitDepthY
+    pub fn bit_depth_luma(&self) -> u32 {
+        8 + self.bit_depth_luma_minus8
+    }
+
+    /// BitDepthC
+    pub fn bit_depth_chroma(&self) -> u32 {
+        8 + self.bit_depth_chroma_minus8
+    }
+
+    /// MaxFrameNum
+    pub fn max_frame_num(&self) -> u32 {
+        1 << (self.log2_max_frame_num_minus4 + 4)
+    }
+
+    /// MaxPicOrderCntLsb, for pic_order_cnt_type 0.
+    pub fn max_pic_order_cnt_lsb(&self) -> u32 {
+        1 << (self.log2_max_pic_order_cnt_lsb_minus4 + 4)
+    }
+
+    /// PicWidthInMbs
+    pub fn pic_width_in_mbs(&self) -> u32 {
+        self.pic_width_in_mbs_minus1.saturating_add(1)
+    }
+
+    /// PicHeightInMapUnits
+    pub fn pic_height_in_map_units(&self) -> u32 {
+        self.pic_height_in_map_units_minus1.saturating_add(1)
+    }
+
+    /// FrameHeightInMbs: twice the map units when field coding is possible.
+    pub fn frame_height_in_mbs(&self) -> u32 {
+        let fields = if self.frame_mbs_only_flag { 1 } else { 2 };
+        self.pic_height_in_map_units().saturating_mul(fields)
+    }
+
+    /// SubWidthC and SubHeightC of Table 6-1, None for monochrome and separate colour planes.
+    pub fn chroma_subsampling(&self) -> Option<(u32, u32)> {
+        match self.chroma_array_type() {
+            1 => Some((2, 2)),
+            2 => Some((2, 1)),
+            3 => Some((1, 1)),
+            _ => None,
+        }
+    }
+
+    /// CropUnitX and CropUnitY, the units of the frame_crop offsets (7-19 to 7-22).
+    pub fn crop_units(&self) -> (u32, u32) {
+        let fields = if self.frame_mbs_only_flag { 1 } else { 2 };
+        match self.chroma_subsampling() {
+            Some((sub_width_c, sub_height_c)) => (sub_width_c, sub_height_c * fields),
+            None => (1, fields),
+        }
+    }
+
+    /// Width of the decoded frame in luma samples, before cropping.
+    pub fn coded_width(&self) -> u32 {
+        self.pic_width_in_mbs().saturating_mul(16)
+    }
+
+    /// Height of the decoded frame in luma samples, before cropping.
+    pub fn coded_height(&self) -> u32 {
+        self.frame_height_in_mbs().saturating_mul(16)
+    }
+
+    /// Width of the output frame in luma samples, after cropping.
+    pub fn width(&self) -> u32 {
+        let (crop_unit_x, _) = self.crop_units();
+        let crop = self
+            .frame_crop_left_offset
+            .saturating_add(self.frame_crop_right_offset)
+            .saturating_mul(crop_unit_x);
+        self.coded_width().saturating_sub(crop)
+    }
+
+    /// Height of the output frame in luma samples, after cropping.
+    pub fn height(&self) -> u32 {
+        let (_, crop_unit_y) = self.crop_units();
+        let crop = self
+            .frame_crop_top_offset
+            .saturating_add(self.frame_crop_bottom_offset)
+            .saturating_mul(crop_unit_y);
+        self.coded_height().saturating_sub(crop)
+    }
+
+    /// The sample aspect ratio of the VUI, 1:1 when not given.
+    pub fn sample_aspect_ratio(&self) -> (u16, u16) {
+        self.vui_parameters
+            .as_ref()
+            .and_then(VuiParameters::sample_aspect_ratio)
+            .unwrap_or((1, 1))
+    }
+
+    /// The display aspect ratio of the cropped frame, reduced: 16:9 for 1920x1080 with square
+    /// samples.
+    pub fn display_aspect_ratio(&self) -> (u64, u64) {
+        let (sar_width, sar_height) = self.sample_aspect_ratio();
+        let width = self.width() as u64 * sar_width as u64;
+        let height = self.height() as u64 * sar_height as u64;
+        let (mut a, mut b) = (width, height);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        if a == 0 {
+            return (width, height);
+        }
+        (width / a, height / a)
+    }
+
+    /// level_idc with level 1b as 9: Baseline, Main and Extended signal it as level_idc 11 with
+    /// constraint_set3_flag.
+    pub fn effective_level_idc(&self) -> u8 {
+        match (self.profile_idc, self.level_idc) {
+            (66 | 77 | 88, 11) if self.constraint_set3_flag => 9,
+            (_, level_idc) => level_idc,
+        }
+    }
+
+    /// MaxDpbFrames, Min(MaxDpbMbs / (PicWidthInMbs * FrameHeightInMbs), 16) (A.3.1), None for
+    /// an unknown level_idc.
+    pub fn max_dpb_frames(&self) -> Option<u32> {
+        let level_idc = self.effective_level_idc();
+        let (_, max_dpb_mbs) = MAX_DPB_MBS.iter().find(|(idc, _)| *idc == level_idc)?;
+        let frame_size = self.pic_width_in_mbs() as u64 * self.frame_height_in_mbs() as u64;
+        Some((max_dpb_mbs / frame_size.max(1)).min(16) as u32)
+    }
+
+    /// max_num_reorder_frames and max_dec_frame_buffering as E.2.1 infers them without a
+    /// bitstream restriction: 0 for the intra profiles, MaxDpbFrames otherwise, and 16, the
+    /// largest MaxDpbFrames, for an unknown level_idc.
+    pub fn inferred_dpb_frames(&self) -> u32 {
+        let intra = matches!(self.profile_idc, 44 | 86 | 100 | 110 | 122 | 244)
+            && self.constraint_set3_flag;
+        if intra {
+            return 0;
+        }
+        self.max_dpb_frames().unwrap_or(16)
+    }
+
+    /// Frames per second from the VUI timing info, if present.
+    pub fn frame_rate(&self) -> Option<f64> {
+        self.vui_parameters.as_ref()?.frame_rate()
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+    use crate::emulation;
+
+    /// The SPS of the v/input streams: High profile, level 4.0, 1920x1080.
+    pub(crate) const HIGH_SPS: [u8; 27] = [
+        0x67, 0x64, 0x00, 0x28, 0xac, 0xd9, 0x40, 0x78, 0x02, 0x27, 0xe5, 0xc0, 0x44, 0x00, 0x00,
+        0x03, 0x00, 0x04, 0x00, 0x00, 0x03, 0x00, 0xf0, 0x3c, 0x60, 0xc6, 0x58,
+    ];
+
+    #[test]
+    fn test_parse_sps() {
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+        assert_eq!(sps.profile_idc, 100);
+        assert_eq!(sps.level_idc, 40);
+        assert_eq!(sps.seq_parameter_set_id, 0);
+        assert_eq!(sps.chroma_format_idc, 1);
+        assert_eq!(sps.chroma_array_type(), 1);
+        assert!(!sps.seq_scaling_matrix_present_flag);
+        assert_eq!(sps.pic_order_cnt_type, 0);
+        assert_eq!(sps.pic_width_in_mbs_minus1, 119);
+        assert_eq!(sps.pic_height_in_map_units_minus1, 67);
+        assert!(sps.frame_cropping_flag);
+        assert_eq!(sps.frame_crop_bottom_offset, 4);
+
+        assert_eq!(sps.pic_width_in_mbs(), 120);
+        assert_eq!(sps.frame_height_in_mbs(), 68);
+        assert_eq!((sps.coded_width(), sps.coded_height()), (1920, 1088));
+        assert_eq!((sps.width(), sps.height()), (1920, 1080));
+        assert_eq!(sps.max_frame_num(), 16);
+        assert_eq!(sps.max_pic_order_cnt_lsb(), 64);
+        assert_eq!(sps.bit_depth_luma(), 8);
+        assert!(sps.frame_mbs_only_flag);
+        assert!(sps.vui_parameters_present_flag);
+        assert_eq!(sps.sample_aspect_ratio(), (1, 1));
+        assert_eq!(sps.display_aspect_ratio(), (16, 9));
+        assert_eq!(sps.frame_rate(), Some(30.0));
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        assert!(!vui.fixed_frame_rate_flag);
+        assert_eq!(vui.colour_primaries_name(), "Unspecified");
+        assert_eq!(vui.max_dec_frame_buffering, 4);
+        assert_eq!(sps.max_dpb_frames(), Some(4));
+    }
+
+    /// A progressive 4:2:0 SPS of 11x9 macroblocks whose VUI has no bitstream restriction.
+    fn unrestricted_sps(profile_idc: u8, constraint_set3: bool, level_idc: u8) -> Sps {
+        let (width_mbs, height_mbs) = (11, 9);
+        let mut w = BitWriter::new();
+        w.u(8, profile_idc as u32).unwrap();
+        w.u(8, if constraint_set3 { 0x10 } else { 0 }).unwrap();
+        w.u(8, level_idc as u32).unwrap();
+        w.ue(0).unwrap(); // seq_parameter_set_id
+        if HIGH_PROFILES.contains(&profile_idc) {
+            w.ue(1).unwrap(); // chroma_format_idc
+            w.ue(0).unwrap();
+            w.ue(0).unwrap();
+            w.write_flag(false);
+            w.write_flag(false);
+        }
+        w.ue(0).unwrap(); // log2_max_frame_num_minus4
+        w.ue(2).unwrap(); // pic_order_cnt_type
+        w.ue(1).unwrap(); // max_num_ref_frames
+        w.write_flag(false);
+        w.ue(width_mbs - 1).unwrap();
+        w.ue(height_mbs - 1).unwrap();
+        w.write_flag(true); // frame_mbs_only_flag
+        w.write_flag(true);
+        w.write_flag(false);
+        w.write_flag(true); // vui_parameters_present_flag
+        for _ in 0..9 {
+            w.write_flag(false);
+        }
+        w.rbsp_trailing_bits();
+        let mut rbsp = vec![0x67];
+        rbsp.extend(w.rbsp());
+        Sps::from_rbsp(&rbsp).unwrap()
+    }
+
+    #[test]
+    fn test_inferred_dpb_frames() {
+        // level 3: MaxDpbMbs 8100 over 99 macroblocks, capped at 16
+        let sps = unrestricted_sps(77, false, 30);
+        assert_eq!(sps.max_dpb_frames(), Some(16));
+        let vui = sps.vui_parameters.as_ref().unwrap();
+        assert!(!vui.bitstream_restriction_flag);
+        assert_eq!(vui.max_num_reorder_frames, 16);
+        assert_eq!(vui.max_dec_frame_buffering, 16);
+
+        // level 1b: MaxDpbMbs 396 over 99 macroblocks
+        let sps = unrestricted_sps(66, true, 11);
+        assert_eq!(sps.effective_level_idc(), 9);
+        assert_eq!(sps.vui_parameters.unwrap().max_dec_frame_buffering, 4);
+        assert_eq!(unrestricted_sps(100, false, 11).effective_level_idc(), 11);
+
+        // High 10 Intra
+        let sps = unrestricted_sps(110, true, 30);
+        assert_eq!(sps.vui_parameters.unwrap().max_num_reorder_frames, 0);
+
+        let sps = unrestricted_sps(77, false, 33);
+        assert_eq!(sps.max_dpb_frames(), None);
+        assert_eq!(sps.vui_parameters.unwrap().max_dec_frame_buffering, 16);
+    }
+
+    #[test]
+    fn test_parse_interlaced_422() {
+        // High 4:2:2 10 bit, 720x480 interlaced with a scaling matrix and POC type 1
+        let mut w = BitWriter::new();
+        w.u(8, 0x67).unwrap();
+        w.u(8, 122).unwrap();
+        w.u(8, 0).unwrap();
+        w.u(8, 30).unwrap();
+        w.ue(1).unwrap(); // seq_parameter_set_id
+        w.ue(2).unwrap(); // chroma_format_idc
+        w.ue(2).unwrap(); // bit_depth_luma_minus8
+        w.ue(2).unwrap(); // bit_depth_chroma_minus8
+        w.write_flag(false);
+        w.write_flag(true); // seq_scaling_matrix_present_flag
+        for i in 0..7 {
+            w.write_flag(i == 6);
+        }
+        w.se(-8).unwrap(); // useDefaultScalingMatrixFlag for the 8x8 intra luma list
+        w.write_flag(false);
+        w.ue(8).unwrap(); // log2_max_frame_num_minus4
+        w.ue(1).unwrap(); // pic_order_cnt_type
+        w.write_flag(false);
+        w.se(-2).unwrap();
+        w.se(1).unwrap();
+        w.ue(2).unwrap(); // num_ref_frames_in_pic_order_cnt_cycle
+        w.se(2).unwrap();
+        w.se(-2).unwrap();
+        w.ue(4).unwrap(); // max_num_ref_frames
+        w.write_flag(false);
+        w.ue(44).unwrap(); // pic_width_in_mbs_minus1
+        w.ue(14).unwrap(); // pic_height_in_map_units_minus1
+        w.write_flag(false); // frame_mbs_only_flag
+        w.write_flag(true);
+        w.write_flag(true);
+        w.write_flag(true); // frame_cropping_flag
+        for offset in [1, 2, 3, 0] {
+            w.ue(offset).unwrap();
+        }
+        w.write_flag(false);
+        w.rbsp_trailing_bits();
+
+        let sps = Sps::from_rbsp(w.rbsp()).unwrap();
+        assert_eq!(sps.seq_parameter_set_id, 1);
+        assert_eq!(sps.chroma_array_type(), 2);
+        assert_eq!(sps.bit_depth_chroma(), 10);
+        assert_eq!(sps.seq_scaling_lists.len(), 8);
+        assert!(sps.seq_scaling_lists[6].as_ref().unwrap().use_default);
+        assert!(sps.seq_scaling_lists[7].is_none());
+        assert_eq!(sps.offset_for_ref_frame, [2, -2]);
+        assert_eq!(sps.max_frame_num(), 4096);
+        assert!(sps.mb_adaptive_frame_field_flag);
+        assert_eq!(sps.frame_height_in_mbs(), 30);
+        assert_eq!(sps.crop_units(), (2, 2));
+        assert_eq!((sps.width(), sps.height()), (720 - 6, 480 - 6));
+
+        let nal = sps.to_nal(3).unwrap();
+        assert_eq!(Sps::from_rbsp(&emulation::decode(&nal)).unwrap(), sps);
+    }
+
+    #[test]
+    fn test_write_sps() {
+        let sps = Sps::from_rbsp(&emulation::decode(&HIGH_SPS)).unwrap();
+        assert_eq!(sps.to_nal(3).unwrap(), HIGH_SPS);
+
+        // the repeated tail of the list is cut short by a nextScale of 0
+        let mut values = vec![6, 13, 20];
+        values.resize(16, 28);
+        let list = ScalingList {
+            values,
+            use_default: false,
+        };
+        let mut w = BitWriter::new();
+        write_scaling_list(&mut w, &list).unwrap();
+        let mut expected = BitWriter::new();
+        for delta in [-2, 7, 7, 8, -28] {
+            expected.se(delta).unwrap();
+        }
+        assert_eq!(w.rbsp(), expected.rbsp());
+        let mut s = SyntaxReader::new(w.rbsp());
+        assert_eq!(scaling_list(&mut s, "list", 16).unwrap(), list);
+    }
+
+    #[test]
+    fn test_scaling_list() {
+        // delta_scale 8 then 0 repeats 16; a first delta of -8 selects the default list
+        let mut s = SyntaxReader::new(&[0b0000_1000, 0b0111_1111, 0b1111_1111]);
+        let list = scaling_list(&mut s, "list", 16).unwrap();
+        assert!(list.values.iter().all(|&v| v == 16));
+        assert!(!list.use_default);
+        let mut s = SyntaxReader::new(&[0b0000_1000, 0b1000_0000]);
+        let list = scaling_list(&mut s, "list", 16).unwrap();
+        assert!(list.use_default);
+    }
+}
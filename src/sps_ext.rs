@@ -0,0 +1,698 @@
+//! Sequence parameter set extension RBSP, 7.3.2.1.2, and subset sequence parameter set RBSP,
+//! 7.3.2.1.3, with the SVC (G.7.3.2.1.4) and MVC (H.7.3.2.1.4) extensions.
+
+use std::fmt;
+
+use crate::sps::Sps;
+use crate::syntax::{ParseError, SyntaxReader};
+use crate::vui::HrdParameters;
+
+/// seq_parameter_set_extension_rbsp(), 7.3.2.1.2: the auxiliary (alpha) picture format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpsExtension {
+    pub seq_parameter_set_id: u32,
+    /// 0 without auxiliary pictures, 1 for alpha, 2 and 3 for other uses.
+    pub aux_format_idc: u32,
+    pub bit_depth_aux_minus8: u32,
+    pub alpha_incr_flag: bool,
+    pub alpha_opaque_value: u32,
+    pub alpha_transparent_value: u32,
+    pub additional_extension_flag: bool,
+}
+
+impl SpsExtension {
+    /// Parses seq_parameter_set_extension_rbsp() from the reader's position, i.e. after the NAL
+    /// header, up to the rbsp_trailing_bits.
+    pub fn parse(s: &mut SyntaxReader) -> Result<SpsExtension, ParseError> {
+        let seq_parameter_set_id = s.ue_max("seq_parameter_set_id", 31)?;
+        let aux_format_idc = s.ue_max("aux_format_idc", 3)?;
+        let mut bit_depth_aux_minus8 = 0;
+        let mut alpha_incr_flag = false;
+        let mut alpha_opaque_value = 0;
+        let mut alpha_transparent_value = 0;
+        if aux_format_idc != 0 {
+            bit_depth_aux_minus8 = s.ue_max("bit_depth_aux_minus8", 4)?;
+            alpha_incr_flag = s.flag("alpha_incr_flag")?;
+            let bits = bit_depth_aux_minus8 + 9;
+            alpha_opaque_value = s.u("alpha_opaque_value", bits)?;
+            alpha_transparent_value = s.u("alpha_transparent_value", bits)?;
+        }
+        let additional_extension_flag = s.flag("additional_extension_flag")?;
+        Ok(SpsExtension {
+            seq_parameter_set_id,
+            aux_format_idc,
+            bit_depth_aux_minus8,
+            alpha_incr_flag,
+            alpha_opaque_value,
+            alpha_transparent_value,
+            additional_extension_flag,
+        })
+    }
+
+    /// Parses seq_parameter_set_extension_rbsp() from the RBSP of an SPS extension NAL unit,
+    /// header included.
+    pub fn from_rbsp(rbsp: &[u8]) -> Result<SpsExtension, ParseError> {
+        let mut s = SyntaxReader::new(rbsp);
+        s.skip_to(8);
+        SpsExtension::parse(&mut s)
+    }
+}
+
+/// Timing and HRD parameters of one layer or operation point in the SVC and MVC VUI
+/// extensions, G.14.1 and H.14.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VuiExtTiming {
+    pub timing_info_present_flag: bool,
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate_flag: bool,
+    pub nal_hrd_parameters: Option<HrdParameters>,
+    pub vcl_hrd_parameters: Option<HrdParameters>,
+    pub low_delay_hrd_flag: bool,
+    pub pic_struct_present_flag: bool,
+}
+
+impl VuiExtTiming {
+    /// Reads the elements from timing_info_present_flag on, named `{prefix}_..._flag[{i}]`.
+    fn parse(s: &mut SyntaxReader, prefix: &str, i: u32) -> Result<VuiExtTiming, ParseError> {
+        let timing_info_present_flag =
+            s.flag(format_args!("{}_timing_info_present_flag[{}]", prefix, i))?;
+        let (mut num_units_in_tick, mut time_scale) = (0, 0);
+        let mut fixed_frame_rate_flag = false;
+        if timing_info_present_flag {
+            num_units_in_tick = s.u(format_args!("{}_num_units_in_tick[{}]", prefix, i), 32)?;
+            time_scale = s.u(format_args!("{}_time_scale[{}]", prefix, i), 32)?;
+            fixed_frame_rate_flag =
+                s.flag(format_args!("{}_fixed_frame_rate_flag[{}]", prefix, i))?;
+        }
+        let mut nal_hrd_parameters = None;
+        if s.flag(format_args!(
+            "{}_nal_hrd_parameters_present_flag[{}]",
+            prefix, i
+        ))? {
+            nal_hrd_parameters = Some(HrdParameters::parse(s)?);
+        }
+        let mut vcl_hrd_parameters = None;
+        if s.flag(format_args!(
+            "{}_vcl_hrd_parameters_present_flag[{}]",
+            prefix, i
+        ))? {
+            vcl_hrd_parameters = Some(HrdParameters::parse(s)?);
+        }
+        let mut low_delay_hrd_flag = false;
+        if nal_hrd_parameters.is_some() || vcl_hrd_parameters.is_some() {
+            low_delay_hrd_flag = s.flag(format_args!("{}_low_delay_hrd_flag[{}]", prefix, i))?;
+        }
+        let pic_struct_present_flag =
+            s.flag(format_args!("{}_pic_struct_present_flag[{}]", prefix, i))?;
+        Ok(VuiExtTiming {
+            timing_info_present_flag,
+            num_units_in_tick,
+            time_scale,
+            fixed_frame_rate_flag,
+            nal_hrd_parameters,
+            vcl_hrd_parameters,
+            low_delay_hrd_flag,
+            pic_struct_present_flag,
+        })
+    }
+}
+
+/// One entry of svc_vui_parameters_extension(), G.14.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvcVuiEntry {
+    pub dependency_id: u8,
+    pub quality_id: u8,
+    pub temporal_id: u8,
+    pub timing: VuiExtTiming,
+}
+
+/// seq_parameter_set_svc_extension(), G.7.3.2.1.4, and the SVC VUI extension following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SvcSpsExtension {
+    pub inter_layer_deblocking_filter_control_present_flag: bool,
+    pub extended_spatial_scalability_idc: u8,
+    pub chroma_phase_x_plus1_flag: bool,
+    pub chroma_phase_y_plus1: u8,
+    pub seq_ref_layer_chroma_phase_x_plus1_flag: bool,
+    pub seq_ref_layer_chroma_phase_y_plus1: u8,
+    pub seq_scaled_ref_layer_left_offset: i32,
+    pub seq_scaled_ref_layer_top_offset: i32,
+    pub seq_scaled_ref_layer_right_offset: i32,
+    pub seq_scaled_ref_layer_bottom_offset: i32,
+    pub seq_tcoeff_level_prediction_flag: bool,
+    pub adaptive_tcoeff_level_prediction_flag: bool,
+    pub slice_header_restriction_flag: bool,
+    /// svc_vui_parameters_extension(), when svc_vui_parameters_present_flag is set.
+    pub vui: Option<Vec<SvcVuiEntry>>,
+}
+
+impl SvcSpsExtension {
+    fn parse(s: &mut SyntaxReader, sps: &Sps) -> Result<SvcSpsExtension, ParseError> {
+        let chroma_array_type = sps.chroma_array_type();
+        let inter_layer_deblocking_filter_control_present_flag =
+            s.flag("inter_layer_deblocking_filter_control_present_flag")?;
+        let extended_spatial_scalability_idc = s.u("extended_spatial_scalability_idc", 2)? as u8;
+        // both inferred to be 1 when not present (G.7.4.2.1.4)
+        let mut chroma_phase_x_plus1_flag = true;
+        let mut chroma_phase_y_plus1 = 1;
+        if chroma_array_type == 1 || chroma_array_type == 2 {
+            chroma_phase_x_plus1_flag = s.flag("chroma_phase_x_plus1_flag")?;
+        }
+        if chroma_array_type == 1 {
+            chroma_phase_y_plus1 = s.u("chroma_phase_y_plus1", 2)? as u8;
+        }
+        let mut seq_ref_layer_chroma_phase_x_plus1_flag = chroma_phase_x_plus1_flag;
+        let mut seq_ref_layer_chroma_phase_y_plus1 = chroma_phase_y_plus1;
+        let mut offsets = [0; 4];
+        if extended_spatial_scalability_idc == 1 {
+            if chroma_array_type > 0 {
+                seq_ref_layer_chroma_phase_x_plus1_flag =
+                    s.flag("seq_ref_layer_chroma_phase_x_plus1_flag")?;
+                seq_ref_layer_chroma_phase_y_plus1 =
+                    s.u("seq_ref_layer_chroma_phase_y_plus1", 2)? as u8;
+            }
+            offsets[0] = s.se("seq_scaled_ref_layer_left_offset")?;
+            offsets[1] = s.se("seq_scaled_ref_layer_top_offset")?;
+            offsets[2] = s.se("seq_scaled_ref_layer_right_offset")?;
+            offsets[3] = s.se("seq_scaled_ref_layer_bottom_offset")?;
+        }
+        let seq_tcoeff_level_prediction_flag = s.flag("seq_tcoeff_level_prediction_flag")?;
+        let mut adaptive_tcoeff_level_prediction_flag = false;
+        if seq_tcoeff_level_prediction_flag {
+            adaptive_tcoeff_level_prediction_flag =
+                s.flag("adaptive_tcoeff_level_prediction_flag")?;
+        }
+        let slice_header_restriction_flag = s.flag("slice_header_restriction_flag")?;
+
+        let mut vui = None;
+        if s.flag("svc_vui_parameters_present_flag")? {
+            let num_entries_minus1 = s.ue_max("vui_ext_num_entries_minus1", 1023)?;
+            let mut entries = Vec::with_capacity(num_entries_minus1 as usize + 1);
+            for i in 0..=num_entries_minus1 {
+                entries.push(SvcVuiEntry {
+                    dependency_id: s.u(format_args!("vui_ext_dependency_id[{}]", i), 3)? as u8,
+                    quality_id: s.u(format_args!("vui_ext_quality_id[{}]", i), 4)? as u8,
+                    temporal_id: s.u(format_args!("vui_ext_temporal_id[{}]", i), 3)? as u8,
+                    timing: VuiExtTiming::parse(s, "vui_ext", i)?,
+                });
+            }
+            vui = Some(entries);
+        }
+
+        Ok(SvcSpsExtension {
+            inter_layer_deblocking_filter_control_present_flag,
+            extended_spatial_scalability_idc,
+            chroma_phase_x_plus1_flag,
+            chroma_phase_y_plus1,
+            seq_ref_layer_chroma_phase_x_plus1_flag,
+            seq_ref_layer_chroma_phase_y_plus1,
+            seq_scaled_ref_layer_left_offset: offsets[0],
+            seq_scaled_ref_layer_top_offset: offsets[1],
+            seq_scaled_ref_layer_right_offset: offsets[2],
+            seq_scaled_ref_layer_bottom_offset: offsets[3],
+            seq_tcoeff_level_prediction_flag,
+            adaptive_tcoeff_level_prediction_flag,
+            slice_header_restriction_flag,
+            vui,
+        })
+    }
+}
+
+/// The inter-view references of one view, in view_id values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ViewDependencies {
+    pub anchor_ref_l0: Vec<u32>,
+    pub anchor_ref_l1: Vec<u32>,
+    pub non_anchor_ref_l0: Vec<u32>,
+    pub non_anchor_ref_l1: Vec<u32>,
+}
+
+/// An operation point signalled for a level in the MVC extension.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApplicableOp {
+    pub temporal_id: u8,
+    pub target_view_ids: Vec<u32>,
+    pub num_views_minus1: u32,
+}
+
+/// A level_idc with the operation points it applies to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MvcLevel {
+    pub level_idc: u8,
+    pub applicable_ops: Vec<ApplicableOp>,
+}
+
+/// One operation point of mvc_vui_parameters_extension(), H.14.1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MvcVuiOp {
+    pub temporal_id: u8,
+    pub target_output_view_ids: Vec<u32>,
+    pub timing: VuiExtTiming,
+}
+
+/// The frame compatible format of MFC High profile streams (profile_idc 134).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MfcFormat {
+    pub mfc_format_idc: u8,
+    pub default_grid_position_flag: bool,
+    pub view0_grid_position_x: u8,
+    pub view0_grid_position_y: u8,
+    pub view1_grid_position_x: u8,
+    pub view1_grid_position_y: u8,
+    pub rpu_filter_enabled_flag: bool,
+    pub rpu_field_processing_flag: bool,
+}
+
+/// seq_parameter_set_mvc_extension(), H.7.3.2.1.4, and the MVC VUI extension following it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MvcSpsExtension {
+    /// view_id[i] in view order index order; num_views_minus1 + 1 entries.
+    pub view_ids: Vec<u32>,
+    /// The references of each view, by view order index. The base view has none.
+    pub dependencies: Vec<ViewDependencies>,
+    pub levels: Vec<MvcLevel>,
+    pub mfc_format: Option<MfcFormat>,
+    /// mvc_vui_parameters_extension(), when mvc_vui_parameters_present_flag is set.
+    pub vui: Option<Vec<MvcVuiOp>>,
+}
+
+/// Reads `{count_name}[i]`, at most 15, and that many `{name}[i][j]`.
+fn view_refs(
+    s: &mut SyntaxReader,
+    count_name: &'static str,
+    name: &str,
+    i: u32,
+) -> Result<Vec<u32>, ParseError> {
+    let count = s.ue(format_args!("{}[{}]", count_name, i))?;
+    if count > 15 {
+        return Err(ParseError::OutOfRange {
+            name: count_name,
+            value: count as i64,
+        });
+    }
+    (0..count)
+        .map(|j| s.ue(format_args!("{}[{}][{}]", name, i, j)))
+        .collect()
+}
+
+impl MvcSpsExtension {
+    fn parse(s: &mut SyntaxReader, sps: &Sps) -> Result<MvcSpsExtension, ParseError> {
+        let num_views_minus1 = s.ue_max("num_views_minus1", 1023)?;
+        let view_ids = (0..=num_views_minus1)
+            .map(|i| s.ue(format_args!("view_id[{}]", i)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut dependencies = vec![ViewDependencies::default(); view_ids.len()];
+        for i in 1..=num_views_minus1 {
+            let deps = &mut dependencies[i as usize];
+            deps.anchor_ref_l0 = view_refs(s, "num_anchor_refs_l0", "anchor_ref_l0", i)?;
+            deps.anchor_ref_l1 = view_refs(s, "num_anchor_refs_l1", "anchor_ref_l1", i)?;
+        }
+        for i in 1..=num_views_minus1 {
+            let deps = &mut dependencies[i as usize];
+            deps.non_anchor_ref_l0 =
+                view_refs(s, "num_non_anchor_refs_l0", "non_anchor_ref_l0", i)?;
+            deps.non_anchor_ref_l1 =
+                view_refs(s, "num_non_anchor_refs_l1", "non_anchor_ref_l1", i)?;
+        }
+
+        let num_level_values_signalled_minus1 =
+            s.ue_max("num_level_values_signalled_minus1", 63)?;
+        let mut levels = Vec::with_capacity(num_level_values_signalled_minus1 as usize + 1);
+        for i in 0..=num_level_values_signalled_minus1 {
+            let level_idc = s.u(format_args!("level_idc[{}]", i), 8)? as u8;
+            let num_applicable_ops_minus1 =
+                s.ue(format_args!("num_applicable_ops_minus1[{}]", i))?;
+            if num_applicable_ops_minus1 > 1023 {
+                return Err(ParseError::OutOfRange {
+                    name: "num_applicable_ops_minus1",
+                    value: num_applicable_ops_minus1 as i64,
+                });
+            }
+            let mut applicable_ops = Vec::with_capacity(num_applicable_ops_minus1 as usize + 1);
+            for j in 0..=num_applicable_ops_minus1 {
+                let temporal_id =
+                    s.u(format_args!("applicable_op_temporal_id[{}][{}]", i, j), 3)? as u8;
+                let num_target_views_minus1 = s.ue(format_args!(
+                    "applicable_op_num_target_views_minus1[{}][{}]",
+                    i, j
+                ))?;
+                if num_target_views_minus1 > num_views_minus1 {
+                    return Err(ParseError::OutOfRange {
+                        name: "applicable_op_num_target_views_minus1",
+                        value: num_target_views_minus1 as i64,
+                    });
+                }
+                let target_view_ids = (0..=num_target_views_minus1)
+                    .map(|k| {
+                        s.ue(format_args!(
+                            "applicable_op_target_view_id[{}][{}][{}]",
+                            i, j, k
+                        ))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                let num_views_minus1 =
+                    s.ue(format_args!("applicable_op_num_views_minus1[{}][{}]", i, j))?;
+                applicable_ops.push(ApplicableOp {
+                    temporal_id,
+                    target_view_ids,
+                    num_views_minus1,
+                });
+            }
+            levels.push(MvcLevel {
+                level_idc,
+                applicable_ops,
+            });
+        }
+
+        let mut mfc_format = None;
+        if sps.profile_idc == 134 {
+            let mfc_format_idc = s.u("mfc_format_idc", 6)? as u8;
+            let mut format = MfcFormat {
+                mfc_format_idc,
+                default_grid_position_flag: false,
+                view0_grid_position_x: 0,
+                view0_grid_position_y: 0,
+                view1_grid_position_x: 0,
+                view1_grid_position_y: 0,
+                rpu_filter_enabled_flag: false,
+                rpu_field_processing_flag: false,
+            };
+            if mfc_format_idc == 0 || mfc_format_idc == 1 {
+                format.default_grid_position_flag = s.flag("default_grid_position_flag")?;
+                if !format.default_grid_position_flag {
+                    format.view0_grid_position_x = s.u("view0_grid_position_x", 4)? as u8;
+                    format.view0_grid_position_y = s.u("view0_grid_position_y", 4)? as u8;
+                    format.view1_grid_position_x = s.u("view1_grid_position_x", 4)? as u8;
+                    format.view1_grid_position_y = s.u("view1_grid_position_y", 4)? as u8;
+                }
+            }
+            format.rpu_filter_enabled_flag = s.flag("rpu_filter_enabled_flag")?;
+            if !sps.frame_mbs_only_flag {
+                format.rpu_field_processing_flag = s.flag("rpu_field_processing_flag")?;
+            }
+            mfc_format = Some(format);
+        }
+
+        let mut vui = None;
+        if s.flag("mvc_vui_parameters_present_flag")? {
+            let num_ops_minus1 = s.ue_max("vui_mvc_num_ops_minus1", 1023)?;
+            let mut ops = Vec::with_capacity(num_ops_minus1 as usize + 1);
+            for i in 0..=num_ops_minus1 {
+                let temporal_id = s.u(format_args!("vui_mvc_temporal_id[{}]", i), 3)? as u8;
+                let num_target_output_views_minus1 = s.ue(format_args!(
+                    "vui_mvc_num_target_output_views_minus1[{}]",
+                    i
+                ))?;
+                if num_target_output_views_minus1 > num_views_minus1 {
+                    return Err(ParseError::OutOfRange {
+                        name: "vui_mvc_num_target_output_views_minus1",
+                        value: num_target_output_views_minus1 as i64,
+                    });
+                }
+                let target_output_view_ids = (0..=num_target_output_views_minus1)
+                    .map(|j| s.ue(format_args!("vui_mvc_view_id[{}][{}]", i, j)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                ops.push(MvcVuiOp {
+                    temporal_id,
+                    target_output_view_ids,
+                    timing: VuiExtTiming::parse(s, "vui_mvc", i)?,
+                });
+            }
+            vui = Some(ops);
+        }
+
+        Ok(MvcSpsExtension {
+            view_ids,
+            dependencies,
+            levels,
+            mfc_format,
+            vui,
+        })
+    }
+
+    /// The view_ids the view with the given view_id references, in anchor and non-anchor
+    /// pictures, over both lists.
+    pub fn references_of(&self, view_id: u32) -> Option<(Vec<u32>, Vec<u32>)> {
+        let index = self.view_ids.iter().position(|&id| id == view_id)?;
+        let deps = &self.dependencies[index];
+        let anchor = deps
+            .anchor_ref_l0
+            .iter()
+            .chain(&deps.anchor_ref_l1)
+            .copied()
+            .collect();
+        let non_anchor = deps
+            .non_anchor_ref_l0
+            .iter()
+            .chain(&deps.non_anchor_ref_l1)
+            .copied()
+            .collect();
+        Some((anchor, non_anchor))
+    }
+}
+
+/// The profile-specific part of a subset SPS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubsetSpsExtension {
+    /// Scalable Baseline and Scalable High profiles (83, 86).
+    Svc(SvcSpsExtension),
+    /// Multiview High, Stereo High and MFC High profiles (118, 128, 134).
+    Mvc(MvcSpsExtension),
+    /// The MVCD and 3D-AVC extensions (profiles 135, 138, 139) and unknown profiles are not
+    /// parsed; the fields after the SPS data are left unread.
+    Unparsed(u8),
+}
+
+impl fmt::Display for SubsetSpsExtension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SubsetSpsExtension::Svc(_) => f.write_str("SVC"),
+            SubsetSpsExtension::Mvc(_) => f.write_str("MVC"),
+            SubsetSpsExtension::Unparsed(profile_idc) => {
+                write!(f, "unparsed (profile_idc {})", profile_idc)
+            }
+        }
+    }
+}
+
+/// subset_seq_parameter_set_rbsp(), 7.3.2.1.3.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubsetSps {
+    pub sps: Sps,
+    pub extension: SubsetSpsExtension,
+    pub additional_extension2_flag: bool,
+}
+
+impl SubsetSps {
+    /// Parses subset_seq_parameter_set_rbsp() from the reader's position, i.e. after the NAL
+    /// header. The reader is left before the rbsp_trailing_bits, or after the SPS data when the
+    /// extension is [`SubsetSpsExtension::Unparsed`].
+    pub fn parse(s: &mut SyntaxReader) -> Result<SubsetSps, ParseError> {
+        let sps = Sps::parse(s)?;
+        let extension = match sps.profile_idc {
+            83 | 86 => SubsetSpsExtension::Svc(SvcSpsExtension::parse(s, &sps)?),
+            118 | 128 | 134 => {
+                s.f("bit_equal_to_one", 1)?;
+                SubsetSpsExtension::Mvc(MvcSpsExtension::parse(s, &sps)?)
+            }
+            profile_idc => {
+                return Ok(SubsetSps {
+                    sps,
+                    extension: SubsetSpsExtension::Unparsed(profile_idc),
+                    additional_extension2_flag: false,
+                })
+            }
+        };
+        let additional_extension2_flag = s.flag("additional_extension2_flag")?;
+        if additional_extension2_flag {
+            while s.more_rbsp_data() {
+                s.flag("additional_extension2_data_flag")?;
+            }
+        }
+        Ok(SubsetSps {
+            sps,
+            extension,
+            additional_extension2_flag,
+        })
+    }
+
+    /// Parses subset_seq_parameter_set_rbsp() from the RBSP of a subset SPS NAL unit, header
+    /// included.
+    pub fn from_rbsp(rbsp: &[u8]) -> Result<SubsetSps, ParseError> {
+        let mut s = SyntaxReader::new(rbsp);
+        s.skip_to(8);
+        SubsetSps::parse(&mut s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+
+    #[test]
+    fn test_parse_sps_extension() {
+        // alpha with 8 bit auxiliary pictures
+        let mut w = BitWriter::new();
+        w.u(8, 0x6d).unwrap();
+        w.ue(2).unwrap();
+        w.ue(1).unwrap();
+        w.ue(0).unwrap();
+        w.write_flag(true);
+        w.u(9, 255).unwrap();
+        w.u(9, 0).unwrap();
+        w.write_flag(false);
+        w.rbsp_trailing_bits();
+        let ext = SpsExtension::from_rbsp(w.rbsp()).unwrap();
+        assert_eq!(ext.seq_parameter_set_id, 2);
+        assert_eq!(ext.aux_format_idc, 1);
+        assert!(ext.alpha_incr_flag);
+        assert_eq!(ext.alpha_opaque_value, 255);
+        assert_eq!(ext.alpha_transparent_value, 0);
+
+        assert_eq!(
+            SpsExtension::from_rbsp(&[0x6d, 0xd0])
+                .unwrap()
+                .aux_format_idc,
+            0
+        );
+    }
+
+    /// seq_parameter_set_data() of a 1280x720 progressive stream with the given profile.
+    fn write_sps_data(w: &mut BitWriter, profile_idc: u32) {
+        w.u(8, 0x6f).unwrap();
+        w.u(8, profile_idc).unwrap();
+        w.u(8, 0).unwrap();
+        w.u(8, 40).unwrap();
+        w.ue(1).unwrap(); // seq_parameter_set_id
+        w.ue(1).unwrap(); // chroma_format_idc
+        w.ue(0).unwrap();
+        w.ue(0).unwrap();
+        w.write_flag(false);
+        w.write_flag(false);
+        w.ue(0).unwrap(); // log2_max_frame_num_minus4
+        w.ue(2).unwrap(); // pic_order_cnt_type
+        w.ue(2).unwrap(); // max_num_ref_frames
+        w.write_flag(false);
+        w.ue(79).unwrap();
+        w.ue(44).unwrap();
+        w.write_flag(true); // frame_mbs_only_flag
+        w.write_flag(true);
+        w.write_flag(false);
+        w.write_flag(false); // vui_parameters_present_flag
+    }
+
+    #[test]
+    fn test_parse_subset_sps_mvc() {
+        // Stereo High: view 0 is the base view, view 1 references it in all pictures
+        let mut w = BitWriter::new();
+        write_sps_data(&mut w, 128);
+        w.write_flag(true); // bit_equal_to_one
+        w.ue(1).unwrap(); // num_views_minus1
+        w.ue(0).unwrap();
+        w.ue(1).unwrap();
+        for _ in 0..2 {
+            // num_{non_,}anchor_refs_l0[1] = 1 with view 0, none in l1
+            w.ue(1).unwrap();
+            w.ue(0).unwrap();
+            w.ue(0).unwrap();
+        }
+        w.ue(0).unwrap(); // num_level_values_signalled_minus1
+        w.u(8, 41).unwrap();
+        w.ue(0).unwrap(); // num_applicable_ops_minus1
+        w.u(3, 0).unwrap();
+        w.ue(1).unwrap(); // applicable_op_num_target_views_minus1
+        w.ue(0).unwrap();
+        w.ue(1).unwrap();
+        w.ue(1).unwrap(); // applicable_op_num_views_minus1
+        w.write_flag(true); // mvc_vui_parameters_present_flag
+        w.ue(0).unwrap();
+        w.u(3, 0).unwrap();
+        w.ue(0).unwrap();
+        w.ue(1).unwrap(); // vui_mvc_view_id[0][0]
+        w.write_flag(true);
+        w.u(32, 1).unwrap();
+        w.u(32, 48).unwrap();
+        w.write_flag(true);
+        w.write_flag(false);
+        w.write_flag(false);
+        w.write_flag(false); // vui_mvc_pic_struct_present_flag
+        w.write_flag(false); // additional_extension2_flag
+        w.rbsp_trailing_bits();
+
+        let mut s = SyntaxReader::new(w.rbsp());
+        s.skip_to(8);
+        let subset = SubsetSps::parse(&mut s).unwrap();
+        assert!(!s.more_rbsp_data());
+        assert_eq!(subset.sps.seq_parameter_set_id, 1);
+        assert_eq!(subset.sps.width(), 1280);
+        let SubsetSpsExtension::Mvc(mvc) = &subset.extension else {
+            panic!("expected an MVC extension, got {}", subset.extension);
+        };
+        assert_eq!(mvc.view_ids, [0, 1]);
+        assert_eq!(mvc.dependencies[0], ViewDependencies::default());
+        assert_eq!(mvc.references_of(1), Some((vec![0], vec![0])));
+        assert_eq!(mvc.levels[0].level_idc, 41);
+        assert_eq!(mvc.levels[0].applicable_ops[0].target_view_ids, [0, 1]);
+        assert_eq!(mvc.mfc_format, None);
+        let vui = mvc.vui.as_ref().unwrap();
+        assert_eq!(vui[0].target_output_view_ids, [1]);
+        assert_eq!(vui[0].timing.time_scale, 48);
+        assert!(vui[0].timing.fixed_frame_rate_flag);
+    }
+
+    #[test]
+    fn test_parse_subset_sps_svc() {
+        let mut w = BitWriter::new();
+        write_sps_data(&mut w, 83);
+        w.write_flag(true);
+        w.u(2, 1).unwrap(); // extended_spatial_scalability_idc
+        w.write_flag(false); // chroma_phase_x_plus1_flag
+        w.u(2, 0).unwrap(); // chroma_phase_y_plus1
+        w.write_flag(true);
+        w.u(2, 2).unwrap();
+        for offset in [0, -8, 0, 8] {
+            w.se(offset).unwrap();
+        }
+        w.write_flag(true); // seq_tcoeff_level_prediction_flag
+        w.write_flag(false);
+        w.write_flag(true); // slice_header_restriction_flag
+        w.write_flag(true); // svc_vui_parameters_present_flag
+        w.ue(0).unwrap();
+        w.u(3, 1).unwrap();
+        w.u(4, 0).unwrap();
+        w.u(3, 2).unwrap();
+        for _ in 0..4 {
+            w.write_flag(false);
+        }
+        w.write_flag(true); // additional_extension2_flag
+        w.write_flag(true);
+        w.write_flag(false);
+        w.rbsp_trailing_bits();
+
+        let subset = SubsetSps::from_rbsp(w.rbsp()).unwrap();
+        let SubsetSpsExtension::Svc(svc) = &subset.extension else {
+            panic!("expected an SVC extension, got {}", subset.extension);
+        };
+        assert!(svc.inter_layer_deblocking_filter_control_present_flag);
+        assert_eq!(svc.extended_spatial_scalability_idc, 1);
+        assert!(!svc.chroma_phase_x_plus1_flag);
+        assert_eq!(svc.seq_ref_layer_chroma_phase_y_plus1, 2);
+        assert_eq!(svc.seq_scaled_ref_layer_top_offset, -8);
+        assert_eq!(svc.seq_scaled_ref_layer_bottom_offset, 8);
+        assert!(svc.seq_tcoeff_level_prediction_flag);
+        assert!(svc.slice_header_restriction_flag);
+        let vui = svc.vui.as_ref().unwrap();
+        assert_eq!((vui[0].dependency_id, vui[0].temporal_id), (1, 2));
+        assert!(!vui[0].timing.timing_info_present_flag);
+        assert!(subset.additional_extension2_flag);
+
+        // MVCD is not parsed
+        let mut w = BitWriter::new();
+        write_sps_data(&mut w, 138);
+        w.rbsp_trailing_bits();
+        let subset = SubsetSps::from_rbsp(w.rbsp()).unwrap();
+        assert_eq!(subset.extension, SubsetSpsExtension::Unparsed(138));
+    }
+}
@@ -0,0 +1,393 @@
+//! Syntax element reading shared by the RBSP parsers, with an optional trace of every element
+//! in the style of the JM reference decoder's trace_dec.txt.
+
+use std::fmt;
+
+use crate::bitreader::{BitReader, BitReaderError};
+use crate::emulation::{self, EpbMap};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    /// The RBSP ends early or holds an invalid Exp-Golomb code.
+    Bits(BitReaderError),
+    /// A syntax element has a value outside the range allowed by the semantics.
+    OutOfRange { name: &'static str, value: i64 },
+    /// The SPS referenced by a PPS is not known.
+    MissingSps(u32),
+    /// The PPS referenced by a slice header is not known.
+    MissingPps(u32),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::Bits(err) => err.fmt(f),
+            ParseError::OutOfRange { name, value } => {
+                write!(f, "{} = {} is out of range", name, value)
+            }
+            ParseError::MissingSps(id) => write!(f, "SPS {} is not available", id),
+            ParseError::MissingPps(id) => write!(f, "PPS {} is not available", id),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl From<BitReaderError> for ParseError {
+    fn from(err: BitReaderError) -> Self {
+        ParseError::Bits(err)
+    }
+}
+
+/// Descriptor of a syntax element, 7.2.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Descriptor {
+    U(u32),
+    F(u32),
+    B(u32),
+    Ue,
+    Se,
+    Te,
+}
+
+impl fmt::Display for Descriptor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Descriptor::U(n) => write!(f, "u({})", n),
+            Descriptor::F(n) => write!(f, "f({})", n),
+            Descriptor::B(n) => write!(f, "b({})", n),
+            Descriptor::Ue => f.write_str("ue(v)"),
+            Descriptor::Se => f.write_str("se(v)"),
+            Descriptor::Te => f.write_str("te(v)"),
+        }
+    }
+}
+
+/// One syntax element as read from a NAL unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxElement {
+    pub name: String,
+    /// Bit position of the first bit in the NAL unit, emulation prevention bytes included.
+    pub bit_offset: usize,
+    /// Number of bits of the code, emulation prevention bytes excluded.
+    pub bit_len: usize,
+    pub descriptor: Descriptor,
+    /// The bits as read, right-aligned; 0 for elements longer than 64 bits such as skipped
+    /// SEI payloads.
+    pub code: u64,
+    pub value: i64,
+}
+
+impl SyntaxElement {
+    /// The code as a string of `bit_len` binary digits, empty for elements longer than 64 bits.
+    pub fn code_bits(&self) -> String {
+        if self.bit_len > 64 {
+            return String::new();
+        }
+        (0..self.bit_len)
+            .rev()
+            .map(|i| if self.code >> i & 1 == 1 { '1' } else { '0' })
+            .collect()
+    }
+}
+
+impl fmt::Display for SyntaxElement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "@{:<7} {:<50} {:<6} {:>24}  ({:>3})",
+            self.bit_offset,
+            self.name,
+            self.descriptor.to_string(),
+            self.code_bits(),
+            self.value
+        )
+    }
+}
+
+/// Formats a trace as text, one element per line.
+pub fn trace_to_text(elements: &[SyntaxElement]) -> String {
+    elements
+        .iter()
+        .map(|element| format!("{}\n", element))
+        .collect()
+}
+
+/// Quotes a string for JSON.
+pub(crate) fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Formats a trace as a JSON array of objects with the fields of [`SyntaxElement`].
+pub fn trace_to_json(elements: &[SyntaxElement]) -> String {
+    let mut out = String::from("[");
+    for (i, element) in elements.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out.push_str("{\"name\":");
+        out.push_str(&json_string(&element.name));
+        out.push_str(&format!(
+            ",\"bit_offset\":{},\"bit_len\":{},\"descriptor\":\"{}\",\"code\":\"{}\",\"value\":{}}}",
+            element.bit_offset,
+            element.bit_len,
+            element.descriptor,
+            element.code_bits(),
+            element.value
+        ));
+    }
+    out.push(']');
+    out
+}
+
+/// Reads syntax elements from the RBSP of a NAL unit, recording them when tracing.
+///
+/// Names are only formatted when tracing, so indexed names can be passed as `format_args!`.
+#[derive(Debug)]
+pub struct SyntaxReader<'a> {
+    reader: BitReader<'a>,
+    trace: Option<Vec<SyntaxElement>>,
+    epb_map: Option<&'a EpbMap>,
+}
+
+impl<'a> SyntaxReader<'a> {
+    pub fn new(rbsp: &'a [u8]) -> Self {
+        SyntaxReader {
+            reader: BitReader::new(rbsp),
+            trace: None,
+            epb_map: None,
+        }
+    }
+
+    /// A reader that records every element; `epb_map` translates the positions back into
+    /// the NAL unit the RBSP was decoded from.
+    pub fn traced(rbsp: &'a [u8], epb_map: &'a EpbMap) -> Self {
+        SyntaxReader {
+            reader: BitReader::new(rbsp),
+            trace: Some(Vec::new()),
+            epb_map: Some(epb_map),
+        }
+    }
+
+    pub fn is_traced(&self) -> bool {
+        self.trace.is_some()
+    }
+
+    /// The elements recorded so far.
+    pub fn trace(&self) -> &[SyntaxElement] {
+        self.trace.as_deref().unwrap_or_default()
+    }
+
+    pub fn into_trace(self) -> Vec<SyntaxElement> {
+        self.trace.unwrap_or_default()
+    }
+
+    pub fn reader(&self) -> &BitReader<'a> {
+        &self.reader
+    }
+
+    /// Moves to a bit position without recording anything, e.g. past a NAL unit header.
+    pub fn skip_to(&mut self, pos: usize) {
+        self.reader.set_position(pos);
+    }
+
+    fn record(
+        &mut self,
+        name: impl fmt::Display,
+        start: usize,
+        descriptor: Descriptor,
+        value: i64,
+    ) {
+        let Some(trace) = self.trace.as_mut() else {
+            return;
+        };
+        let bit_len = self.reader.position() - start;
+        let mut code = 0;
+        if bit_len <= 64 {
+            let mut code_reader = self.reader.clone();
+            code_reader.set_position(start);
+            code = code_reader.read_bits(bit_len as u32).unwrap_or_default();
+        }
+        let bit_offset = match self.epb_map {
+            Some(map) => map.rbsp_bit_to_nal_bit(start),
+            None => start,
+        };
+        trace.push(SyntaxElement {
+            name: name.to_string(),
+            bit_offset,
+            bit_len,
+            descriptor,
+            code,
+            value,
+        });
+    }
+
+    /// u(n)
+    pub fn u(&mut self, name: impl fmt::Display, n: u32) -> Result<u32, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.u(n)?;
+        self.record(name, start, Descriptor::U(n), value as i64);
+        Ok(value)
+    }
+
+    /// u(1) as a flag.
+    pub fn flag(&mut self, name: impl fmt::Display) -> Result<bool, ParseError> {
+        Ok(self.u(name, 1)? == 1)
+    }
+
+    /// f(n)
+    pub fn f(&mut self, name: impl fmt::Display, n: u32) -> Result<u32, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.f(n)?;
+        self.record(name, start, Descriptor::F(n), value as i64);
+        Ok(value)
+    }
+
+    /// b(8)
+    pub fn b8(&mut self, name: impl fmt::Display) -> Result<u8, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.u(8)? as u8;
+        self.record(name, start, Descriptor::B(8), value as i64);
+        Ok(value)
+    }
+
+    /// ue(v)
+    pub fn ue(&mut self, name: impl fmt::Display) -> Result<u32, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.ue()?;
+        self.record(name, start, Descriptor::Ue, value as i64);
+        Ok(value)
+    }
+
+    /// ue(v) whose value must not exceed `max`.
+    pub fn ue_max(&mut self, name: &'static str, max: u32) -> Result<u32, ParseError> {
+        let value = self.ue(name)?;
+        if value > max {
+            return Err(ParseError::OutOfRange {
+                name,
+                value: value as i64,
+            });
+        }
+        Ok(value)
+    }
+
+    /// se(v)
+    pub fn se(&mut self, name: impl fmt::Display) -> Result<i32, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.se()?;
+        self.record(name, start, Descriptor::Se, value as i64);
+        Ok(value)
+    }
+
+    /// te(v) with the syntax element's range 0..=range.
+    pub fn te(&mut self, name: impl fmt::Display, range: u32) -> Result<u32, ParseError> {
+        let start = self.reader.position();
+        let value = self.reader.te(range)?;
+        self.record(name, start, Descriptor::Te, value as i64);
+        Ok(value)
+    }
+
+    /// Skips `n` bytes of payload, recorded as a single b(8 * n) element.
+    pub fn skip_bytes(&mut self, name: impl fmt::Display, n: usize) -> Result<(), ParseError> {
+        let start = self.reader.position();
+        self.reader.skip_bits(n * 8)?;
+        self.record(name, start, Descriptor::B(8 * n as u32), n as i64);
+        Ok(())
+    }
+
+    /// next_bits(n): the next `n` bits without consuming them, None at the end of the data.
+    pub fn next_bits(&self, n: u32) -> Option<u32> {
+        self.reader.clone().u(n).ok()
+    }
+
+    pub fn byte_aligned(&self) -> bool {
+        self.reader.byte_aligned()
+    }
+
+    pub fn more_rbsp_data(&self) -> bool {
+        self.reader.more_rbsp_data()
+    }
+
+    /// rbsp_trailing_bits(), 7.3.2.11
+    pub fn rbsp_trailing_bits(&mut self) -> Result<(), ParseError> {
+        self.f("rbsp_stop_one_bit", 1)?;
+        // the rbsp_alignment_zero_bits are recorded as one element
+        let padding = (8 - self.reader.position() % 8) % 8;
+        if padding > 0 {
+            self.f("rbsp_alignment_zero_bit", padding as u32)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads the syntax elements leading the RBSP of a NAL unit, such as the ids of parameter sets
+/// and slice headers, without unescaping the whole unit: `read` starts after the one-byte NAL
+/// unit header and sees the first 16 bytes only.
+pub fn read_leading<T>(
+    nal: &[u8],
+    read: impl FnOnce(&mut SyntaxReader) -> Result<T, ParseError>,
+) -> Result<T, ParseError> {
+    let rbsp = emulation::decode(&nal[..nal.len().min(16)]);
+    let mut s = SyntaxReader::new(&rbsp);
+    s.skip_to(8);
+    read(&mut s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::emulation;
+
+    #[test]
+    fn test_trace() {
+        // u(8) = 0, u(8) = 0, then across the emulation prevention byte ue(v) = 6 and se(v) = -1
+        let (rbsp, map) = emulation::decode_with_map(&[0, 0, 3, 0b0011_1011, 0x80]);
+        let mut s = SyntaxReader::traced(&rbsp, &map);
+        s.u("a", 8).unwrap();
+        s.u("b", 8).unwrap();
+        assert_eq!(s.ue("c"), Ok(6));
+        assert_eq!(s.se("d"), Ok(-1));
+        s.rbsp_trailing_bits().unwrap();
+        let trace = s.into_trace();
+        assert_eq!(trace.len(), 6);
+        assert_eq!(trace[2].name, "c");
+        assert_eq!(trace[2].bit_offset, 24);
+        assert_eq!(trace[2].bit_len, 5);
+        assert_eq!(trace[2].code_bits(), "00111");
+        assert_eq!(trace[3].bit_offset, 29);
+        assert_eq!(trace[3].descriptor, Descriptor::Se);
+        assert_eq!(trace[3].value, -1);
+        assert_eq!(trace[4].name, "rbsp_stop_one_bit");
+        assert_eq!(trace[4].bit_offset, 32);
+
+        let text = trace_to_text(&trace);
+        assert_eq!(text.lines().count(), 6);
+        assert!(text.lines().nth(2).unwrap().starts_with("@24      c "));
+        let json = trace_to_json(&trace[2..3]);
+        assert_eq!(
+            json,
+            r#"[{"name":"c","bit_offset":24,"bit_len":5,"descriptor":"ue(v)","code":"00111","value":6}]"#
+        );
+    }
+
+    #[test]
+    fn test_untraced() {
+        let mut s = SyntaxReader::new(&[0x80]);
+        assert_eq!(s.ue_max("x", 0), Ok(0));
+        assert!(s.trace().is_empty());
+        assert_eq!(
+            s.u("y", 8),
+            Err(ParseError::Bits(BitReaderError::Eof { pos: 1, needed: 8 }))
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Syntax element traces of Annex B streams, the view the JM reference decoder writes to
+//! trace_dec.txt: SPS, SPS extension, subset SPS, PPS, SEI, AUD and slice headers element by
+//! element.
+
+use crate::annexb::{self, NalUnit};
+use crate::emulation;
+use crate::nal::{NalHeader, NalUnitType};
+use crate::parameter_sets::ParameterSets;
+use crate::pps::Pps;
+use crate::sei;
+use crate::slice::SliceHeader;
+use crate::sps::Sps;
+use crate::sps_ext::{SpsExtension, SubsetSps, SubsetSpsExtension};
+use crate::syntax::{self, ParseError, SyntaxElement, SyntaxReader};
+
+/// The trace of one NAL unit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NalTrace {
+    pub nal: NalUnit,
+    pub elements: Vec<SyntaxElement>,
+    /// Why parsing stopped before the end of the traced syntax structure, if it did.
+    pub error: Option<ParseError>,
+}
+
+/// Traces NAL units in stream order, keeping the parameter sets the slice headers refer to.
+#[derive(Debug, Default)]
+pub struct Tracer {
+    parameter_sets: ParameterSets,
+}
+
+impl Tracer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn parse_payload(
+        &mut self,
+        s: &mut SyntaxReader,
+        header: &NalHeader,
+    ) -> Result<(), ParseError> {
+        match header.nal_unit_type {
+            NalUnitType::Sps => {
+                let sps = Sps::parse(s)?;
+                s.rbsp_trailing_bits()?;
+                self.parameter_sets.insert_sps(sps, 0)?;
+            }
+            NalUnitType::Pps => {
+                let sets = &self.parameter_sets;
+                let pps = Pps::parse(s, |id| sets.sps(id).ok_or(ParseError::MissingSps(id)))?;
+                s.rbsp_trailing_bits()?;
+                self.parameter_sets.insert_pps(pps, 0)?;
+            }
+            NalUnitType::SpsExtension => {
+                SpsExtension::parse(s)?;
+                s.rbsp_trailing_bits()?;
+            }
+            NalUnitType::SubsetSps => {
+                let subset = SubsetSps::parse(s)?;
+                if !matches!(subset.extension, SubsetSpsExtension::Unparsed(_)) {
+                    s.rbsp_trailing_bits()?;
+                }
+            }
+            NalUnitType::Sei => {
+                sei::parse_sei(s)?;
+            }
+            NalUnitType::Aud => {
+                s.u("primary_pic_type", 3)?;
+                s.rbsp_trailing_bits()?;
+            }
+            NalUnitType::SliceNonIdr | NalUnitType::SliceIdr => {
+                let sets = &self.parameter_sets;
+                SliceHeader::parse(s, header.nal_unit_type, header.nal_ref_idc, |id| {
+                    sets.resolve(id)
+                })?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Traces one NAL unit, given without its start code.
+    pub fn trace_nal(&mut self, nal: &[u8]) -> (Vec<SyntaxElement>, Option<ParseError>) {
+        let (rbsp, epb_map) = emulation::decode_with_map(nal);
+        let mut s = SyntaxReader::traced(&rbsp, &epb_map);
+        let result = (|| {
+            s.f("forbidden_zero_bit", 1)?;
+            s.u("nal_ref_idc", 2)?;
+            s.u("nal_unit_type", 5)?;
+            // the SVC/MVC header extension is skipped
+            let header = NalHeader::parse(nal).unwrap_or_else(|| NalHeader::from_byte(nal[0]));
+            s.skip_to(header.size() * 8);
+            self.parse_payload(&mut s, &header)
+        })();
+        (s.into_trace(), result.err())
+    }
+}
+
+/// Traces every NAL unit of an Annex B stream.
+pub fn trace_stream(data: &[u8]) -> Vec<NalTrace> {
+    let mut tracer = Tracer::new();
+    annexb::nal_units(data)
+        .map(|nal| {
+            let (elements, error) = tracer.trace_nal(&data[nal.start..nal.end]);
+            NalTrace {
+                nal,
+                elements,
+                error,
+            }
+        })
+        .collect()
+}
+
+impl NalTrace {
+    /// The trace as text: a line describing the NAL unit, then one line per element.
+    pub fn to_text(&self) -> String {
+        let mut out = format!(
+            "Annex B NALU @{}, len {}, nal_unit_type {} ({})\n",
+            self.nal.start,
+            self.nal.end - self.nal.start,
+            self.nal.header.nal_unit_type.value(),
+            self.nal.header.nal_unit_type.name()
+        );
+        out.push_str(&syntax::trace_to_text(&self.elements));
+        if let Some(error) = &self.error {
+            out.push_str(&format!("error: {}\n", error));
+        }
+        out
+    }
+
+    /// The trace as a JSON object with the NAL unit position, type, elements and error.
+    pub fn to_json(&self) -> String {
+        let error = match &self.error {
+            Some(error) => syntax::json_string(&error.to_string()),
+            None => "null".to_string(),
+        };
+        format!(
+            "{{\"offset\":{},\"len\":{},\"nal_unit_type\":{},\"name\":\"{}\",\"elements\":{},\"error\":{}}}",
+            self.nal.start,
+            self.nal.end - self.nal.start,
+            self.nal.header.nal_unit_type.value(),
+            self.nal.header.nal_unit_type.name(),
+            syntax::trace_to_json(&self.elements),
+            error
+        )
+    }
+}
+
+/// The traces of a stream as text, NAL units separated by blank lines.
+pub fn stream_trace_to_text(traces: &[NalTrace]) -> String {
+    traces
+        .iter()
+        .map(NalTrace::to_text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// The traces of a stream as a JSON array.
+pub fn stream_trace_to_json(traces: &[NalTrace]) -> String {
+    format!(
+        "[{}]",
+        traces
+            .iter()
+            .map(NalTrace::to_json)
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pps::tests::CABAC_PPS;
+    use crate::sps::tests::HIGH_SPS;
+
+    #[test]
+    fn test_trace_stream() {
+        let mut data = vec![0, 0, 0, 1, 0x09, 0x10];
+        // an SPS, a PPS, a recovery_point SEI, then an IDR slice
+        for nal in [
+            &HIGH_SPS[..],
+            &CABAC_PPS,
+            &[0x06, 0x06, 0x02, 0x88, 0x40, 0x80],
+            &[0x65, 0x88, 0x84],
+        ] {
+            data.extend([0, 0, 1]);
+            data.extend(nal);
+        }
+        let traces = trace_stream(&data);
+        assert_eq!(traces.len(), 5);
+
+        let aud = &traces[0];
+        assert_eq!(aud.error, None);
+        let names: Vec<_> = aud.elements.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "forbidden_zero_bit",
+                "nal_ref_idc",
+                "nal_unit_type",
+                "primary_pic_type",
+                "rbsp_stop_one_bit",
+                "rbsp_alignment_zero_bit"
+            ]
+        );
+
+        let sps = &traces[1];
+        assert_eq!(sps.error, None);
+        let level = sps.elements.iter().find(|e| e.name == "level_idc").unwrap();
+        assert_eq!((level.bit_offset, level.value), (24, 40));
+        let width = sps
+            .elements
+            .iter()
+            .find(|e| e.name == "pic_width_in_mbs_minus1")
+            .unwrap();
+        assert_eq!(width.value, 119);
+        let fixed_frame_rate = sps
+            .elements
+            .iter()
+            .find(|e| e.name == "fixed_frame_rate_flag")
+            .unwrap();
+        assert_eq!(fixed_frame_rate.value, 0);
+        assert_eq!(sps.elements.last().unwrap().name, "rbsp_alignment_zero_bit");
+
+        let pps = &traces[2];
+        assert_eq!(pps.error, None);
+        let names: Vec<_> = pps.elements.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names[names.len() - 5..],
+            [
+                "transform_8x8_mode_flag",
+                "pic_scaling_matrix_present_flag",
+                "second_chroma_qp_index_offset",
+                "rbsp_stop_one_bit",
+                "rbsp_alignment_zero_bit"
+            ]
+        );
+
+        let sei = &traces[3];
+        assert_eq!(sei.error, None);
+        let names: Vec<_> = sei.elements.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "forbidden_zero_bit",
+                "nal_ref_idc",
+                "nal_unit_type",
+                "last_payload_type_byte",
+                "last_payload_size_byte",
+                "recovery_point",
+                "rbsp_stop_one_bit",
+                "rbsp_alignment_zero_bit"
+            ]
+        );
+        assert_eq!(
+            (sei.elements[5].bit_offset, sei.elements[5].bit_len),
+            (24, 16)
+        );
+
+        // the IDR slice header refers to the PPS and SPS traced before it
+        let slice = &traces[4];
+        assert_eq!(slice.elements[3].name, "first_mb_in_slice");
+        assert_eq!(slice.elements[4].value, 7);
+        assert!(matches!(slice.error, Some(ParseError::Bits(_))));
+
+        let text = stream_trace_to_text(&traces);
+        assert!(text.starts_with("Annex B NALU @4, len 2, nal_unit_type 9 (AUD)\n@0 "));
+        let json = stream_trace_to_json(&traces);
+        assert!(json.starts_with(r#"[{"offset":4,"len":2,"nal_unit_type":9,"name":"AUD","elements":[{"name":"forbidden_zero_bit""#));
+    }
+
+    #[test]
+    fn test_trace_sps_extension() {
+        let mut tracer = Tracer::new();
+        let (elements, error) = tracer.trace_nal(&[0x6d, 0xd0]);
+        assert_eq!(error, None);
+        let names: Vec<_> = elements[3..].iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "seq_parameter_set_id",
+                "aux_format_idc",
+                "additional_extension_flag",
+                "rbsp_stop_one_bit",
+                "rbsp_alignment_zero_bit"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_parameter_sets() {
+        let mut tracer = Tracer::new();
+        let (elements, error) = tracer.trace_nal(&[0x65, 0x88, 0x84]);
+        assert_eq!(elements.len(), 6);
+        assert_eq!(error, Some(ParseError::MissingPps(0)));
+    }
+}
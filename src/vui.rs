@@ -0,0 +1,596 @@
+//! VUI parameters, E.1.1, and HRD parameters, E.1.2.
+
+use crate::bitwriter::{BitWriter, BitWriterError};
+use crate::syntax::{ParseError, SyntaxReader};
+
+/// Extended_SAR: sar_width and sar_height are transmitted.
+pub const EXTENDED_SAR: u8 = 255;
+
+/// Sample aspect ratio of an aspect_ratio_idc, Table E-1. None for Unspecified, Extended_SAR
+/// and the reserved values.
+pub fn sample_aspect_ratio(aspect_ratio_idc: u8) -> Option<(u16, u16)> {
+    Some(match aspect_ratio_idc {
+        1 => (1, 1),
+        2 => (12, 11),
+        3 => (10, 11),
+        4 => (16, 11),
+        5 => (40, 33),
+        6 => (24, 11),
+        7 => (20, 11),
+        8 => (32, 11),
+        9 => (80, 33),
+        10 => (18, 11),
+        11 => (15, 11),
+        12 => (64, 33),
+        13 => (160, 99),
+        14 => (4, 3),
+        15 => (3, 2),
+        16 => (2, 1),
+        _ => return None,
+    })
+}
+
+/// Name of a video_format, Table E-2.
+pub fn video_format_name(video_format: u8) -> &'static str {
+    match video_format {
+        0 => "Component",
+        1 => "PAL",
+        2 => "NTSC",
+        3 => "SECAM",
+        4 => "MAC",
+        5 => "Unspecified",
+        _ => "Reserved",
+    }
+}
+
+/// Name of a colour_primaries value, Table E-3.
+pub fn colour_primaries_name(colour_primaries: u8) -> &'static str {
+    match colour_primaries {
+        1 => "BT.709",
+        2 => "Unspecified",
+        4 => "BT.470M",
+        5 => "BT.470BG",
+        6 => "SMPTE 170M",
+        7 => "SMPTE 240M",
+        8 => "Generic film",
+        9 => "BT.2020",
+        10 => "SMPTE ST 428-1",
+        11 => "SMPTE RP 431-2",
+        12 => "SMPTE EG 432-1",
+        22 => "EBU Tech 3213-E",
+        _ => "Reserved",
+    }
+}
+
+/// Name of a transfer_characteristics value, Table E-4.
+pub fn transfer_characteristics_name(transfer_characteristics: u8) -> &'static str {
+    match transfer_characteristics {
+        1 => "BT.709",
+        2 => "Unspecified",
+        4 => "BT.470M",
+        5 => "BT.470BG",
+        6 => "SMPTE 170M",
+        7 => "SMPTE 240M",
+        8 => "Linear",
+        9 => "Log 100:1",
+        10 => "Log 316.22777:1",
+        11 => "IEC 61966-2-4",
+        12 => "BT.1361",
+        13 => "IEC 61966-2-1",
+        14 => "BT.2020 10 bit",
+        15 => "BT.2020 12 bit",
+        16 => "SMPTE ST 2084",
+        17 => "SMPTE ST 428-1",
+        18 => "ARIB STD-B67",
+        _ => "Reserved",
+    }
+}
+
+/// Name of a matrix_coefficients value, Table E-5.
+pub fn matrix_coefficients_name(matrix_coefficients: u8) -> &'static str {
+    match matrix_coefficients {
+        0 => "Identity",
+        1 => "BT.709",
+        2 => "Unspecified",
+        4 => "FCC",
+        5 => "BT.470BG",
+        6 => "SMPTE 170M",
+        7 => "SMPTE 240M",
+        8 => "YCgCo",
+        9 => "BT.2020 non-constant luminance",
+        10 => "BT.2020 constant luminance",
+        11 => "SMPTE ST 2085",
+        12 => "Chromaticity-derived non-constant luminance",
+        13 => "Chromaticity-derived constant luminance",
+        14 => "ICtCp",
+        _ => "Reserved",
+    }
+}
+
+/// One CPB specification of the HRD parameters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpbSpec {
+    pub bit_rate_value_minus1: u32,
+    pub cpb_size_value_minus1: u32,
+    pub cbr_flag: bool,
+}
+
+/// hrd_parameters(), E.1.2.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HrdParameters {
+    pub bit_rate_scale: u8,
+    pub cpb_size_scale: u8,
+    /// cpb_cnt_minus1 + 1 entries.
+    pub cpbs: Vec<CpbSpec>,
+    pub initial_cpb_removal_delay_length_minus1: u8,
+    pub cpb_removal_delay_length_minus1: u8,
+    pub dpb_output_delay_length_minus1: u8,
+    pub time_offset_length: u8,
+}
+
+impl HrdParameters {
+    /// Parses hrd_parameters() from the reader's position.
+    pub fn parse(s: &mut SyntaxReader) -> Result<HrdParameters, ParseError> {
+        let cpb_cnt_minus1 = s.ue_max("cpb_cnt_minus1", 31)?;
+        let bit_rate_scale = s.u("bit_rate_scale", 4)? as u8;
+        let cpb_size_scale = s.u("cpb_size_scale", 4)? as u8;
+        let mut cpbs = Vec::with_capacity(cpb_cnt_minus1 as usize + 1);
+        for sched_sel_idx in 0..=cpb_cnt_minus1 {
+            cpbs.push(CpbSpec {
+                bit_rate_value_minus1: s
+                    .ue(format_args!("bit_rate_value_minus1[{}]", sched_sel_idx))?,
+                cpb_size_value_minus1: s
+                    .ue(format_args!("cpb_size_value_minus1[{}]", sched_sel_idx))?,
+                cbr_flag: s.flag(format_args!("cbr_flag[{}]", sched_sel_idx))?,
+            });
+        }
+        Ok(HrdParameters {
+            bit_rate_scale,
+            cpb_size_scale,
+            cpbs,
+            initial_cpb_removal_delay_length_minus1: s
+                .u("initial_cpb_removal_delay_length_minus1", 5)?
+                as u8,
+            cpb_removal_delay_length_minus1: s.u("cpb_removal_delay_length_minus1", 5)? as u8,
+            dpb_output_delay_length_minus1: s.u("dpb_output_delay_length_minus1", 5)? as u8,
+            time_offset_length: s.u("time_offset_length", 5)? as u8,
+        })
+    }
+
+    /// Writes hrd_parameters(), the inverse of `parse`.
+    pub fn write(&self, w: &mut BitWriter) -> Result<(), BitWriterError> {
+        w.ue(self.cpbs.len().saturating_sub(1) as u32)?;
+        w.u(4, self.bit_rate_scale as u32)?;
+        w.u(4, self.cpb_size_scale as u32)?;
+        for cpb in &self.cpbs {
+            w.ue(cpb.bit_rate_value_minus1)?;
+            w.ue(cpb.cpb_size_value_minus1)?;
+            w.write_flag(cpb.cbr_flag);
+        }
+        w.u(5, self.initial_cpb_removal_delay_length_minus1 as u32)?;
+        w.u(5, self.cpb_removal_delay_length_minus1 as u32)?;
+        w.u(5, self.dpb_output_delay_length_minus1 as u32)?;
+        w.u(5, self.time_offset_length as u32)
+    }
+
+    /// BitRate[SchedSelIdx] in bits per second, E-37.
+    pub fn bit_rate(&self, sched_sel_idx: usize) -> Option<u64> {
+        let cpb = self.cpbs.get(sched_sel_idx)?;
+        Some((cpb.bit_rate_value_minus1 as u64 + 1) << (6 + self.bit_rate_scale))
+    }
+
+    /// CpbSize[SchedSelIdx] in bits, E-38.
+    pub fn cpb_size(&self, sched_sel_idx: usize) -> Option<u64> {
+        let cpb = self.cpbs.get(sched_sel_idx)?;
+        Some((cpb.cpb_size_value_minus1 as u64 + 1) << (4 + self.cpb_size_scale))
+    }
+}
+
+/// vui_parameters(), E.1.1. Elements that are not present hold their inferred values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VuiParameters {
+    pub aspect_ratio_info_present_flag: bool,
+    pub aspect_ratio_idc: u8,
+    /// Only transmitted for Extended_SAR.
+    pub sar_width: u16,
+    pub sar_height: u16,
+    pub overscan_info_present_flag: bool,
+    pub overscan_appropriate_flag: bool,
+    pub video_signal_type_present_flag: bool,
+    /// Inferred to be 5 (Unspecified).
+    pub video_format: u8,
+    pub video_full_range_flag: bool,
+    pub colour_description_present_flag: bool,
+    /// Inferred to be 2 (Unspecified), as are the two following.
+    pub colour_primaries: u8,
+    pub transfer_characteristics: u8,
+    pub matrix_coefficients: u8,
+    pub chroma_loc_info_present_flag: bool,
+    pub chroma_sample_loc_type_top_field: u32,
+    pub chroma_sample_loc_type_bottom_field: u32,
+    pub timing_info_present_flag: bool,
+    pub num_units_in_tick: u32,
+    pub time_scale: u32,
+    pub fixed_frame_rate_flag: bool,
+    /// Present when nal_hrd_parameters_present_flag is set.
+    pub nal_hrd_parameters: Option<HrdParameters>,
+    /// Present when vcl_hrd_parameters_present_flag is set.
+    pub vcl_hrd_parameters: Option<HrdParameters>,
+    /// Only transmitted with HRD parameters; inferred to be the inverse of fixed_frame_rate_flag.
+    pub low_delay_hrd_flag: bool,
+    pub pic_struct_present_flag: bool,
+    pub bitstream_restriction_flag: bool,
+    /// The following are inferred to be 1, 2, 1, 15 and 15 without a bitstream restriction.
+    pub motion_vectors_over_pic_boundaries_flag: bool,
+    pub max_bytes_per_pic_denom: u32,
+    pub max_bits_per_mb_denom: u32,
+    pub log2_max_mv_length_horizontal: u32,
+    pub log2_max_mv_length_vertical: u32,
+    /// Transmitted with the bitstream restriction only. Otherwise 0 here, and inferred by
+    /// `Sps::parse` from the profile and level, see `Sps::inferred_dpb_frames`.
+    pub max_num_reorder_frames: u32,
+    pub max_dec_frame_buffering: u32,
+}
+
+/// No element present: every element holds its inferred value.
+impl Default for VuiParameters {
+    fn default() -> Self {
+        VuiParameters {
+            aspect_ratio_info_present_flag: false,
+            aspect_ratio_idc: 0,
+            sar_width: 0,
+            sar_height: 0,
+            overscan_info_present_flag: false,
+            overscan_appropriate_flag: false,
+            video_signal_type_present_flag: false,
+            video_format: 5,
+            video_full_range_flag: false,
+            colour_description_present_flag: false,
+            colour_primaries: 2,
+            transfer_characteristics: 2,
+            matrix_coefficients: 2,
+            chroma_loc_info_present_flag: false,
+            chroma_sample_loc_type_top_field: 0,
+            chroma_sample_loc_type_bottom_field: 0,
+            timing_info_present_flag: false,
+            num_units_in_tick: 0,
+            time_scale: 0,
+            fixed_frame_rate_flag: false,
+            nal_hrd_parameters: None,
+            vcl_hrd_parameters: None,
+            low_delay_hrd_flag: true,
+            pic_struct_present_flag: false,
+            bitstream_restriction_flag: false,
+            motion_vectors_over_pic_boundaries_flag: true,
+            max_bytes_per_pic_denom: 2,
+            max_bits_per_mb_denom: 1,
+            log2_max_mv_length_horizontal: 15,
+            log2_max_mv_length_vertical: 15,
+            max_num_reorder_frames: 0,
+            max_dec_frame_buffering: 0,
+        }
+    }
+}
+
+impl VuiParameters {
+    /// Parses vui_parameters() from the reader's position.
+    pub fn parse(s: &mut SyntaxReader) -> Result<VuiParameters, ParseError> {
+        let aspect_ratio_info_present_flag = s.flag("aspect_ratio_info_present_flag")?;
+        let (mut aspect_ratio_idc, mut sar_width, mut sar_height) = (0, 0, 0);
+        if aspect_ratio_info_present_flag {
+            aspect_ratio_idc = s.u("aspect_ratio_idc", 8)? as u8;
+            if aspect_ratio_idc == EXTENDED_SAR {
+                sar_width = s.u("sar_width", 16)? as u16;
+                sar_height = s.u("sar_height", 16)? as u16;
+            }
+        }
+
+        let overscan_info_present_flag = s.flag("overscan_info_present_flag")?;
+        let mut overscan_appropriate_flag = false;
+        if overscan_info_present_flag {
+            overscan_appropriate_flag = s.flag("overscan_appropriate_flag")?;
+        }
+
+        let video_signal_type_present_flag = s.flag("video_signal_type_present_flag")?;
+        let mut video_format = 5;
+        let mut video_full_range_flag = false;
+        let mut colour_description_present_flag = false;
+        let (mut colour_primaries, mut transfer_characteristics, mut matrix_coefficients) =
+            (2, 2, 2);
+        if video_signal_type_present_flag {
+            video_format = s.u("video_format", 3)? as u8;
+            video_full_range_flag = s.flag("video_full_range_flag")?;
+            colour_description_present_flag = s.flag("colour_description_present_flag")?;
+            if colour_description_present_flag {
+                colour_primaries = s.u("colour_primaries", 8)? as u8;
+                transfer_characteristics = s.u("transfer_characteristics", 8)? as u8;
+                matrix_coefficients = s.u("matrix_coefficients", 8)? as u8;
+            }
+        }
+
+        let chroma_loc_info_present_flag = s.flag("chroma_loc_info_present_flag")?;
+        let (mut top_field, mut bottom_field) = (0, 0);
+        if chroma_loc_info_present_flag {
+            top_field = s.ue_max("chroma_sample_loc_type_top_field", 5)?;
+            bottom_field = s.ue_max("chroma_sample_loc_type_bottom_field", 5)?;
+        }
+
+        let timing_info_present_flag = s.flag("timing_info_present_flag")?;
+        let (mut num_units_in_tick, mut time_scale) = (0, 0);
+        let mut fixed_frame_rate_flag = false;
+        if timing_info_present_flag {
+            num_units_in_tick = s.u("num_units_in_tick", 32)?;
+            time_scale = s.u("time_scale", 32)?;
+            fixed_frame_rate_flag = s.flag("fixed_frame_rate_flag")?;
+        }
+
+        let mut nal_hrd_parameters = None;
+        if s.flag("nal_hrd_parameters_present_flag")? {
+            nal_hrd_parameters = Some(HrdParameters::parse(s)?);
+        }
+        let mut vcl_hrd_parameters = None;
+        if s.flag("vcl_hrd_parameters_present_flag")? {
+            vcl_hrd_parameters = Some(HrdParameters::parse(s)?);
+        }
+        let mut low_delay_hrd_flag = !fixed_frame_rate_flag;
+        if nal_hrd_parameters.is_some() || vcl_hrd_parameters.is_some() {
+            low_delay_hrd_flag = s.flag("low_delay_hrd_flag")?;
+        }
+        let pic_struct_present_flag = s.flag("pic_struct_present_flag")?;
+
+        let bitstream_restriction_flag = s.flag("bitstream_restriction_flag")?;
+        let mut motion_vectors_over_pic_boundaries_flag = true;
+        let mut max_bytes_per_pic_denom = 2;
+        let mut max_bits_per_mb_denom = 1;
+        let mut log2_max_mv_length_horizontal = 15;
+        let mut log2_max_mv_length_vertical = 15;
+        let mut max_num_reorder_frames = 0;
+        let mut max_dec_frame_buffering = 0;
+        if bitstream_restriction_flag {
+            motion_vectors_over_pic_boundaries_flag =
+                s.flag("motion_vectors_over_pic_boundaries_flag")?;
+            max_bytes_per_pic_denom = s.ue_max("max_bytes_per_pic_denom", 16)?;
+            max_bits_per_mb_denom = s.ue_max("max_bits_per_mb_denom", 16)?;
+            log2_max_mv_length_horizontal = s.ue_max("log2_max_mv_length_horizontal", 16)?;
+            log2_max_mv_length_vertical = s.ue_max("log2_max_mv_length_vertical", 16)?;
+            max_num_reorder_frames = s.ue("max_num_reorder_frames")?;
+            max_dec_frame_buffering = s.ue("max_dec_frame_buffering")?;
+        }
+
+        Ok(VuiParameters {
+            aspect_ratio_info_present_flag,
+            aspect_ratio_idc,
+            sar_width,
+            sar_height,
+            overscan_info_present_flag,
+            overscan_appropriate_flag,
+            video_signal_type_present_flag,
+            video_format,
+            video_full_range_flag,
+            colour_description_present_flag,
+            colour_primaries,
+            transfer_characteristics,
+            matrix_coefficients,
+            chroma_loc_info_present_flag,
+            chroma_sample_loc_type_top_field: top_field,
+            chroma_sample_loc_type_bottom_field: bottom_field,
+            timing_info_present_flag,
+            num_units_in_tick,
+            time_scale,
+            fixed_frame_rate_flag,
+            nal_hrd_parameters,
+            vcl_hrd_parameters,
+            low_delay_hrd_flag,
+            pic_struct_present_flag,
+            bitstream_restriction_flag,
+            motion_vectors_over_pic_boundaries_flag,
+            max_bytes_per_pic_denom,
+            max_bits_per_mb_denom,
+            log2_max_mv_length_horizontal,
+            log2_max_mv_length_vertical,
+            max_num_reorder_frames,
+            max_dec_frame_buffering,
+        })
+    }
+
+    /// Writes vui_parameters(), the inverse of `parse`. Elements are only written under their
+    /// present flags.
+    pub fn write(&self, w: &mut BitWriter) -> Result<(), BitWriterError> {
+        w.write_flag(self.aspect_ratio_info_present_flag);
+        if self.aspect_ratio_info_present_flag {
+            w.u(8, self.aspect_ratio_idc as u32)?;
+            if self.aspect_ratio_idc == EXTENDED_SAR {
+                w.u(16, self.sar_width as u32)?;
+                w.u(16, self.sar_height as u32)?;
+            }
+        }
+
+        w.write_flag(self.overscan_info_present_flag);
+        if self.overscan_info_present_flag {
+            w.write_flag(self.overscan_appropriate_flag);
+        }
+
+        w.write_flag(self.video_signal_type_present_flag);
+        if self.video_signal_type_present_flag {
+            w.u(3, self.video_format as u32)?;
+            w.write_flag(self.video_full_range_flag);
+            w.write_flag(self.colour_description_present_flag);
+            if self.colour_description_present_flag {
+                w.u(8, self.colour_primaries as u32)?;
+                w.u(8, self.transfer_characteristics as u32)?;
+                w.u(8, self.matrix_coefficients as u32)?;
+            }
+        }
+
+        w.write_flag(self.chroma_loc_info_present_flag);
+        if self.chroma_loc_info_present_flag {
+            w.ue(self.chroma_sample_loc_type_top_field)?;
+            w.ue(self.chroma_sample_loc_type_bottom_field)?;
+        }
+
+        w.write_flag(self.timing_info_present_flag);
+        if self.timing_info_present_flag {
+            w.u(32, self.num_units_in_tick)?;
+            w.u(32, self.time_scale)?;
+            w.write_flag(self.fixed_frame_rate_flag);
+        }
+
+        for hrd in [&self.nal_hrd_parameters, &self.vcl_hrd_parameters] {
+            w.write_flag(hrd.is_some());
+            if let Some(hrd) = hrd {
+                hrd.write(w)?;
+            }
+        }
+        if self.nal_hrd_parameters.is_some() || self.vcl_hrd_parameters.is_some() {
+            w.write_flag(self.low_delay_hrd_flag);
+        }
+        w.write_flag(self.pic_struct_present_flag);
+
+        w.write_flag(self.bitstream_restriction_flag);
+        if self.bitstream_restriction_flag {
+            w.write_flag(self.motion_vectors_over_pic_boundaries_flag);
+            w.ue(self.max_bytes_per_pic_denom)?;
+            w.ue(self.max_bits_per_mb_denom)?;
+            w.ue(self.log2_max_mv_length_horizontal)?;
+            w.ue(self.log2_max_mv_length_vertical)?;
+            w.ue(self.max_num_reorder_frames)?;
+            w.ue(self.max_dec_frame_buffering)?;
+        }
+        Ok(())
+    }
+
+    /// The sample aspect ratio, from Table E-1 or sar_width:sar_height. None when unspecified.
+    pub fn sample_aspect_ratio(&self) -> Option<(u16, u16)> {
+        if !self.aspect_ratio_info_present_flag {
+            return None;
+        }
+        if self.aspect_ratio_idc == EXTENDED_SAR {
+            if self.sar_width == 0 || self.sar_height == 0 {
+                return None;
+            }
+            return Some((self.sar_width, self.sar_height));
+        }
+        sample_aspect_ratio(self.aspect_ratio_idc)
+    }
+
+    /// Frames per second, time_scale / (2 * num_units_in_tick): a frame lasts two ticks, one
+    /// per field. None without timing info.
+    pub fn frame_rate(&self) -> Option<f64> {
+        if !self.timing_info_present_flag || self.num_units_in_tick == 0 || self.time_scale == 0 {
+            return None;
+        }
+        Some(self.time_scale as f64 / (2.0 * self.num_units_in_tick as f64))
+    }
+
+    pub fn video_format_name(&self) -> &'static str {
+        video_format_name(self.video_format)
+    }
+
+    pub fn colour_primaries_name(&self) -> &'static str {
+        colour_primaries_name(self.colour_primaries)
+    }
+
+    pub fn transfer_characteristics_name(&self) -> &'static str {
+        transfer_characteristics_name(self.transfer_characteristics)
+    }
+
+    pub fn matrix_coefficients_name(&self) -> &'static str {
+        matrix_coefficients_name(self.matrix_coefficients)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bitwriter::BitWriter;
+
+    #[test]
+    fn test_parse_vui() {
+        // Extended SAR, BT.2020 PQ full range, 29.97 fps with NAL HRD, bitstream restriction
+        let mut w = BitWriter::new();
+        w.write_flag(true);
+        w.u(8, EXTENDED_SAR as u32).unwrap();
+        w.u(16, 4).unwrap();
+        w.u(16, 3).unwrap();
+        w.write_flag(false); // overscan_info_present_flag
+        w.write_flag(true); // video_signal_type_present_flag
+        w.u(3, 5).unwrap();
+        w.write_flag(true);
+        w.write_flag(true);
+        for value in [9, 16, 9] {
+            w.u(8, value).unwrap();
+        }
+        w.write_flag(false); // chroma_loc_info_present_flag
+        w.write_flag(true); // timing_info_present_flag
+        w.u(32, 1001).unwrap();
+        w.u(32, 60000).unwrap();
+        w.write_flag(true);
+        w.write_flag(true); // nal_hrd_parameters_present_flag
+        w.ue(1).unwrap();
+        w.u(4, 2).unwrap();
+        w.u(4, 3).unwrap();
+        for (bit_rate, cpb_size, cbr) in [(1249, 3124, false), (2499, 6249, true)] {
+            w.ue(bit_rate).unwrap();
+            w.ue(cpb_size).unwrap();
+            w.write_flag(cbr);
+        }
+        for length in [23, 23, 23, 24] {
+            w.u(5, length).unwrap();
+        }
+        w.write_flag(false); // vcl_hrd_parameters_present_flag
+        w.write_flag(false); // low_delay_hrd_flag
+        w.write_flag(true);
+        w.write_flag(true); // bitstream_restriction_flag
+        w.write_flag(true);
+        for value in [0, 0, 11, 11, 2, 4] {
+            w.ue(value).unwrap();
+        }
+        w.rbsp_trailing_bits();
+
+        let mut s = SyntaxReader::new(w.rbsp());
+        let vui = VuiParameters::parse(&mut s).unwrap();
+        assert_eq!(vui.sample_aspect_ratio(), Some((4, 3)));
+        assert!(!vui.overscan_info_present_flag);
+        assert_eq!(vui.video_format_name(), "Unspecified");
+        assert!(vui.video_full_range_flag);
+        assert_eq!(vui.colour_primaries_name(), "BT.2020");
+        assert_eq!(vui.transfer_characteristics_name(), "SMPTE ST 2084");
+        assert_eq!(
+            vui.matrix_coefficients_name(),
+            "BT.2020 non-constant luminance"
+        );
+        assert_eq!(vui.frame_rate(), Some(60000.0 / 2002.0));
+        assert!(vui.fixed_frame_rate_flag);
+
+        let hrd = vui.nal_hrd_parameters.as_ref().unwrap();
+        assert_eq!(hrd.cpbs.len(), 2);
+        assert_eq!(hrd.bit_rate(0), Some(1250 << 8));
+        assert_eq!(hrd.cpb_size(1), Some(6250 << 7));
+        assert!(hrd.cpbs[1].cbr_flag);
+        assert_eq!(hrd.time_offset_length, 24);
+        assert_eq!(vui.vcl_hrd_parameters, None);
+        assert!(!vui.low_delay_hrd_flag);
+        assert!(vui.pic_struct_present_flag);
+        assert_eq!(vui.max_bytes_per_pic_denom, 0);
+        assert_eq!(vui.log2_max_mv_length_vertical, 11);
+        assert_eq!(vui.max_num_reorder_frames, 2);
+        assert_eq!(vui.max_dec_frame_buffering, 4);
+        assert!(!s.more_rbsp_data());
+    }
+
+    #[test]
+    fn test_inferred_values() {
+        let mut w = BitWriter::new();
+        for _ in 0..9 {
+            w.write_flag(false);
+        }
+        w.rbsp_trailing_bits();
+        let vui = VuiParameters::parse(&mut SyntaxReader::new(w.rbsp())).unwrap();
+        assert_eq!(vui.sample_aspect_ratio(), None);
+        assert_eq!(vui.frame_rate(), None);
+        assert_eq!((vui.video_format, vui.colour_primaries), (5, 2));
+        assert!(vui.low_delay_hrd_flag);
+        assert!(vui.motion_vectors_over_pic_boundaries_flag);
+        assert_eq!(vui.log2_max_mv_length_horizontal, 15);
+    }
+}
@@ -24,6 +24,13 @@ class NaluTypesTest(unittest.TestCase):
                 print(hex(start))
                 nalu_slice = nalutypes.VCLSlice(raw_data, sps, pps, True, include_header=True)
 
+    def test_get_description(self):
+        self.assertEqual(nalutypes.get_description(nalutypes.NAL_UNIT_TYPE_SPS), "Sequence parameter set")
+        self.assertEqual(
+            nalutypes.get_description(nalutypes.NAL_UNIT_TYPE_CODED_SLICE_AUX),
+            "Coded slice of an auxiliary coded picture without partitioning",
+        )
+
 
 if __name__ == "__main__":
     unittest.main()
\ No newline at end of file
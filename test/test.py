@@ -8,6 +8,8 @@ import sys
 
 sys.path.insert(0, os.path.join(os.path.dirname(__file__), ".."))
 
+from bitstring import BitStream
+
 from h26x_extractor import h26x_parser
 
 
@@ -20,10 +22,32 @@ class ParsingTest(unittest.TestCase):
         # make sure decode is happy
         ex.parse()
 
+    def testSliceWithoutParameterSets(self):
+        """A slice whose PPS was not received is reported."""
+        slices = []
+        ex = h26x_parser.H26xParser(None, use_bitstream="000000016588840021")
+        ex.set_callback("slice", slices.append)
+        with self.assertWarns(UserWarning):
+            ex.parse()
+        self.assertEqual(len(slices), 1)
+
     def testfileParser(self):
         ex = h26x_parser.H26xParser('../v/input/small_bunny_1080p_30fps_h264_keyframe_each_one_second.h264', verbose=False)
         ex.parse()
 
+    def testCallbackBitStream(self):
+        """Callbacks get a bitstring.BitStream, whichever reader the parser uses."""
+        streams = []
+        ex = h26x_parser.H26xParser('../v/input/small_bunny_1080p_30fps_h264_keyframe_each_one_second.h264', verbose=False)
+        for name in ["sps", "pps", "slice"]:
+            ex.set_callback(name, streams.append)
+        ex.parse()
+        self.assertTrue(streams)
+        for s in streams:
+            self.assertIsInstance(s, BitStream)
+            # positioned after the syntax elements the parser read
+            self.assertGreater(s.pos, 0)
+
 
 if __name__ == "__main__":
     unittest.main()
@@ -32,6 +32,12 @@ class ParsingTest(unittest.TestCase):
             decode = utils.nalu_decode(encode)
             self.assertEqual(data, decode)
 
+    def test_nalu_encode_trailing_zero(self):
+        data = b"\x65\x88\x12\x00"
+        self.assertEqual(utils.nalu_encode(data), data)
+        with self.assertRaises(utils.NaluValidationError):
+            utils.nalu_encode(data, strict=True)
+
     def test_is_same_file(self):
         self.assertTrue(utils.is_same_file(__file__, __file__))
         self.assertTrue(not utils.is_same_file(__file__, unittest.__file__))